@@ -0,0 +1 @@
+ALTER TABLE todos ADD COLUMN priority SMALLINT NOT NULL DEFAULT 1;
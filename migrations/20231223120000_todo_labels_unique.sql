@@ -0,0 +1,4 @@
+DELETE FROM todo_labels a
+    USING todo_labels b
+    WHERE a.id > b.id AND a.todo_id = b.todo_id AND a.label_id = b.label_id;
+ALTER TABLE todo_labels ADD CONSTRAINT todo_labels_todo_id_label_id_key UNIQUE (todo_id, label_id);
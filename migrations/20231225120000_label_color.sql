@@ -0,0 +1 @@
+ALTER TABLE labels ADD COLUMN color TEXT NOT NULL DEFAULT '#cccccc';
@@ -0,0 +1,5 @@
+CREATE TABLE idempotency_keys (
+    key TEXT PRIMARY KEY,
+    todo_id INTEGER NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
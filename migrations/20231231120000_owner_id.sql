@@ -0,0 +1,2 @@
+ALTER TABLE todos ADD COLUMN owner_id TEXT;
+CREATE INDEX todos_owner_id_idx ON todos (owner_id);
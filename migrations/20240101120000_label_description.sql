@@ -0,0 +1 @@
+ALTER TABLE labels ADD COLUMN description TEXT;
@@ -0,0 +1 @@
+ALTER TABLE labels ADD COLUMN archived BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,17 @@
+-- checked right away, CREATE INDEX refuses to run with deferred checks pending
+SET CONSTRAINTS ALL IMMEDIATE;
+-- keep the oldest of the labels whose names differ only in case, moving their todos over
+INSERT INTO todo_labels (todo_id, label_id)
+    SELECT todo_labels.todo_id, kept.id
+    FROM todo_labels
+    JOIN labels ON labels.id = todo_labels.label_id
+    JOIN (SELECT lower(name) AS name, min(id) AS id FROM labels GROUP BY lower(name)) kept
+        ON kept.name = lower(labels.name) AND kept.id <> labels.id
+    ON CONFLICT (todo_id, label_id) DO NOTHING;
+DELETE FROM todo_labels
+    USING labels
+    WHERE labels.id = todo_labels.label_id
+        AND EXISTS (SELECT 1 FROM labels older WHERE lower(older.name) = lower(labels.name) AND older.id < labels.id);
+DELETE FROM labels
+    WHERE EXISTS (SELECT 1 FROM labels older WHERE lower(older.name) = lower(labels.name) AND older.id < labels.id);
+CREATE UNIQUE INDEX labels_lower_name_key ON labels (lower(name));
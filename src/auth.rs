@@ -0,0 +1,223 @@
+use axum::{
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderValue, Request, StatusCode,
+    },
+    middleware::Next,
+    response::Response,
+};
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use thiserror::Error;
+use tracing::Instrument;
+
+use crate::handlers::error_response;
+
+/// Shared secret the HS256 signature of bearer tokens is checked against.
+#[derive(Clone, PartialEq, Eq)]
+pub struct JwtSecret(String);
+
+impl JwtSecret {
+    pub fn new(secret: String) -> Self {
+        Self(secret)
+    }
+
+    fn decoding_key(&self) -> DecodingKey {
+        DecodingKey::from_secret(self.0.as_bytes())
+    }
+}
+
+impl fmt::Debug for JwtSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JwtSecret(***)")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Claims {
+    pub sub: String,
+    /// Expiry as seconds since the epoch. Defaulted only so that a token without it is
+    /// reported as missing the claim by validation instead of failing to deserialize.
+    #[serde(default)]
+    pub exp: i64,
+}
+
+/// Subject of the verified bearer token, available as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthSubject(pub String);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AuthError {
+    #[error("missing bearer token")]
+    Missing,
+    #[error("malformed token")]
+    Malformed,
+    #[error("unsupported algorithm")]
+    Algorithm,
+    #[error("invalid signature")]
+    Signature,
+    #[error("token expired")]
+    Expired,
+    #[error("token not valid yet")]
+    NotYetValid,
+    #[error("missing claim [{0}]")]
+    MissingClaim(String),
+}
+
+impl From<jsonwebtoken::errors::Error> for AuthError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        match err.into_kind() {
+            ErrorKind::InvalidAlgorithm => AuthError::Algorithm,
+            ErrorKind::InvalidSignature => AuthError::Signature,
+            ErrorKind::ExpiredSignature => AuthError::Expired,
+            ErrorKind::ImmatureSignature => AuthError::NotYetValid,
+            ErrorKind::MissingRequiredClaim(claim) => AuthError::MissingClaim(claim),
+            _ => AuthError::Malformed,
+        }
+    }
+}
+
+/// Verifies a compact HS256 JWT and returns its claims. `exp` is required, `nbf` is checked
+/// when present, both without leeway.
+pub fn verify_token(secret: &JwtSecret, token: &str) -> Result<Claims, AuthError> {
+    // only accept the algorithm we sign with, in particular never `none`
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.validate_nbf = true;
+    validation.leeway = 0;
+    let data = jsonwebtoken::decode::<Claims>(token, &secret.decoding_key(), &validation)?;
+    Ok(data.claims)
+}
+
+/// Rejects requests without a valid `Authorization: Bearer` token with 401,
+/// otherwise stores the token subject as `AuthSubject`.
+pub async fn authenticate<B>(secret: JwtSecret, mut req: Request<B>, next: Next<B>) -> Response {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthError::Missing);
+    let claims = match token.and_then(|token| verify_token(&secret, token.trim())) {
+        Ok(claims) => claims,
+        Err(err) => {
+            tracing::debug!("rejected request: {}", err);
+            let mut res =
+                error_response(StatusCode::UNAUTHORIZED, json!({ "error": "unauthorized" }));
+            res.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            return res;
+        }
+    };
+
+    let subject = AuthSubject(claims.sub);
+    let span = tracing::info_span!("auth", subject = %subject.0);
+    req.extensions_mut().insert(subject);
+    next.run(req).instrument(span).await
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+    use chrono::Utc;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    /// Signs `claims` the way an identity provider sharing `secret` would.
+    pub fn sign_token(secret: &JwtSecret, claims: &impl Serialize) -> String {
+        let key = EncodingKey::from_secret(secret.0.as_bytes());
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &key).unwrap()
+    }
+
+    pub fn claims(sub: &str) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            exp: Utc::now().timestamp() + 60,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::test_utils::*;
+    use super::*;
+    use chrono::Utc;
+
+    fn secret() -> JwtSecret {
+        JwtSecret::new("test-secret".to_string())
+    }
+
+    #[test]
+    fn verify_signed_token() {
+        let token = sign_token(&secret(), &claims("alice"));
+        assert_eq!(verify_token(&secret(), &token).unwrap().sub, "alice");
+    }
+
+    #[test]
+    fn reject_invalid_tokens() {
+        let token = sign_token(&secret(), &claims("alice"));
+        let other_secret = JwtSecret::new("other-secret".to_string());
+        assert_eq!(
+            verify_token(&other_secret, &token),
+            Err(AuthError::Signature)
+        );
+
+        // swapping the payload invalidates the signature
+        let forged = sign_token(&other_secret, &claims("mallory"));
+        let mut parts: Vec<&str> = token.split('.').collect();
+        parts[1] = forged.split('.').nth(1).unwrap();
+        assert_eq!(
+            verify_token(&secret(), &parts.join(".")),
+            Err(AuthError::Signature)
+        );
+
+        let expired = Claims {
+            exp: Utc::now().timestamp() - 1,
+            ..claims("alice")
+        };
+        assert_eq!(
+            verify_token(&secret(), &sign_token(&secret(), &expired)),
+            Err(AuthError::Expired)
+        );
+
+        // `none` is not even a known algorithm, the header is `{"alg":"none"}`
+        let unsigned = format!("eyJhbGciOiJub25lIn0.{}.", token.split('.').nth(1).unwrap());
+        assert!(verify_token(&secret(), &unsigned).is_err());
+        let hs512 = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::HS512),
+            &claims("alice"),
+            &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+        )
+        .unwrap();
+        assert_eq!(verify_token(&secret(), &hs512), Err(AuthError::Algorithm));
+
+        assert_eq!(
+            verify_token(&secret(), "not-a-token"),
+            Err(AuthError::Malformed)
+        );
+    }
+
+    #[test]
+    fn validate_time_claims() {
+        let now = Utc::now().timestamp();
+        let without_exp = json!({ "sub": "alice" });
+        assert_eq!(
+            verify_token(&secret(), &sign_token(&secret(), &without_exp)),
+            Err(AuthError::MissingClaim("exp".to_string()))
+        );
+
+        let not_yet_valid = json!({ "sub": "alice", "exp": now + 120, "nbf": now + 60 });
+        assert_eq!(
+            verify_token(&secret(), &sign_token(&secret(), &not_yet_valid)),
+            Err(AuthError::NotYetValid)
+        );
+
+        let valid = json!({ "sub": "alice", "exp": now + 120, "nbf": now - 60, "iat": now - 60 });
+        assert_eq!(
+            verify_token(&secret(), &sign_token(&secret(), &valid))
+                .unwrap()
+                .sub,
+            "alice"
+        );
+    }
+}
@@ -0,0 +1,537 @@
+use crate::auth::JwtSecret;
+use crate::repositories::{RetryPolicy, DEFAULT_MAX_RETRIES};
+use anyhow::Context;
+use hyper::header::HeaderValue;
+use sqlx::postgres::PgPoolOptions;
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use thiserror::Error;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+const DEFAULT_CORS_ORIGIN: &str = "http://localhost:3001";
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_DB_MAX_CONNECTIONS: u32 = 5;
+const DEFAULT_DB_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_BODY_LIMIT_BYTES: usize = 64 * 1024;
+const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_COMPRESSION_MIN_BYTES: u16 = 1024;
+const DEFAULT_SLOW_QUERY_MS: u64 = 200;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+
+/// Every problem found while reading the configuration, reported at once.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid configuration: {}", .0.join("; "))]
+pub struct ConfigError(Vec<String>);
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub database_url: String,
+    pub bind_addr: SocketAddr,
+    pub log_level: String,
+    pub pool: PoolConfig,
+    pub http: HttpConfig,
+    /// Apply pending migrations at startup, disabled with `RUN_MIGRATIONS=false`.
+    pub run_migrations: bool,
+    /// Seed example data into empty tables at startup, enabled with `SEED_ON_START=true`.
+    pub seed_on_start: bool,
+    /// How long requests in flight may still run after a shutdown signal.
+    pub shutdown_timeout: Duration,
+}
+
+/// Settings applied to every request by `create_app`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpConfig {
+    pub cors_origins: CorsOrigins,
+    /// Requests with a larger body are rejected with 413.
+    pub body_limit: usize,
+    /// How long a processed `Idempotency-Key` is remembered.
+    pub idempotency_ttl: Duration,
+    /// Requests still running after this long are answered with 408.
+    pub request_timeout: Duration,
+    /// Responses of at least this many bytes are compressed when the client accepts gzip or
+    /// brotli, `None` when disabled with `COMPRESSION_ENABLED=false`.
+    pub compression_min_size: Option<u16>,
+    /// Set when `AUTH_ENABLED=true`, the API then requires a bearer token signed with it.
+    pub jwt_secret: Option<JwtSecret>,
+    /// Enables `DELETE /todos/all` when `ALLOW_DESTRUCTIVE=true`, meant for test environments.
+    pub allow_destructive: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            cors_origins: CorsOrigins::default(),
+            body_limit: DEFAULT_BODY_LIMIT_BYTES,
+            idempotency_ttl: Duration::from_secs(DEFAULT_IDEMPOTENCY_TTL_SECS),
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            compression_min_size: Some(DEFAULT_COMPRESSION_MIN_BYTES),
+            jwt_secret: None,
+            allow_destructive: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub acquire_timeout: Duration,
+    /// Todo queries running longer than this are logged as warnings.
+    pub slow_query: Duration,
+    /// Applied to queries failing with a connection error, see `repositories::retry`.
+    pub retry: RetryPolicy,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_DB_MAX_CONNECTIONS,
+            acquire_timeout: Duration::from_secs(DEFAULT_DB_ACQUIRE_TIMEOUT_SECS),
+            slow_query: Duration::from_millis(DEFAULT_SLOW_QUERY_MS),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            // sqlx 0.5 names the pool acquire timeout `connect_timeout`
+            .connect_timeout(self.acquire_timeout)
+    }
+}
+
+impl AppConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|key| env::var(key).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut errors = vec![];
+
+        let database_url = lookup("DATABASE_URL");
+        if database_url.is_none() {
+            errors.push("DATABASE_URL is not set".to_string());
+        }
+        let bind_addr = bind_addr(lookup("APP_HOST"), lookup("APP_PORT"))
+            .map_err(|e| errors.push(format!("{:#}", e)))
+            .ok();
+        let cors_origins = lookup("CORS_ALLOWED_ORIGINS")
+            .map(|value| CorsOrigins::parse(&value))
+            .transpose()
+            .map_err(|e| errors.push(format!("CORS_ALLOWED_ORIGINS: {:#}", e)))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let log_level = lookup("RUST_LOG").unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string());
+        let max_connections = parse_var(&lookup, "DB_MAX_CONNECTIONS", DEFAULT_DB_MAX_CONNECTIONS)
+            .map_err(|e| errors.push(e))
+            .unwrap_or_default();
+        let acquire_timeout_secs = parse_var(
+            &lookup,
+            "DB_ACQUIRE_TIMEOUT_SECS",
+            DEFAULT_DB_ACQUIRE_TIMEOUT_SECS,
+        )
+        .map_err(|e| errors.push(e))
+        .unwrap_or_default();
+        let slow_query_ms = parse_var(&lookup, "SLOW_QUERY_MS", DEFAULT_SLOW_QUERY_MS)
+            .map_err(|e| errors.push(e))
+            .unwrap_or_default();
+        let max_retries = parse_var(&lookup, "DB_MAX_RETRIES", DEFAULT_MAX_RETRIES)
+            .map_err(|e| errors.push(e))
+            .unwrap_or_default();
+        let body_limit = parse_var(&lookup, "BODY_LIMIT_BYTES", DEFAULT_BODY_LIMIT_BYTES)
+            .map_err(|e| errors.push(e))
+            .unwrap_or_default();
+        let idempotency_ttl_secs = parse_var(
+            &lookup,
+            "IDEMPOTENCY_TTL_SECS",
+            DEFAULT_IDEMPOTENCY_TTL_SECS,
+        )
+        .map_err(|e| errors.push(e))
+        .unwrap_or_default();
+        let request_timeout_secs = parse_var(
+            &lookup,
+            "REQUEST_TIMEOUT_SECS",
+            DEFAULT_REQUEST_TIMEOUT_SECS,
+        )
+        .map_err(|e| errors.push(e))
+        .unwrap_or_default();
+        let compression_min_size = match lookup("COMPRESSION_ENABLED").as_deref() {
+            Some("false") => None,
+            _ => parse_var(
+                &lookup,
+                "COMPRESSION_MIN_BYTES",
+                DEFAULT_COMPRESSION_MIN_BYTES,
+            )
+            .map_err(|e| errors.push(e))
+            .ok(),
+        };
+        let jwt_secret = match lookup("AUTH_ENABLED").as_deref() {
+            Some("true") => match lookup("JWT_SECRET").filter(|secret| !secret.is_empty()) {
+                Some(secret) => Some(JwtSecret::new(secret)),
+                None => {
+                    errors.push("JWT_SECRET must be set when AUTH_ENABLED=true".to_string());
+                    None
+                }
+            },
+            _ => None,
+        };
+        let shutdown_timeout_secs = parse_var(
+            &lookup,
+            "SHUTDOWN_TIMEOUT_SECS",
+            DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+        )
+        .map_err(|e| errors.push(e))
+        .unwrap_or_default();
+        let run_migrations = lookup("RUN_MIGRATIONS").is_none_or(|value| value != "false");
+        let seed_on_start = lookup("SEED_ON_START").is_some_and(|value| value == "true");
+        let allow_destructive = lookup("ALLOW_DESTRUCTIVE").is_some_and(|value| value == "true");
+
+        match (database_url, bind_addr) {
+            (Some(database_url), Some(bind_addr)) if errors.is_empty() => Ok(Self {
+                database_url,
+                bind_addr,
+                log_level,
+                pool: PoolConfig {
+                    max_connections,
+                    acquire_timeout: Duration::from_secs(acquire_timeout_secs),
+                    slow_query: Duration::from_millis(slow_query_ms),
+                    retry: RetryPolicy {
+                        max_retries,
+                        ..RetryPolicy::default()
+                    },
+                },
+                http: HttpConfig {
+                    cors_origins,
+                    body_limit,
+                    idempotency_ttl: Duration::from_secs(idempotency_ttl_secs),
+                    request_timeout: Duration::from_secs(request_timeout_secs),
+                    compression_min_size,
+                    jwt_secret,
+                    allow_destructive,
+                },
+                run_migrations,
+                seed_on_start,
+                shutdown_timeout: Duration::from_secs(shutdown_timeout_secs),
+            }),
+            _ => Err(ConfigError(errors)),
+        }
+    }
+}
+
+fn parse_var<T: FromStr>(
+    lookup: impl Fn(&str) -> Option<String>,
+    key: &str,
+    default: T,
+) -> Result<T, String> {
+    match lookup(key) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("{} must be a number, got [{}]", key, value)),
+        None => Ok(default),
+    }
+}
+
+/// Builds the listen address from `APP_HOST` / `APP_PORT`, falling back to 127.0.0.1:3000.
+fn bind_addr(host: Option<String>, port: Option<String>) -> anyhow::Result<SocketAddr> {
+    let host = host.unwrap_or_else(|| DEFAULT_HOST.to_string());
+    let ip: IpAddr = host
+        .parse()
+        .with_context(|| format!("APP_HOST must be an IP address, got [{}]", host))?;
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("APP_PORT must be a port number, got [{}]", port))?,
+        None => DEFAULT_PORT,
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Origins allowed to call the API from a browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorsOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl Default for CorsOrigins {
+    fn default() -> Self {
+        Self::List(vec![HeaderValue::from_static(DEFAULT_CORS_ORIGIN)])
+    }
+}
+
+impl CorsOrigins {
+    /// Parses a comma-separated list of origins, where `*` allows any origin.
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let origins = value
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .collect::<Vec<_>>();
+        if origins.is_empty() {
+            anyhow::bail!("no origin is given");
+        }
+        if origins.contains(&"*") {
+            return Ok(Self::Any);
+        }
+        let origins = origins
+            .into_iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("invalid origin [{}]", origin))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::List(origins))
+    }
+
+    pub fn apply(&self, layer: CorsLayer) -> CorsLayer {
+        match self {
+            Self::Any => layer.allow_origin(Any),
+            Self::List(origins) => layer.allow_origin(AllowOrigin::list(origins.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn load_config_with_defaults() {
+        let config =
+            AppConfig::from_lookup(lookup(&[("DATABASE_URL", "postgres://localhost/todos")]))
+                .unwrap();
+        assert_eq!(config.database_url, "postgres://localhost/todos");
+        assert_eq!(config.bind_addr, SocketAddr::from(([127, 0, 0, 1], 3000)));
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.pool, PoolConfig::default());
+        assert_eq!(config.http, HttpConfig::default());
+        assert!(config.run_migrations);
+        assert!(!config.seed_on_start);
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(30));
+        assert_eq!(
+            format!("{:?}", config.pool.options()),
+            format!(
+                "{:?}",
+                PgPoolOptions::new()
+                    .max_connections(5)
+                    .connect_timeout(Duration::from_secs(30))
+            )
+        );
+    }
+
+    #[test]
+    fn load_pool_config() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("DB_MAX_CONNECTIONS", "20"),
+            ("DB_ACQUIRE_TIMEOUT_SECS", "3"),
+            ("SLOW_QUERY_MS", "50"),
+            ("DB_MAX_RETRIES", "0"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.pool,
+            PoolConfig {
+                max_connections: 20,
+                acquire_timeout: Duration::from_secs(3),
+                slow_query: Duration::from_millis(50),
+                retry: RetryPolicy {
+                    max_retries: 0,
+                    ..RetryPolicy::default()
+                },
+            }
+        );
+
+        let result = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("DB_MAX_CONNECTIONS", "many"),
+        ]));
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError(vec![
+                "DB_MAX_CONNECTIONS must be a number, got [many]".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn load_shutdown_timeout() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("SHUTDOWN_TIMEOUT_SECS", "5"),
+        ]))
+        .unwrap();
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(5));
+
+        let result = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("SHUTDOWN_TIMEOUT_SECS", "soon"),
+        ]));
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError(vec![
+                "SHUTDOWN_TIMEOUT_SECS must be a number, got [soon]".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn load_body_limit() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("BODY_LIMIT_BYTES", "1024"),
+        ]))
+        .unwrap();
+        assert_eq!(config.http.body_limit, 1024);
+    }
+
+    #[test]
+    fn load_idempotency_ttl() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("IDEMPOTENCY_TTL_SECS", "60"),
+        ]))
+        .unwrap();
+        assert_eq!(config.http.idempotency_ttl, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn load_auth() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("AUTH_ENABLED", "true"),
+            ("JWT_SECRET", "secret"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.http.jwt_secret,
+            Some(JwtSecret::new("secret".to_string()))
+        );
+
+        // the secret alone does not turn auth on
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("JWT_SECRET", "secret"),
+        ]))
+        .unwrap();
+        assert_eq!(config.http.jwt_secret, None);
+
+        let result = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("AUTH_ENABLED", "true"),
+        ]));
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError(vec![
+                "JWT_SECRET must be set when AUTH_ENABLED=true".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn load_request_timeout() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("REQUEST_TIMEOUT_SECS", "3"),
+        ]))
+        .unwrap();
+        assert_eq!(config.http.request_timeout, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn load_compression() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("COMPRESSION_MIN_BYTES", "256"),
+        ]))
+        .unwrap();
+        assert_eq!(config.http.compression_min_size, Some(256));
+
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("COMPRESSION_ENABLED", "false"),
+            ("COMPRESSION_MIN_BYTES", "256"),
+        ]))
+        .unwrap();
+        assert_eq!(config.http.compression_min_size, None);
+    }
+
+    #[test]
+    fn allow_destructive() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("ALLOW_DESTRUCTIVE", "true"),
+        ]))
+        .unwrap();
+        assert!(config.http.allow_destructive);
+    }
+
+    #[test]
+    fn disable_migrations() {
+        let config = AppConfig::from_lookup(lookup(&[
+            ("DATABASE_URL", "postgres://localhost/todos"),
+            ("RUN_MIGRATIONS", "false"),
+        ]))
+        .unwrap();
+        assert!(!config.run_migrations);
+    }
+
+    #[test]
+    fn report_every_config_error_at_once() {
+        let result = AppConfig::from_lookup(lookup(&[
+            ("APP_HOST", "localhost"),
+            ("APP_PORT", "3000"),
+            ("CORS_ALLOWED_ORIGINS", ","),
+        ]));
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError(vec![
+                "DATABASE_URL is not set".to_string(),
+                "APP_HOST must be an IP address, got [localhost]: invalid IP address syntax"
+                    .to_string(),
+                "CORS_ALLOWED_ORIGINS: no origin is given".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_bind_addr() {
+        assert_eq!(
+            bind_addr(Some("0.0.0.0".to_string()), Some("8080".to_string())).unwrap(),
+            SocketAddr::from(([0, 0, 0, 0], 8080))
+        );
+        assert!(bind_addr(None, Some("70000".to_string())).is_err());
+    }
+
+    #[test]
+    fn parse_cors_origins() {
+        assert_eq!(
+            CorsOrigins::parse("http://a.example, http://b.example").unwrap(),
+            CorsOrigins::List(vec![
+                HeaderValue::from_static("http://a.example"),
+                HeaderValue::from_static("http://b.example"),
+            ])
+        );
+        assert_eq!(CorsOrigins::parse("*").unwrap(), CorsOrigins::Any);
+        assert!(CorsOrigins::parse(" , ").is_err());
+        assert!(CorsOrigins::parse("http://a.example\u{7f}").is_err());
+    }
+}
@@ -1,15 +1,67 @@
 use axum::{
     async_trait,
-    extract::{FromRequest, RequestParts},
+    extract::{Form, FromRequest, Path, Query, RequestParts},
+    http::{
+        header::{HeaderName, ACCEPT, CONTENT_TYPE},
+        HeaderMap, Request,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
     BoxError, Json,
 };
 use hyper::StatusCode;
-use serde::de::DeserializeOwned;
-use validator::Validate;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, convert::Infallible, fmt::Display};
+use utoipa::IntoParams;
+use uuid::Uuid;
+use validator::{Validate, ValidationErrors};
 
+use crate::{
+    auth::AuthSubject,
+    repositories::{classify, RepositoryError},
+};
+
+pub mod events;
+pub mod health;
 pub mod label;
+pub mod metrics;
+pub mod openapi;
 pub mod todo;
 
+/// `Json` extractor answering with a JSON body when the body does not parse.
+#[derive(Debug)]
+pub struct CheckedJson<T>(T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for CheckedJson<T>
+where
+    T: DeserializeOwned,
+    B: http_body::Body + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req).await.map_err(|rejection| {
+            let message = format!("Json parse error: [{}]", rejection);
+            // bodies without Content-Length only hit the size limit while being read
+            if rejection.into_response().status() == StatusCode::PAYLOAD_TOO_LARGE {
+                return error_response(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    json!({ "error": "payload_too_large", "message": message }),
+                );
+            }
+            error_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "invalid_json", "message": message }),
+            )
+        })?;
+        Ok(CheckedJson(value))
+    }
+}
+
 #[derive(Debug)]
 pub struct ValidatedJson<T>(T);
 
@@ -21,17 +73,414 @@ where
     B::Data: Send,
     B::Error: Into<BoxError>,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = Response;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let Json(value) = Json::<T>::from_request(req).await.map_err(|rejection| {
-            let message = format!("Json parse error: [{}]", rejection);
-            (StatusCode::BAD_REQUEST, message)
+        let CheckedJson(value) = CheckedJson::<T>::from_request(req).await?;
+        value.validate().map_err(validation_error)?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Like `ValidatedJson`, but also accepts `application/x-www-form-urlencoded` bodies.
+/// Any other content type is rejected with 415.
+#[derive(Debug)]
+pub struct ValidatedJsonOrForm<T>(T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for ValidatedJsonOrForm<T>
+where
+    T: DeserializeOwned + Validate,
+    B: http_body::Body + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let mime = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok());
+        match mime.as_ref().map(|mime| mime.essence_str()) {
+            Some(essence) if essence == mime::APPLICATION_JSON.essence_str() => {
+                let ValidatedJson(value) = ValidatedJson::<T>::from_request(req).await?;
+                Ok(ValidatedJsonOrForm(value))
+            }
+            Some(essence) if essence == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str() => {
+                let Form(value) = Form::<T>::from_request(req).await.map_err(|rejection| {
+                    let message = format!("Form parse error: [{}]", rejection);
+                    error_response(
+                        StatusCode::BAD_REQUEST,
+                        json!({ "error": "invalid_form", "message": message }),
+                    )
+                })?;
+                value.validate().map_err(validation_error)?;
+                Ok(ValidatedJsonOrForm(value))
+            }
+            _ => Err(error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                json!({ "error": "unsupported_media_type" }),
+            )),
+        }
+    }
+}
+
+fn validation_error(errors: ValidationErrors) -> Response {
+    error_response(
+        StatusCode::BAD_REQUEST,
+        json!({ "errors": field_messages(&errors) }),
+    )
+}
+
+/// `Path` extractor answering 400 with a JSON body when an id segment does not parse.
+#[derive(Debug)]
+pub struct IdPath<T>(T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for IdPath<T>
+where
+    T: DeserializeOwned + Send,
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request(req).await.map_err(|rejection| {
+            tracing::debug!("invalid path: {}", rejection);
+            error_response(StatusCode::BAD_REQUEST, json!({ "error": "invalid_id" }))
         })?;
-        value.validate().map_err(|rejection| {
-            let message = format!("Validation error: [{}]", rejection).replace('\n', " ");
-            (StatusCode::BAD_REQUEST, message)
+        Ok(IdPath(value))
+    }
+}
+
+/// `Query` extractor answering 400 with a JSON body when the query string does not parse.
+#[derive(Debug)]
+pub struct CheckedQuery<T>(T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for CheckedQuery<T>
+where
+    T: DeserializeOwned,
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request(req).await.map_err(|rejection| {
+            error_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "invalid_query", "message": rejection.to_string() }),
+            )
         })?;
-        Ok(ValidatedJson(value))
+        Ok(CheckedQuery(value))
+    }
+}
+
+/// Answers requests aborted by the `TimeoutLayer` of `create_app`.
+pub async fn handle_timeout(err: BoxError) -> Response {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        error_response(
+            StatusCode::REQUEST_TIMEOUT,
+            json!({ "error": "request_timeout" }),
+        )
+    } else {
+        internal_error(err)
+    }
+}
+
+/// Logs the whole error chain under a fresh correlation id, which is all the client gets to see.
+fn internal_error(err: impl Display) -> Response {
+    let correlation_id = Uuid::new_v4().to_string();
+    tracing::error!(%correlation_id, "unexpected error: {:#}", err);
+    error_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        json!({ "error": "internal", "correlation_id": correlation_id }),
+    )
+}
+
+/// Body format of `HandlerError` responses, negotiated from the `Accept` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ErrorFormat {
+    #[default]
+    Json,
+    PlainText,
+}
+
+impl ErrorFormat {
+    /// Plain text only when `text/plain` is weighted above `application/json`.
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let accept = headers
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let quality = |media_type: &str| {
+            accept
+                .split(',')
+                .filter_map(|range| {
+                    let mut params = range.split(';').map(str::trim);
+                    if !params.next()?.eq_ignore_ascii_case(media_type) {
+                        return None;
+                    }
+                    params
+                        .find_map(|param| param.strip_prefix("q="))
+                        .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+                })
+                .fold(0.0, f32::max)
+        };
+        if quality(mime::TEXT_PLAIN.as_ref()) > quality(mime::APPLICATION_JSON.as_ref()) {
+            Self::PlainText
+        } else {
+            Self::Json
+        }
+    }
+}
+
+tokio::task_local! {
+    static ERROR_FORMAT: ErrorFormat;
+}
+
+/// Makes the `Accept` header of the request known to `error_response`.
+pub async fn negotiate_error_format<B>(req: Request<B>, next: Next<B>) -> Response {
+    let format = ErrorFormat::from_headers(req.headers());
+    ERROR_FORMAT.scope(format, next.run(req)).await
+}
+
+/// Renders the error as JSON, or as `key: value` lines for clients preferring `text/plain`.
+/// Every error answered by the API goes through here.
+pub fn error_response(status: StatusCode, body: Value) -> Response {
+    match ERROR_FORMAT.try_with(|format| *format).unwrap_or_default() {
+        ErrorFormat::Json => (status, Json(body)).into_response(),
+        ErrorFormat::PlainText => (status, plain_text(&body)).into_response(),
+    }
+}
+
+fn plain_text(body: &Value) -> String {
+    let line = |key: &str, value: &Value| match value {
+        Value::String(value) => format!("{}: {}\n", key, value),
+        value => format!("{}: {}\n", key, value),
+    };
+    match body {
+        // the error code leads, the details follow
+        Value::Object(fields) => fields
+            .get_key_value("error")
+            .into_iter()
+            .chain(fields.iter().filter(|(key, _)| *key != "error"))
+            .map(|(key, value)| line(key, value))
+            .collect(),
+        body => format!("{}\n", body),
+    }
+}
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Optional `Idempotency-Key` header, answering 400 when it is empty, too long or not visible ASCII.
+#[derive(Debug)]
+pub struct IdempotencyKey(Option<String>);
+
+#[async_trait]
+impl<B> FromRequest<B> for IdempotencyKey
+where
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let value = match req.headers().get(IDEMPOTENCY_KEY) {
+            Some(value) => value,
+            None => return Ok(IdempotencyKey(None)),
+        };
+        match value.to_str() {
+            // `to_str` lets spaces through, which would blur the owner scoping of the key
+            Ok(key)
+                if !key.is_empty()
+                    && key.len() <= 255
+                    && key.bytes().all(|byte| byte.is_ascii_graphic()) =>
+            {
+                Ok(IdempotencyKey(Some(key.to_string())))
+            }
+            _ => Err(error_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "invalid_idempotency_key" }),
+            )),
+        }
+    }
+}
+
+/// Subject of the authenticated user, `None` when authentication is disabled.
+#[derive(Debug)]
+pub struct Owner(Option<String>);
+
+#[async_trait]
+impl<B> FromRequest<B> for Owner
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let subject = req.extensions().get::<AuthSubject>();
+        Ok(Owner(subject.map(|subject| subject.0.clone())))
+    }
+}
+
+/// Output options of the list endpoints.
+#[derive(Debug, Clone, Copy, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JsonFormat {
+    /// Indent the JSON body, handy while debugging.
+    #[serde(default)]
+    pretty: bool,
+}
+
+impl JsonFormat {
+    pub fn json<T>(self, value: T) -> FormattedJson<T> {
+        FormattedJson {
+            value,
+            pretty: self.pretty,
+        }
+    }
+}
+
+/// `Json` that is indented when the request asked for it, see `JsonFormat`.
+#[derive(Debug)]
+pub struct FormattedJson<T> {
+    value: T,
+    pretty: bool,
+}
+
+impl<T: Serialize> IntoResponse for FormattedJson<T> {
+    fn into_response(self) -> Response {
+        let body = if self.pretty {
+            serde_json::to_vec_pretty(&self.value)
+        } else {
+            serde_json::to_vec(&self.value)
+        };
+        match body {
+            Ok(body) => ([(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())], body).into_response(),
+            Err(err) => internal_error(err),
+        }
+    }
+}
+
+/// Collects the messages of each invalid field, falling back to the error code.
+/// Schema level errors are reported under `__all__`.
+fn field_messages(errors: &ValidationErrors) -> BTreeMap<&str, Vec<String>> {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|error| {
+                    error
+                        .message
+                        .as_ref()
+                        .map_or_else(|| error.code.to_string(), |message| message.to_string())
+                })
+                .collect();
+            (field, messages)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct HandlerError(anyhow::Error);
+
+impl From<anyhow::Error> for HandlerError {
+    fn from(err: anyhow::Error) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        // repositories often bubble sqlx errors up with `?`, classify them here as well
+        let err = classify(self.0);
+        let (status, body) = match err.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::Duplicate(id)) => (
+                StatusCode::CONFLICT,
+                json!({ "error": "duplicate", "id": id }),
+            ),
+            Some(RepositoryError::InUse(id)) => {
+                (StatusCode::CONFLICT, json!({ "error": "in_use", "id": id }))
+            }
+            Some(RepositoryError::UnknownLabels(ids)) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "error": "unknown_labels", "ids": ids }),
+            ),
+            Some(RepositoryError::UnknownParent(parent_id)) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "error": "unknown_parent", "parent_id": parent_id }),
+            ),
+            Some(RepositoryError::ParentCycle(parent_id)) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "error": "parent_cycle", "parent_id": parent_id }),
+            ),
+            Some(RepositoryError::OrderMismatch) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "error": "order_mismatch" }),
+            ),
+            Some(RepositoryError::VersionConflict(version)) => (
+                StatusCode::PRECONDITION_FAILED,
+                json!({ "error": "version_conflict", "version": version }),
+            ),
+            Some(RepositoryError::NotFound(id)) => (
+                StatusCode::NOT_FOUND,
+                json!({ "error": "not_found", "id": id }),
+            ),
+            Some(RepositoryError::UniqueViolation(constraint)) => (
+                StatusCode::CONFLICT,
+                json!({ "error": "unique_violation", "constraint": constraint }),
+            ),
+            Some(RepositoryError::ForeignKeyViolation(constraint)) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "error": "foreign_key_violation", "constraint": constraint }),
+            ),
+            _ => return internal_error(err),
+        };
+        error_response(status, body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn unexpected_error_has_correlation_id() {
+        let res = HandlerError::from(anyhow::anyhow!("connection reset")).into_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"], "internal");
+        let correlation_id = body["correlation_id"].as_str().unwrap();
+        assert!(Uuid::parse_str(correlation_id).is_ok());
+        assert!(!body.to_string().contains("connection reset"));
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn foreign_key_violation_is_unprocessable() {
+        use dotenv::dotenv;
+        use sqlx::PgPool;
+        use std::env;
+
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let err = sqlx::query("insert into todo_labels (todo_id, label_id) values (-1, -1)")
+            .execute(&pool)
+            .await
+            .expect_err("insert referencing missing rows must fail");
+
+        let res = HandlerError::from(anyhow::Error::from(err)).into_response();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"], "foreign_key_violation");
     }
 }
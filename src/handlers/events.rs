@@ -0,0 +1,219 @@
+use axum::{
+    async_trait,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, FromRequest, RequestParts,
+    },
+    http::StatusCode,
+    response::Response,
+};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use crate::repositories::todo::TodoEntity;
+
+use super::{error_response, Owner};
+
+/// Change to todos, pushed to `/ws` subscribers as `{"type": ..., "todo": ...}`, or as
+/// `{"type": "bulk", "action": ..., "count": ...}` when an endpoint changed several at once.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TodoEvent {
+    Created {
+        todo: TodoEntity,
+    },
+    Updated {
+        todo: TodoEntity,
+    },
+    Deleted {
+        todo: DeletedTodo,
+    },
+    /// Subscribers are expected to refetch the todos they show.
+    Bulk {
+        action: BulkAction,
+        count: u64,
+        #[serde(skip)]
+        owner_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct DeletedTodo {
+    pub id: i32,
+    #[serde(skip)]
+    pub owner_id: Option<String>,
+}
+
+/// Bulk endpoint behind a `bulk` event.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    Import,
+    BatchDelete,
+    CompleteAll,
+    IncompleteAll,
+    DeleteCompleted,
+    Reorder,
+    AssignLabel,
+    Purge,
+}
+
+impl TodoEvent {
+    fn owner_id(&self) -> Option<&str> {
+        match self {
+            TodoEvent::Created { todo } | TodoEvent::Updated { todo } => todo.owner_id.as_deref(),
+            TodoEvent::Deleted { todo } => todo.owner_id.as_deref(),
+            TodoEvent::Bulk { owner_id, .. } => owner_id.as_deref(),
+        }
+    }
+}
+
+/// Fans `TodoEvent`s out to every open WebSocket.
+#[derive(Debug, Clone)]
+pub struct TodoEvents(broadcast::Sender<TodoEvent>);
+
+impl TodoEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self(sender)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TodoEvent> {
+        self.0.subscribe()
+    }
+
+    pub fn publish(&self, event: TodoEvent) {
+        // nobody listening is not an error
+        let _ = self.0.send(event);
+    }
+
+    /// Publishes a `bulk` event unless the endpoint did not change anything.
+    pub fn publish_bulk(&self, owner_id: Option<String>, action: BulkAction, count: u64) {
+        if count > 0 {
+            self.publish(TodoEvent::Bulk {
+                action,
+                count,
+                owner_id,
+            });
+        }
+    }
+}
+
+impl Default for TodoEvents {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+/// Clients have nothing to send, so anything bigger is dropped along with the connection.
+const MAX_INCOMING_MESSAGE: usize = 4096;
+
+/// `WebSocketUpgrade` answering 400 with a JSON body when the request is not a handshake.
+#[derive(Debug)]
+pub struct CheckedUpgrade(WebSocketUpgrade);
+
+#[async_trait]
+impl<B> FromRequest<B> for CheckedUpgrade
+where
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let upgrade = WebSocketUpgrade::from_request(req)
+            .await
+            .map_err(|rejection| {
+                error_response(
+                    StatusCode::BAD_REQUEST,
+                    json!({ "error": "invalid_websocket_upgrade", "message": rejection.to_string() }),
+                )
+            })?;
+        Ok(CheckedUpgrade(upgrade))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    responses(
+        (status = 101, description = "WebSocket pushing `{type, todo}` events of the caller's todos"),
+        (status = 400, description = "Not a WebSocket handshake"),
+    )
+)]
+pub async fn todo_events(
+    Owner(owner_id): Owner,
+    Extension(events): Extension<TodoEvents>,
+    CheckedUpgrade(upgrade): CheckedUpgrade,
+) -> Response {
+    let receiver = events.subscribe();
+    upgrade
+        .max_message_size(MAX_INCOMING_MESSAGE)
+        .max_frame_size(MAX_INCOMING_MESSAGE)
+        .on_upgrade(move |socket| push_events(socket, owner_id, receiver))
+}
+
+async fn push_events(
+    mut socket: WebSocket,
+    owner_id: Option<String>,
+    mut events: broadcast::Receiver<TodoEvent>,
+) {
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if event.owner_id() == owner_id.as_deref() => {
+                    Message::Text(serde_json::to_string(&event).expect("events serialize"))
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("websocket subscriber missed {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // pings are answered and a close is acknowledged while receiving, the stream
+            // ends once the close handshake is done
+            message = socket.recv() => match message {
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => break,
+            },
+        };
+        if socket.send(message).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_serialize_with_type_tag() {
+        let todo = TodoEntity::new(1, "todo".to_string(), vec![]);
+        let event = serde_json::to_value(TodoEvent::Created { todo: todo.clone() }).unwrap();
+        assert_eq!(event["type"], "created");
+        assert_eq!(event["todo"], serde_json::to_value(&todo).unwrap());
+
+        let event = TodoEvent::Deleted {
+            todo: DeletedTodo {
+                id: 1,
+                owner_id: Some("alice".to_string()),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            json!({ "type": "deleted", "todo": { "id": 1 } })
+        );
+
+        let event = TodoEvent::Bulk {
+            action: BulkAction::CompleteAll,
+            count: 3,
+            owner_id: Some("alice".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            json!({ "type": "bulk", "action": "complete_all", "count": 3 })
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::repositories::health::HealthRepository;
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "Database is reachable"),
+        (status = 503, description = "Database is unavailable"),
+    )
+)]
+pub async fn health<T: HealthRepository + ?Sized>(
+    Extension(repository): Extension<Arc<T>>,
+) -> impl IntoResponse {
+    match repository.check().await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "status": "ok" }))),
+        Err(e) => {
+            tracing::error!("health check failed: {}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "db_unavailable" })),
+            )
+        }
+    }
+}
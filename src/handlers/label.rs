@@ -1,49 +1,299 @@
 use axum::{
-    extract::{Extension, Path},
-    http::StatusCode,
+    extract::Extension,
+    http::{header::LOCATION, StatusCode},
     response::IntoResponse,
     Json,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use serde_json::json;
 use std::sync::Arc;
-use validator::Validate;
 
-use crate::repositories::label::LabelRepository;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
 
-use super::ValidatedJson;
+use crate::repositories::label::{CreateLabel, Label, LabelQuery, LabelRepository, UpdateLabel};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
-pub struct CreateLabel {
-    #[validate(length(min = 1, message = "Can not be empty"))]
-    #[validate(length(max = 20, message = "Name is too long"))]
-    name: String,
+use super::{
+    CheckedQuery, HandlerError, IdPath, JsonFormat, Owner, ValidatedJson, ValidatedJsonOrForm,
+};
+
+#[utoipa::path(
+    post,
+    path = "/labels",
+    request_body = CreateLabel,
+    responses(
+        (status = 201, description = "Label created", body = Label),
+        (status = 400, description = "Invalid payload"),
+        (status = 409, description = "Label name already exists"),
+        (status = 415, description = "Body is neither JSON nor form-encoded"),
+    )
+)]
+pub async fn create_label<T: LabelRepository + ?Sized>(
+    ValidatedJsonOrForm(payload): ValidatedJsonOrForm<CreateLabel>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let label = repository.create(payload).await?;
+    let location = format!("/labels/{}", label.id);
+    Ok((StatusCode::CREATED, [(LOCATION, location)], Json(label)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/labels/{id}",
+    params(("id" = i32, Path, description = "Label id")),
+    responses(
+        (status = 200, description = "Label found", body = Label),
+        (status = 404, description = "Label not found"),
+    )
+)]
+pub async fn find_label<T: LabelRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let label = repository.find(id).await?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/labels",
+    params(LabelQuery, JsonFormat),
+    responses(
+        (status = 200, description = "Labels matching `q`, all of them by default", body = [Label]),
+    )
+)]
+pub async fn all_label<T: LabelRepository + ?Sized>(
+    CheckedQuery(query): CheckedQuery<LabelQuery>,
+    CheckedQuery(format): CheckedQuery<JsonFormat>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let label = repository.all(query).await?;
+    Ok((StatusCode::OK, format.json(label)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/labels/stats",
+    params(JsonFormat),
+    responses(
+        (status = 200, description = "Labels with the number of the caller's todos carrying them", body = [LabelWithCount]),
+    )
+)]
+pub async fn label_stats<T: LabelRepository + ?Sized>(
+    CheckedQuery(format): CheckedQuery<JsonFormat>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let labels = repository.all_with_counts(owner_id.as_deref()).await?;
+    Ok((StatusCode::OK, format.json(labels)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/labels/{id}/count",
+    params(("id" = i32, Path, description = "Label id")),
+    responses(
+        (status = 200, description = "Number of the caller's todos carrying the label as `{count}`"),
+        (status = 404, description = "Label not found"),
+    )
+)]
+pub async fn label_todo_count<T: LabelRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let count = repository.todo_count(owner_id.as_deref(), id).await?;
+    Ok((StatusCode::OK, Json(json!({ "count": count }))))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/labels/{id}",
+    params(("id" = i32, Path, description = "Label id")),
+    request_body = UpdateLabel,
+    responses(
+        (status = 200, description = "Label updated", body = Label),
+        (status = 400, description = "Invalid payload"),
+        (status = 404, description = "Label not found"),
+    )
+)]
+pub async fn update_label<T: LabelRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    ValidatedJson(payload): ValidatedJson<UpdateLabel>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let label = repository.update(id, payload).await?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteLabelOptions {
+    #[serde(default)]
+    force: bool,
 }
 
-pub async fn create_label<T: LabelRepository>(
-    ValidatedJson(payload): ValidatedJson<CreateLabel>,
+#[utoipa::path(
+    delete,
+    path = "/labels/{id}",
+    params(("id" = i32, Path, description = "Label id"), DeleteLabelOptions),
+    responses(
+        (status = 204, description = "Label deleted"),
+        (status = 404, description = "Label not found"),
+        (status = 409, description = "Label is still attached to todos"),
+    )
+)]
+pub async fn delete_label<T: LabelRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    CheckedQuery(options): CheckedQuery<DeleteLabelOptions>,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let label = repository
-        .create(payload.name)
-        .await
-        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
-    Ok((StatusCode::CREATED, Json(label)))
+) -> Result<StatusCode, HandlerError> {
+    repository.delete(id, options.force).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[validate(schema(function = "validate_distinct_labels"))]
+pub struct MergeLabels {
+    from: i32,
+    into: i32,
 }
 
-pub async fn all_label<T: LabelRepository>(
+fn validate_distinct_labels(payload: &MergeLabels) -> Result<(), ValidationError> {
+    if payload.from == payload.into {
+        let mut error = ValidationError::new("same_label");
+        error.message = Some("Can not merge a label into itself".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/labels/merge",
+    request_body = MergeLabels,
+    responses(
+        (status = 200, description = "Labels merged into the target", body = Label),
+        (status = 400, description = "Invalid payload"),
+        (status = 404, description = "Label not found"),
+    )
+)]
+pub async fn merge_labels<T: LabelRepository + ?Sized>(
+    ValidatedJson(payload): ValidatedJson<MergeLabels>,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let label = repository.all().await.unwrap();
+) -> Result<impl IntoResponse, HandlerError> {
+    let label = repository.merge(payload.from, payload.into).await?;
     Ok((StatusCode::OK, Json(label)))
 }
 
-pub async fn delete_label<T: LabelRepository>(
-    Path(id): Path<i32>,
+#[utoipa::path(
+    post,
+    path = "/labels/{id}/archive",
+    params(("id" = i32, Path, description = "Label id")),
+    responses(
+        (status = 200, description = "Label archived", body = Label),
+        (status = 404, description = "Label not found"),
+    )
+)]
+pub async fn archive_label<T: LabelRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let label = repository.archive(id).await?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SuggestLabels {
+    #[validate(length(max = 100, message = "Text is too long"))]
+    text: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/suggest-labels",
+    request_body = SuggestLabels,
+    responses(
+        (status = 200, description = "Labels whose name appears as whole words in the text", body = [Label]),
+        (status = 400, description = "Invalid payload"),
+    )
+)]
+pub async fn suggest_labels<T: LabelRepository + ?Sized>(
+    ValidatedJson(payload): ValidatedJson<SuggestLabels>,
     Extension(repository): Extension<Arc<T>>,
-) -> StatusCode {
-    repository
-        .delete(id)
-        .await
-        .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+) -> Result<impl IntoResponse, HandlerError> {
+    let labels = repository.all(LabelQuery::default()).await?;
+    Ok((StatusCode::OK, Json(match_labels(&payload.text, labels))))
+}
+
+/// Lowercase runs of letters and digits, everything else separates words.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Keeps the labels whose name shows up in `text` as whole words, ignoring case and punctuation.
+/// Names of several words must appear next to each other.
+pub fn match_labels(text: &str, labels: Vec<Label>) -> Vec<Label> {
+    let text = words(text);
+    labels
+        .into_iter()
+        .filter(|label| {
+            let name = words(&label.name);
+            !name.is_empty() && text.windows(name.len()).any(|window| window == name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn labels(names: &[&str]) -> Vec<Label> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Label::new(i as i32 + 1, name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn match_labels_by_whole_words() {
+        let found = match_labels(
+            "Buy milk for the office",
+            labels(&["Office", "shopping", "milk"]),
+        );
+        assert_eq!(
+            found,
+            vec![
+                Label::new(1, "Office".to_string()),
+                Label::new(3, "milk".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_labels_only_part_of_a_word() {
+        let found = match_labels("Finish homework", labels(&["work", "home", "homework"]));
+        assert_eq!(found, vec![Label::new(3, "homework".to_string())]);
+        assert!(match_labels("nothing to see", labels(&["work"])).is_empty());
+    }
+
+    #[test]
+    fn ignore_punctuation_around_words() {
+        let found = match_labels(
+            "Call mom (urgent!), then: high-priority stuff.",
+            labels(&["urgent", "High Priority", "priority stuff", "mom's", "!!!"]),
+        );
+        assert_eq!(
+            found,
+            vec![
+                Label::new(1, "urgent".to_string()),
+                Label::new(2, "High Priority".to_string()),
+                Label::new(3, "priority stuff".to_string()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,16 @@
+use axum::{extract::Extension, http::header::CONTENT_TYPE, response::IntoResponse};
+use std::sync::Arc;
+
+use crate::metrics::Metrics;
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text exposition format"),
+    )
+)]
+pub async fn render_metrics(Extension(metrics): Extension<Arc<Metrics>>) -> impl IntoResponse {
+    let (content_type, body) = metrics.render();
+    ([(CONTENT_TYPE, content_type)], body)
+}
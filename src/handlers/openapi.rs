@@ -0,0 +1,95 @@
+use axum::Json;
+use utoipa::OpenApi;
+
+use super::{
+    events, health,
+    label::{self, MergeLabels, SuggestLabels},
+    metrics,
+    todo::{self, AssignLabel, BatchDeleteTodos, BatchGetTodos, ExportFormat, ReorderTodos},
+};
+use crate::repositories::{
+    label::{CreateLabel, Label, LabelWithCount, UpdateLabel},
+    todo::{
+        CreateTodo, ImportSummary, ImportTodo, LabelMatch, LabelMode, Priority, ReplaceTodo,
+        TodoEntity, TodoSort, TodoStats, UpdateTodo, UpdateTodoStatus,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        health::health,
+        metrics::render_metrics,
+        events::todo_events,
+        todo::create_todo,
+        todo::create_todos,
+        todo::import_todos,
+        todo::find_todos,
+        todo::delete_todos,
+        todo::find_todo,
+        todo::all_todo,
+        todo::grouped_todo,
+        todo::count_todo,
+        todo::open_count_todo,
+        todo::todo_stats,
+        todo::export_todos,
+        todo::find_todos_by_label,
+        todo::assign_label,
+        todo::update_todo,
+        todo::replace_todo,
+        todo::reorder_todos,
+        todo::update_todo_status,
+        todo::toggle_complete,
+        todo::duplicate_todo,
+        todo::find_todo_labels,
+        todo::find_todo_children,
+        todo::add_todo_label,
+        todo::remove_todo_label,
+        todo::delete_todo,
+        todo::complete_all_todo,
+        todo::incomplete_all_todo,
+        todo::delete_completed_todo,
+        todo::purge_todos,
+        todo::restore_todo,
+        label::create_label,
+        label::find_label,
+        label::all_label,
+        label::label_stats,
+        label::label_todo_count,
+        label::update_label,
+        label::delete_label,
+        label::merge_labels,
+        label::archive_label,
+        label::suggest_labels,
+    ),
+    components(schemas(
+        TodoEntity,
+        Priority,
+        TodoSort,
+        LabelMatch,
+        LabelMode,
+        ExportFormat,
+        CreateTodo,
+        UpdateTodo,
+        ReplaceTodo,
+        UpdateTodoStatus,
+        ImportTodo,
+        ImportSummary,
+        TodoStats,
+        BatchGetTodos,
+        BatchDeleteTodos,
+        AssignLabel,
+        ReorderTodos,
+        Label,
+        LabelWithCount,
+        CreateLabel,
+        UpdateLabel,
+        MergeLabels,
+        SuggestLabels,
+    ))
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
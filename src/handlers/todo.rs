@@ -1,61 +1,1027 @@
 use axum::{
-    extract::{Extension, Path},
-    http::StatusCode,
-    response::IntoResponse,
+    async_trait,
+    extract::{Extension, FromRequest, RequestParts},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION},
+        HeaderMap, HeaderName, StatusCode,
+    },
+    response::{IntoResponse, Response},
     Json,
 };
-use std::sync::Arc;
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::repositories::{
+    todo::{
+        CreateTodo, ImportTodo, LabelMode, ReplaceTodo, TodoEntity, TodoFilter, TodoRepository,
+        TodoSort, UpdateTodo, UpdateTodoStatus,
+    },
+    Pagination,
+};
+
+use super::{
+    error_response,
+    events::{BulkAction, DeletedTodo, TodoEvent, TodoEvents},
+    field_messages, CheckedJson, CheckedQuery, HandlerError, IdPath, IdempotencyKey, JsonFormat,
+    Owner, ValidatedJson, ValidatedJsonOrForm,
+};
 
-use crate::repositories::todo::{CreateTodo, TodoRepository, UpdateTodo};
+/// How long a processed `Idempotency-Key` is remembered.
+#[derive(Debug, Clone, Copy)]
+pub struct IdempotencyTtl(pub Duration);
 
-use super::ValidatedJson;
+/// Whether `DELETE /todos/all` may run, enabled with `ALLOW_DESTRUCTIVE=true`.
+#[derive(Debug, Clone, Copy)]
+pub struct AllowDestructive(pub bool);
 
-pub async fn create_todo<T: TodoRepository>(
-    ValidatedJson(payload): ValidatedJson<CreateTodo>,
+#[utoipa::path(
+    post,
+    path = "/todos",
+    request_body = CreateTodo,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Repeating a key returns the todo created first"),
+    ),
+    responses(
+        (status = 201, description = "Todo created", body = TodoEntity),
+        (status = 400, description = "Invalid payload or idempotency key"),
+        (status = 415, description = "Body is neither JSON nor form-encoded"),
+        (status = 422, description = "Unknown label ids or parent todo"),
+    )
+)]
+pub async fn create_todo<T: TodoRepository + ?Sized>(
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJsonOrForm(payload): ValidatedJsonOrForm<CreateTodo>,
+    Owner(owner_id): Owner,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repository
-        .create(payload)
+    Extension(IdempotencyTtl(ttl)): Extension<IdempotencyTtl>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = match key {
+        Some(key) => {
+            repository
+                .create_idempotent(owner_id.as_deref(), key, ttl, payload)
+                .await?
+        }
+        None => repository.create(owner_id.as_deref(), payload).await?,
+    };
+    events.publish(TodoEvent::Created { todo: todo.clone() });
+    let location = format!("/todos/{}", todo.id);
+
+    Ok((StatusCode::CREATED, [(LOCATION, location)], Json(todo)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/batch",
+    request_body = Vec<CreateTodo>,
+    responses(
+        (status = 201, description = "Todos created", body = [TodoEntity]),
+        (status = 400, description = "Invalid todo, reported with its `index`"),
+        (status = 422, description = "Unknown label ids"),
+    )
+)]
+pub async fn create_todos<T: TodoRepository + ?Sized>(
+    CheckedJson(payloads): CheckedJson<Vec<CreateTodo>>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, Response> {
+    for (index, payload) in payloads.iter().enumerate() {
+        payload.validate().map_err(|errors| {
+            let body =
+                json!({ "error": "invalid_item", "index": index, "errors": field_messages(&errors) });
+            error_response(StatusCode::BAD_REQUEST, body)
+        })?;
+    }
+    let todos = repository
+        .create_many(owner_id.as_deref(), payloads)
         .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+        .map_err(|e| HandlerError::from(e).into_response())?;
+    for todo in &todos {
+        events.publish(TodoEvent::Created { todo: todo.clone() });
+    }
+
+    Ok((StatusCode::CREATED, Json(todos)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/import",
+    request_body = Vec<ImportTodo>,
+    responses(
+        (status = 201, description = "Todos imported", body = ImportSummary),
+        (status = 400, description = "Invalid item, reported with its `index`"),
+    )
+)]
+pub async fn import_todos<T: TodoRepository + ?Sized>(
+    CheckedJson(items): CheckedJson<Vec<Value>>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, Response> {
+    // parse the items one by one so that a malformed item can be pointed at
+    let mut imports = vec![];
+    for (index, item) in items.into_iter().enumerate() {
+        let item: ImportTodo = serde_json::from_value(item).map_err(|e| {
+            let body = json!({ "error": "invalid_item", "index": index, "message": e.to_string() });
+            error_response(StatusCode::BAD_REQUEST, body)
+        })?;
+        item.validate().map_err(|errors| {
+            let body =
+                json!({ "error": "invalid_item", "index": index, "errors": field_messages(&errors) });
+            error_response(StatusCode::BAD_REQUEST, body)
+        })?;
+        imports.push(item);
+    }
+    let summary = repository
+        .import(owner_id.as_deref(), imports)
+        .await
+        .map_err(|e| HandlerError::from(e).into_response())?;
+    events.publish_bulk(owner_id, BulkAction::Import, summary.created);
+
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct BatchGetTodos {
+    ids: Vec<i32>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/batch-get",
+    request_body = BatchGetTodos,
+    responses(
+        (status = 200, description = "Todos found, unknown ids are left out", body = [TodoEntity]),
+        (status = 400, description = "Invalid payload"),
+    )
+)]
+pub async fn find_todos<T: TodoRepository + ?Sized>(
+    ValidatedJson(payload): ValidatedJson<BatchGetTodos>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todos = repository
+        .find_many(owner_id.as_deref(), payload.ids)
+        .await?;
+    Ok((StatusCode::OK, Json(todos)))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct BatchDeleteTodos {
+    ids: Vec<i32>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/batch-delete",
+    request_body = BatchDeleteTodos,
+    responses(
+        (status = 200, description = "Number of deleted todos as `{deleted}`, unknown ids are skipped"),
+        (status = 400, description = "Invalid payload"),
+    )
+)]
+pub async fn delete_todos<T: TodoRepository + ?Sized>(
+    ValidatedJson(payload): ValidatedJson<BatchDeleteTodos>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let deleted = repository
+        .delete_many(owner_id.as_deref(), payload.ids)
+        .await?;
+    events.publish_bulk(owner_id, BulkAction::BatchDelete, deleted);
+    Ok((StatusCode::OK, Json(json!({ "deleted": deleted }))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/{id}",
+    params(("id" = i32, Path, description = "Todo id"), TodoFieldsQuery),
+    responses(
+        (status = 200, description = "Todo found", body = TodoEntity),
+        (status = 304, description = "Todo matches If-None-Match"),
+        (status = 400, description = "Unknown name in `fields`"),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn find_todo<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    fields: TodoFields,
+    headers: HeaderMap,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<Response, HandlerError> {
+    let todo = fields.pick(repository.find(owner_id.as_deref(), id).await?);
+    let etag = etag(&todo);
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
+    Ok((StatusCode::OK, [(ETAG, etag)], Json(todo)).into_response())
+}
+
+/// A strong validator computed from the serialized representation of the todo.
+fn etag(todo: &impl Serialize) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(todo)
+        .expect("failed to serialize todo")
+        .hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Keys of a serialized `TodoEntity`.
+const TODO_FIELDS: [&str; 10] = [
+    "id",
+    "text",
+    "completed",
+    "completed_at",
+    "due_date",
+    "priority",
+    "position",
+    "version",
+    "parent_id",
+    "labels",
+];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TodoFieldsQuery {
+    /// Comma separated fields to return, e.g. `fields=id,text`. Every field by default.
+    fields: Option<String>,
+}
+
+/// Sparse fieldset of `?fields=`, answering 400 with the unknown names.
+#[derive(Debug, Default)]
+pub struct TodoFields(Option<Vec<String>>);
 
-    Ok((StatusCode::CREATED, Json(todo)))
+#[async_trait]
+impl<B> FromRequest<B> for TodoFields
+where
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let CheckedQuery(query) = CheckedQuery::<TodoFieldsQuery>::from_request(req).await?;
+        let mut fields: Vec<String> = vec![];
+        let mut unknown = vec![];
+        for field in query.fields.iter().flat_map(|list| list.split(',')) {
+            let field = field.trim();
+            if field.is_empty() || fields.iter().any(|known| known == field) {
+                continue;
+            }
+            if TODO_FIELDS.contains(&field) {
+                fields.push(field.to_string());
+            } else {
+                unknown.push(field.to_string());
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "unknown_fields", "fields": unknown }),
+            ));
+        }
+        Ok(TodoFields((!fields.is_empty()).then_some(fields)))
+    }
+}
+
+impl TodoFields {
+    fn pick(&self, todo: TodoEntity) -> SparseTodo<'_> {
+        SparseTodo {
+            todo,
+            fields: self.0.as_deref(),
+        }
+    }
+
+    fn pick_all(&self, todos: Vec<TodoEntity>) -> Vec<SparseTodo<'_>> {
+        todos.into_iter().map(|todo| self.pick(todo)).collect()
+    }
+}
+
+/// Serializes only the picked fields of the todo, in the order they were asked for.
+#[derive(Debug)]
+struct SparseTodo<'a> {
+    todo: TodoEntity,
+    fields: Option<&'a [String]>,
+}
+
+impl Serialize for SparseTodo<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(fields) = self.fields else {
+            return self.todo.serialize(serializer);
+        };
+        let value = serde_json::to_value(&self.todo).map_err(serde::ser::Error::custom)?;
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for field in fields {
+            map.serialize_entry(field, &value[field.as_str()])?;
+        }
+        map.end()
+    }
+}
+
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AllTodoOptions {
+    #[serde(default)]
+    with_total: bool,
+    /// Respond with `{todos, next_cursor}`, pass `next_cursor` as `after` to fetch the next page.
+    #[serde(default)]
+    with_cursor: bool,
+    #[serde(default)]
+    sort: TodoSort,
+}
+
+#[derive(Debug, Serialize)]
+struct TodoPage<'a> {
+    todos: Vec<SparseTodo<'a>>,
+    total: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct TodoCursorPage<'a> {
+    todos: Vec<SparseTodo<'a>>,
+    next_cursor: Option<i32>,
+}
+
+pub const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+pub const X_PAGE_LIMIT: HeaderName = HeaderName::from_static("x-page-limit");
+pub const X_PAGE_OFFSET: HeaderName = HeaderName::from_static("x-page-offset");
+
+#[utoipa::path(
+    get,
+    path = "/todos",
+    params(TodoFilter, Pagination, AllTodoOptions, TodoFieldsQuery, JsonFormat),
+    responses(
+        (status = 200, description = "Todos, or `{todos, total}` with `with_total=true`, or `{todos, next_cursor}` with `with_cursor=true`", body = [TodoEntity],
+            headers(
+                ("X-Total-Count" = i64, description = "Number of todos matching the filter"),
+                ("X-Page-Limit" = u32, description = "Applied `limit`"),
+                ("X-Page-Offset" = u32, description = "Applied `offset`"),
+            )
+        ),
+        (status = 400, description = "Malformed filter or unknown name in `fields`"),
+    )
+)]
+pub async fn all_todo<T: TodoRepository + ?Sized>(
+    CheckedQuery(filter): CheckedQuery<TodoFilter>,
+    CheckedQuery(pagination): CheckedQuery<Pagination>,
+    CheckedQuery(options): CheckedQuery<AllTodoOptions>,
+    fields: TodoFields,
+    CheckedQuery(format): CheckedQuery<JsonFormat>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<Response, HandlerError> {
+    // the keyset cursor is an id, so it only works in id descending order
+    let sort = if options.with_cursor || pagination.after.is_some() {
+        TodoSort::IdDesc
+    } else {
+        options.sort
+    };
+    let todos = repository
+        .all(owner_id.as_deref(), filter.clone(), sort, pagination)
+        .await?;
+    let total = repository.count(owner_id.as_deref(), filter).await?;
+    let headers = [
+        (X_TOTAL_COUNT, total.to_string()),
+        (X_PAGE_LIMIT, pagination.limit.to_string()),
+        (X_PAGE_OFFSET, pagination.offset.to_string()),
+    ];
+    if options.with_cursor {
+        let next_cursor = match todos.last() {
+            Some(todo) if todos.len() == pagination.limit as usize => Some(todo.id),
+            _ => None,
+        };
+        let todos = fields.pick_all(todos);
+        return Ok((
+            StatusCode::OK,
+            headers,
+            format.json(TodoCursorPage { todos, next_cursor }),
+        )
+            .into_response());
+    }
+    let todos = fields.pick_all(todos);
+    if options.with_total {
+        return Ok((
+            StatusCode::OK,
+            headers,
+            format.json(TodoPage { todos, total }),
+        )
+            .into_response());
+    }
+    Ok((StatusCode::OK, headers, format.json(todos)).into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct GroupedTodos {
+    active: Vec<TodoEntity>,
+    completed: Vec<TodoEntity>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/grouped",
+    params(AllTodoOptions, JsonFormat),
+    responses(
+        (status = 200, description = "Every todo, split into `active` and `completed`"),
+    )
+)]
+pub async fn grouped_todo<T: TodoRepository + ?Sized>(
+    CheckedQuery(options): CheckedQuery<AllTodoOptions>,
+    CheckedQuery(format): CheckedQuery<JsonFormat>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todos = repository
+        .all(
+            owner_id.as_deref(),
+            TodoFilter::default(),
+            options.sort,
+            // a board missing cards is worse than a large response
+            Pagination::unbounded(),
+        )
+        .await?;
+    let (completed, active) = todos.into_iter().partition(|todo| todo.completed);
+    Ok((
+        StatusCode::OK,
+        format.json(GroupedTodos { active, completed }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/count",
+    params(TodoFilter),
+    responses(
+        (status = 200, description = "Number of todos as `{count}`"),
+    )
+)]
+pub async fn count_todo<T: TodoRepository + ?Sized>(
+    CheckedQuery(filter): CheckedQuery<TodoFilter>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let count = repository.count(owner_id.as_deref(), filter).await?;
+    Ok((StatusCode::OK, Json(json!({ "count": count }))))
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportOptions {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/open-count",
+    responses(
+        (status = 200, description = "Number of todos that are not completed as `{open}`"),
+    )
+)]
+pub async fn open_count_todo<T: TodoRepository + ?Sized>(
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let open = repository.open_count(owner_id.as_deref()).await?;
+    Ok((StatusCode::OK, Json(json!({ "open": open }))))
 }
 
-pub async fn find_todo<T: TodoRepository>(
-    Path(id): Path<i32>,
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 200, description = "Todo counts for a dashboard", body = TodoStats),
+    )
+)]
+pub async fn todo_stats<T: TodoRepository + ?Sized>(
+    Owner(owner_id): Owner,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repository.find(id).await.or(Err(StatusCode::NOT_FOUND))?;
+) -> Result<impl IntoResponse, HandlerError> {
+    let stats = repository.stats(owner_id.as_deref()).await?;
+    Ok((StatusCode::OK, Json(stats)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/export",
+    params(ExportOptions),
+    responses(
+        (status = 200, description = "Every todo as a JSON array, or as a CSV attachment with `format=csv`", body = [TodoEntity]),
+    )
+)]
+pub async fn export_todos<T: TodoRepository + ?Sized>(
+    CheckedQuery(options): CheckedQuery<ExportOptions>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<Response, HandlerError> {
+    let pagination = Pagination {
+        limit: u32::MAX,
+        ..Pagination::default()
+    };
+    let todos = repository
+        .all(
+            owner_id.as_deref(),
+            TodoFilter::default(),
+            TodoSort::IdAsc,
+            pagination,
+        )
+        .await?;
+    match options.format {
+        ExportFormat::Json => Ok((StatusCode::OK, Json(todos)).into_response()),
+        ExportFormat::Csv => Ok((
+            StatusCode::OK,
+            [
+                (CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (CONTENT_DISPOSITION, "attachment; filename=\"todos.csv\""),
+            ],
+            todos_to_csv(&todos),
+        )
+            .into_response()),
+    }
+}
+
+/// Renders `id,text,completed,labels` rows, the labels being a comma-joined list of names.
+fn todos_to_csv(todos: &[TodoEntity]) -> String {
+    let mut csv = String::from("id,text,completed,labels\r\n");
+    for todo in todos {
+        let labels = todo
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push_str(&format!(
+            "{},{},{},{}\r\n",
+            todo.id,
+            csv_field(&todo.text),
+            todo.completed,
+            csv_field(&labels)
+        ));
+    }
+    csv
+}
+
+/// Quotes a field as described in RFC 4180 when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/labels/{id}/todos",
+    params(("id" = i32, Path, description = "Label id"), JsonFormat),
+    responses(
+        (status = 200, description = "Todos carrying the label, with all of their labels", body = [TodoEntity]),
+    )
+)]
+pub async fn find_todos_by_label<T: TodoRepository + ?Sized>(
+    IdPath(label_id): IdPath<i32>,
+    CheckedQuery(format): CheckedQuery<JsonFormat>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todos = repository.by_label(owner_id.as_deref(), label_id).await?;
+    Ok((StatusCode::OK, format.json(todos)))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct AssignLabel {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    todo_ids: Vec<i32>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/labels/{id}/assign",
+    params(("id" = i32, Path, description = "Label id")),
+    request_body = AssignLabel,
+    responses(
+        (status = 200, description = "Number of newly labeled todos as `{assigned}`"),
+        (status = 400, description = "Invalid payload"),
+        (status = 404, description = "Label or todo not found"),
+    )
+)]
+pub async fn assign_label<T: TodoRepository + ?Sized>(
+    IdPath(label_id): IdPath<i32>,
+    ValidatedJson(payload): ValidatedJson<AssignLabel>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let assigned = repository
+        .assign_label_many(owner_id.as_deref(), label_id, payload.todo_ids)
+        .await?;
+    events.publish_bulk(owner_id, BulkAction::AssignLabel, assigned);
+    Ok((StatusCode::OK, Json(json!({ "assigned": assigned }))))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ReorderTodos {
+    /// Every todo id, in the new order.
+    ids: Vec<i32>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/reorder",
+    request_body = ReorderTodos,
+    responses(
+        (status = 204, description = "Positions rewritten"),
+        (status = 422, description = "Ids do not match the existing todos"),
+    )
+)]
+pub async fn reorder_todos<T: TodoRepository + ?Sized>(
+    ValidatedJson(payload): ValidatedJson<ReorderTodos>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<StatusCode, HandlerError> {
+    let count = payload.ids.len() as u64;
+    repository.reorder(owner_id.as_deref(), payload.ids).await?;
+    events.publish_bulk(owner_id, BulkAction::Reorder, count);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UpdateTodoOptions {
+    /// How `label_ids` is applied: `replace` (default), `add` or `remove`.
+    #[serde(default)]
+    #[param(value_type = Option<LabelMode>)]
+    label_mode: LabelMode,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/todos/{id}",
+    params(("id" = i32, Path, description = "Todo id"), UpdateTodoOptions),
+    request_body = UpdateTodo,
+    responses(
+        (status = 200, description = "Todo updated", body = TodoEntity),
+        (status = 400, description = "Invalid payload"),
+        (status = 404, description = "Todo not found"),
+        (status = 412, description = "`version` does not match the stored todo"),
+        (status = 422, description = "Unknown parent todo, or the parent is a subtask of this todo"),
+    )
+)]
+pub async fn update_todo<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    CheckedQuery(options): CheckedQuery<UpdateTodoOptions>,
+    ValidatedJson(payload): ValidatedJson<UpdateTodo>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let payload = payload.with_label_mode(options.label_mode);
+    let todo = repository.update(owner_id.as_deref(), id, payload).await?;
+    events.publish(TodoEvent::Updated { todo: todo.clone() });
     Ok((StatusCode::OK, Json(todo)))
 }
 
-pub async fn all_todo<T: TodoRepository>(
+#[utoipa::path(
+    put,
+    path = "/todos/{id}",
+    params(("id" = i32, Path, description = "Todo id")),
+    request_body = ReplaceTodo,
+    responses(
+        (status = 200, description = "Todo replaced", body = TodoEntity),
+        (status = 400, description = "Invalid payload"),
+        (status = 404, description = "Todo not found"),
+        (status = 422, description = "Unknown label ids"),
+    )
+)]
+pub async fn replace_todo<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    ValidatedJson(payload): ValidatedJson<ReplaceTodo>,
+    Owner(owner_id): Owner,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repository.all().await.unwrap();
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository.replace(owner_id.as_deref(), id, payload).await?;
+    events.publish(TodoEvent::Updated { todo: todo.clone() });
     Ok((StatusCode::OK, Json(todo)))
 }
 
-pub async fn update_todo<T: TodoRepository>(
-    Path(id): Path<i32>,
-    ValidatedJson(payload): ValidatedJson<UpdateTodo>,
+#[utoipa::path(
+    patch,
+    path = "/todos/{id}/status",
+    params(("id" = i32, Path, description = "Todo id")),
+    request_body = UpdateTodoStatus,
+    responses(
+        (status = 200, description = "Completion updated, other fields are kept", body = TodoEntity),
+        (status = 400, description = "Invalid payload"),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn update_todo_status<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    ValidatedJson(payload): ValidatedJson<UpdateTodoStatus>,
+    Owner(owner_id): Owner,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
     let todo = repository
-        .update(id, payload)
-        .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+        .update(owner_id.as_deref(), id, payload.into())
+        .await?;
+    events.publish(TodoEvent::Updated { todo: todo.clone() });
     Ok((StatusCode::OK, Json(todo)))
 }
 
-pub async fn delete_todo<T: TodoRepository>(
-    Path(id): Path<i32>,
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/toggle",
+    params(("id" = i32, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Completion flipped", body = TodoEntity),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn toggle_complete<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Owner(owner_id): Owner,
     Extension(repository): Extension<Arc<T>>,
-) -> StatusCode {
-    repository
-        .delete(id)
-        .await
-        .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::NOT_FOUND)
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository.toggle(owner_id.as_deref(), id).await?;
+    events.publish(TodoEvent::Updated { todo: todo.clone() });
+    Ok((StatusCode::OK, Json(todo)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/duplicate",
+    params(("id" = i32, Path, description = "Todo id")),
+    responses(
+        (status = 201, description = "Open copy of the todo created", body = TodoEntity),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn duplicate_todo<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository.duplicate(owner_id.as_deref(), id).await?;
+    events.publish(TodoEvent::Created { todo: todo.clone() });
+    let location = format!("/todos/{}", todo.id);
+
+    Ok((StatusCode::CREATED, [(LOCATION, location)], Json(todo)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/{id}/labels",
+    params(("id" = i32, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Labels of the todo", body = [Label]),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn find_todo_labels<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository.find(owner_id.as_deref(), id).await?;
+    Ok((StatusCode::OK, Json(todo.labels)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/{id}/children",
+    params(("id" = i32, Path, description = "Todo id"), JsonFormat),
+    responses(
+        (status = 200, description = "Direct subtasks of the todo", body = [TodoEntity]),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn find_todo_children<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    CheckedQuery(format): CheckedQuery<JsonFormat>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todos = repository.children(owner_id.as_deref(), id).await?;
+    Ok((StatusCode::OK, format.json(todos)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/labels/{label_id}",
+    params(
+        ("id" = i32, Path, description = "Todo id"),
+        ("label_id" = i32, Path, description = "Label id"),
+    ),
+    responses(
+        (status = 200, description = "Label attached", body = TodoEntity),
+        (status = 404, description = "Todo or label not found"),
+    )
+)]
+pub async fn add_todo_label<T: TodoRepository + ?Sized>(
+    IdPath((id, label_id)): IdPath<(i32, i32)>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository
+        .add_label(owner_id.as_deref(), id, label_id)
+        .await?;
+    events.publish(TodoEvent::Updated { todo: todo.clone() });
+    Ok((StatusCode::OK, Json(todo)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/todos/{id}/labels/{label_id}",
+    params(
+        ("id" = i32, Path, description = "Todo id"),
+        ("label_id" = i32, Path, description = "Label id"),
+    ),
+    responses(
+        (status = 200, description = "Label detached", body = TodoEntity),
+        (status = 404, description = "Todo or label not found"),
+    )
+)]
+pub async fn remove_todo_label<T: TodoRepository + ?Sized>(
+    IdPath((id, label_id)): IdPath<(i32, i32)>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository
+        .remove_label(owner_id.as_deref(), id, label_id)
+        .await?;
+    events.publish(TodoEvent::Updated { todo: todo.clone() });
+    Ok((StatusCode::OK, Json(todo)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/todos/{id}",
+    params(("id" = i32, Path, description = "Todo id")),
+    responses(
+        (status = 204, description = "Todo deleted"),
+        (status = 404, description = "Todo not found"),
+    )
+)]
+pub async fn delete_todo<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<StatusCode, HandlerError> {
+    repository.delete(owner_id.as_deref(), id).await?;
+    events.publish(TodoEvent::Deleted {
+        todo: DeletedTodo { id, owner_id },
+    });
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/complete-all",
+    responses(
+        (status = 200, description = "Number of todos that got completed as `{updated}`"),
+    )
+)]
+pub async fn complete_all_todo<T: TodoRepository + ?Sized>(
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let updated = repository
+        .set_all_completed(owner_id.as_deref(), true)
+        .await?;
+    events.publish_bulk(owner_id, BulkAction::CompleteAll, updated);
+    Ok((StatusCode::OK, Json(json!({ "updated": updated }))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/incomplete-all",
+    responses(
+        (status = 200, description = "Number of todos that got reopened as `{updated}`"),
+    )
+)]
+pub async fn incomplete_all_todo<T: TodoRepository + ?Sized>(
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let updated = repository
+        .set_all_completed(owner_id.as_deref(), false)
+        .await?;
+    events.publish_bulk(owner_id, BulkAction::IncompleteAll, updated);
+    Ok((StatusCode::OK, Json(json!({ "updated": updated }))))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/todos/completed",
+    responses(
+        (status = 200, description = "Number of deleted todos as `{deleted}`"),
+    )
+)]
+pub async fn delete_completed_todo<T: TodoRepository + ?Sized>(
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let deleted = repository.delete_completed(owner_id.as_deref()).await?;
+    events.publish_bulk(owner_id, BulkAction::DeleteCompleted, deleted);
+    Ok((StatusCode::OK, Json(json!({ "deleted": deleted }))))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PurgeQuery {
+    /// Must be `yes`.
+    confirm: Option<String>,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/todos/all",
+    params(PurgeQuery),
+    responses(
+        (status = 200, description = "Number of removed todos as `{deleted}`, deleted ones included"),
+        (status = 400, description = "`confirm=yes` is missing"),
+        (status = 403, description = "Disabled unless `ALLOW_DESTRUCTIVE=true`"),
+    )
+)]
+pub async fn purge_todos<T: TodoRepository + ?Sized>(
+    CheckedQuery(query): CheckedQuery<PurgeQuery>,
+    Owner(owner_id): Owner,
+    Extension(AllowDestructive(allowed)): Extension<AllowDestructive>,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<Response, HandlerError> {
+    if !allowed {
+        return Ok(error_response(
+            StatusCode::FORBIDDEN,
+            json!({ "error": "destructive_disabled" }),
+        ));
+    }
+    if query.confirm.as_deref() != Some("yes") {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            json!({ "error": "confirmation_required" }),
+        ));
+    }
+    let deleted = repository.purge(owner_id.as_deref()).await?;
+    tracing::warn!(?owner_id, "purged {} todos", deleted);
+    events.publish_bulk(owner_id, BulkAction::Purge, deleted);
+    Ok((StatusCode::OK, Json(json!({ "deleted": deleted }))).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/restore",
+    params(("id" = i32, Path, description = "Todo id")),
+    responses(
+        (status = 200, description = "Todo restored", body = TodoEntity),
+        (status = 404, description = "Deleted todo not found"),
+    )
+)]
+pub async fn restore_todo<T: TodoRepository + ?Sized>(
+    IdPath(id): IdPath<i32>,
+    Owner(owner_id): Owner,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(events): Extension<TodoEvents>,
+) -> Result<impl IntoResponse, HandlerError> {
+    let todo = repository.restore(owner_id.as_deref(), id).await?;
+    events.publish(TodoEvent::Created { todo: todo.clone() });
+    Ok((StatusCode::OK, Json(todo)))
 }
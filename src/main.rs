@@ -1,93 +1,404 @@
+mod auth;
+mod config;
 mod handlers;
+mod metrics;
+mod migrations;
 mod repositories;
+mod request_id;
+mod routes;
+mod seed;
+mod shutdown;
 
+use crate::auth::authenticate;
+use crate::config::{AppConfig, HttpConfig};
+use crate::metrics::{track_metrics, Metrics};
+use crate::migrations::run_migrations;
 use crate::repositories::{
+    health::{HealthRepository, HealthRepositoryForDb},
     label::{LabelRepository, LabelRepositoryForDb},
     todo::{TodoRepository, TodoRepositoryForDb},
 };
+use crate::request_id::{make_span, propagate_request_id, X_REQUEST_ID};
+use crate::routes::RouteTable;
+use crate::seed::seed;
+use crate::shutdown::{drain, Drain, InFlight};
 use axum::{
-    extract::Extension,
-    routing::{delete, get, post},
-    Router,
+    body::Body, error_handling::HandleErrorLayer, extract::Extension, http::Method, middleware,
+    Json, Router,
 };
 use dotenv::dotenv;
 use handlers::{
-    label::{all_label, create_label, delete_label},
-    todo::{all_todo, create_todo, delete_todo, find_todo, update_todo},
+    events::{todo_events, TodoEvents},
+    handle_timeout,
+    health::health,
+    label::{
+        all_label, archive_label, create_label, delete_label, find_label, label_stats,
+        label_todo_count, merge_labels, suggest_labels, update_label,
+    },
+    metrics::render_metrics,
+    negotiate_error_format,
+    openapi::openapi_json,
+    todo::{
+        add_todo_label, all_todo, assign_label, complete_all_todo, count_todo, create_todo,
+        create_todos, delete_completed_todo, delete_todo, delete_todos, duplicate_todo,
+        export_todos, find_todo, find_todo_children, find_todo_labels, find_todos,
+        find_todos_by_label, grouped_todo, import_todos, incomplete_all_todo, open_count_todo,
+        purge_todos, remove_todo_label, reorder_todos, replace_todo, restore_todo, todo_stats,
+        toggle_complete, update_todo, update_todo_status, AllowDestructive, IdempotencyTtl,
+        X_PAGE_LIMIT, X_PAGE_OFFSET, X_TOTAL_COUNT,
+    },
+    IDEMPOTENCY_KEY,
 };
-use hyper::header::CONTENT_TYPE;
-use std::{env, net::SocketAddr, sync::Arc};
-use tower_http::cors::{Any, CorsLayer, Origin};
+use http_body::Limited;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde_json::{json, Value};
+use std::{env, sync::Arc};
+use tokio::sync::Notify;
+use tower::{timeout::TimeoutLayer, ServiceBuilder};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    cors::{Any, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    trace::{DefaultOnResponse, TraceLayer},
+    LatencyUnit,
+};
+use tracing::Level;
 
 #[tokio::main]
 async fn main() {
+    dotenv().ok();
+    let config = AppConfig::from_env().unwrap_or_else(|e| panic!("{}", e));
+
     // logging
-    let log_level = env::var("RUST_LOG").unwrap_or("info".to_string());
-    env::set_var("RUST_LOG", log_level);
+    env::set_var("RUST_LOG", &config.log_level);
     tracing_subscriber::fmt::init();
-    dotenv().ok();
 
-    let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-    tracing::debug!("start connecting to {}...", database_url);
-    let pool = sqlx::PgPool::connect(database_url).await.expect(&format!(
-        "failed to connect to database, url: {}",
-        database_url
-    ));
+    tracing::info!(
+        "database pool: max_connections={}, acquire_timeout={:?}",
+        config.pool.max_connections,
+        config.pool.acquire_timeout
+    );
+    tracing::debug!("start connecting to {}...", config.database_url);
+    let pool = config
+        .pool
+        .options()
+        .connect(&config.database_url)
+        .await
+        .unwrap_or_else(|_| {
+            panic!(
+                "failed to connect to database, url: {}",
+                config.database_url
+            )
+        });
+
+    if config.run_migrations {
+        run_migrations(&pool)
+            .await
+            .expect("failed to run database migrations");
+    }
+
+    let todo_repository = TodoRepositoryForDb::new(pool.clone())
+        .with_slow_query(config.pool.slow_query)
+        .with_retry(config.pool.retry);
+    let label_repository = LabelRepositoryForDb::new(pool.clone()).with_retry(config.pool.retry);
+    if config.seed_on_start {
+        seed(&todo_repository, &label_repository)
+            .await
+            .expect("failed to seed example data");
+    }
 
+    let in_flight = InFlight::default();
     let app = create_app(
-        TodoRepositoryForDb::new(pool.clone()),
-        LabelRepositoryForDb::new(pool.clone()),
-    );
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    tracing::debug!("listening on {}", addr);
+        todo_repository,
+        label_repository,
+        HealthRepositoryForDb::new(pool.clone()),
+        config.http,
+    )
+    .layer(Extension(Arc::new(Metrics::new())))
+    .layer(middleware::from_fn({
+        let in_flight = in_flight.clone();
+        move |req, next| in_flight.clone().track(req, next)
+    }));
+    for (method, path) in registered_routes() {
+        tracing::info!(%method, path, "route registered");
+    }
+    tracing::debug!("listening on {}", config.bind_addr);
 
-    axum::Server::bind(&addr)
+    let draining = Arc::new(Notify::new());
+    let server = axum::Server::bind(&config.bind_addr)
         .serve(app.into_make_service())
-        .await
-        .unwrap();
+        .with_graceful_shutdown({
+            let draining = draining.clone();
+            async move {
+                shutdown_signal().await;
+                draining.notify_one();
+            }
+        });
+    let drained = drain(
+        server,
+        draining.notified(),
+        config.shutdown_timeout,
+        &in_flight,
+    )
+    .await
+    .unwrap();
+    if drained == Drain::Completed {
+        tracing::info!("shutdown completed");
+    }
+}
+
+/// Resolves once the process receives Ctrl+C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("signal received, starting graceful shutdown");
 }
 
-fn create_app<Todo: TodoRepository, Label: LabelRepository>(
+fn create_app<Todo: TodoRepository, Label: LabelRepository, Health: HealthRepository>(
     todo_repository: Todo,
     label_repository: Label,
+    health_repository: Health,
+    http: HttpConfig,
+) -> Router {
+    create_app_dyn(
+        Arc::new(todo_repository),
+        Arc::new(label_repository),
+        Arc::new(health_repository),
+        TodoEvents::default(),
+        http,
+    )
+}
+
+/// Same as `create_app`, for repositories that are only chosen at runtime.
+fn create_app_dyn(
+    todo_repository: Arc<dyn TodoRepository>,
+    label_repository: Arc<dyn LabelRepository>,
+    health_repository: Arc<dyn HealthRepository>,
+    events: TodoEvents,
+    http: HttpConfig,
 ) -> Router {
-    Router::new()
-        .route("/", get(root))
-        .route("/todos", post(create_todo::<Todo>).get(all_todo::<Todo>))
+    let mut api = api_routes().into_router();
+    if let Some(secret) = http.jwt_secret.clone() {
+        api = api.route_layer(middleware::from_fn(move |req, next| {
+            authenticate(secret.clone(), req, next)
+        }));
+    }
+
+    let mut app = public_routes()
+        .into_router()
+        .merge(api)
+        .layer(Extension(todo_repository))
+        .layer(Extension(label_repository))
+        .layer(Extension(health_repository))
+        .layer(Extension(events))
+        .layer(Extension(IdempotencyTtl(http.idempotency_ttl)))
+        .layer(Extension(AllowDestructive(http.allow_destructive)))
+        // below cors and tracing so that only the handler work is limited
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_timeout))
+                .layer(TimeoutLayer::new(http.request_timeout)),
+        )
+        .layer(middleware::from_fn(negotiate_error_format))
+        .layer(middleware::from_fn(track_metrics))
+        .layer(RequestBodyLimitLayer::new(http.body_limit));
+    // inside cors, so that preflight responses and cors headers are left alone
+    if let Some(min_size) = http.compression_min_size {
+        app = app.layer(
+            CompressionLayer::new().compress_when(
+                SizeAbove::new(min_size)
+                    .and(NotForContentType::GRPC)
+                    .and(NotForContentType::IMAGES),
+            ),
+        );
+    }
+    app.layer(
+        http.cors_origins.apply(
+            CorsLayer::new()
+                .allow_methods(Any)
+                .allow_headers(vec![
+                    AUTHORIZATION,
+                    CONTENT_TYPE,
+                    IDEMPOTENCY_KEY,
+                    X_REQUEST_ID,
+                ])
+                .expose_headers(vec![
+                    X_TOTAL_COUNT,
+                    X_PAGE_LIMIT,
+                    X_PAGE_OFFSET,
+                    X_REQUEST_ID,
+                ]),
+        ),
+    )
+    .layer(
+        TraceLayer::new_for_http()
+            .make_span_with(make_span)
+            .on_response(
+                DefaultOnResponse::new()
+                    .level(Level::INFO)
+                    .latency_unit(LatencyUnit::Millis),
+            ),
+    )
+    // outermost, so that the trace span and error responses carry the id as well
+    .layer(middleware::from_fn(propagate_request_id))
+}
+
+/// Request body seen by the handlers, below `RequestBodyLimitLayer`.
+type LimitedBody = Limited<Body>;
+
+/// Routes open to everyone, even when auth is enabled.
+fn public_routes() -> RouteTable<LimitedBody> {
+    type Health = dyn HealthRepository;
+
+    RouteTable::new()
+        .route(Method::GET, "/", root)
+        .route(Method::GET, "/health", health::<Health>)
+        .route(Method::GET, "/api-docs/openapi.json", openapi_json)
+        .route(Method::GET, "/metrics", render_metrics)
+}
+
+/// Routes requiring a bearer token when `AUTH_ENABLED=true`.
+fn api_routes() -> RouteTable<LimitedBody> {
+    type Todo = dyn TodoRepository;
+    type Label = dyn LabelRepository;
+
+    RouteTable::new()
+        .route(Method::POST, "/todos", create_todo::<Todo>)
+        .route(Method::GET, "/todos", all_todo::<Todo>)
+        .route(Method::POST, "/todos/batch", create_todos::<Todo>)
+        .route(Method::POST, "/todos/batch-get", find_todos::<Todo>)
+        .route(Method::POST, "/todos/batch-delete", delete_todos::<Todo>)
+        .route(Method::POST, "/todos/import", import_todos::<Todo>)
+        .route(Method::GET, "/todos/count", count_todo::<Todo>)
+        .route(Method::GET, "/todos/open-count", open_count_todo::<Todo>)
+        .route(Method::GET, "/stats", todo_stats::<Todo>)
+        .route(Method::GET, "/todos/export", export_todos::<Todo>)
+        .route(Method::GET, "/todos/grouped", grouped_todo::<Todo>)
         .route(
-            "/todos/:id",
-            get(find_todo::<Todo>)
-                .delete(delete_todo::<Todo>)
-                .patch(update_todo::<Todo>),
+            Method::POST,
+            "/todos/suggest-labels",
+            suggest_labels::<Label>,
         )
+        .route(Method::POST, "/todos/reorder", reorder_todos::<Todo>)
         .route(
-            "/labels",
-            post(create_label::<Label>).get(all_label::<Label>),
+            Method::POST,
+            "/todos/complete-all",
+            complete_all_todo::<Todo>,
         )
-        .route("/labels/:id", delete(delete_label::<Label>))
-        .layer(Extension(Arc::new(todo_repository)))
-        .layer(Extension(Arc::new(label_repository)))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Origin::exact("http://localhost:3001".parse().unwrap()))
-                .allow_methods(Any)
-                .allow_headers(vec![CONTENT_TYPE]),
+        .route(
+            Method::POST,
+            "/todos/incomplete-all",
+            incomplete_all_todo::<Todo>,
+        )
+        .route(
+            Method::DELETE,
+            "/todos/completed",
+            delete_completed_todo::<Todo>,
+        )
+        .route(Method::DELETE, "/todos/all", purge_todos::<Todo>)
+        .route(Method::GET, "/todos/:id", find_todo::<Todo>)
+        .route(Method::DELETE, "/todos/:id", delete_todo::<Todo>)
+        .route(Method::PATCH, "/todos/:id", update_todo::<Todo>)
+        .route(Method::PUT, "/todos/:id", replace_todo::<Todo>)
+        .route(Method::GET, "/ws", todo_events)
+        .route(Method::POST, "/todos/:id/restore", restore_todo::<Todo>)
+        .route(
+            Method::PATCH,
+            "/todos/:id/status",
+            update_todo_status::<Todo>,
+        )
+        .route(Method::POST, "/todos/:id/toggle", toggle_complete::<Todo>)
+        .route(Method::POST, "/todos/:id/duplicate", duplicate_todo::<Todo>)
+        .route(
+            Method::GET,
+            "/todos/:id/children",
+            find_todo_children::<Todo>,
+        )
+        .route(Method::GET, "/todos/:id/labels", find_todo_labels::<Todo>)
+        .route(
+            Method::POST,
+            "/todos/:id/labels/:label_id",
+            add_todo_label::<Todo>,
+        )
+        .route(
+            Method::DELETE,
+            "/todos/:id/labels/:label_id",
+            remove_todo_label::<Todo>,
         )
+        .route(Method::POST, "/labels", create_label::<Label>)
+        .route(Method::GET, "/labels", all_label::<Label>)
+        .route(Method::GET, "/labels/stats", label_stats::<Label>)
+        .route(Method::POST, "/labels/merge", merge_labels::<Label>)
+        .route(Method::GET, "/labels/:id", find_label::<Label>)
+        .route(Method::DELETE, "/labels/:id", delete_label::<Label>)
+        .route(Method::PATCH, "/labels/:id", update_label::<Label>)
+        .route(Method::GET, "/labels/:id/count", label_todo_count::<Label>)
+        .route(Method::POST, "/labels/:id/archive", archive_label::<Label>)
+        .route(
+            Method::GET,
+            "/labels/:id/todos",
+            find_todos_by_label::<Todo>,
+        )
+        .route(Method::POST, "/labels/:id/assign", assign_label::<Todo>)
+}
+
+/// Method and path of every route served by `create_app`, in registration order.
+fn registered_routes() -> Vec<(Method, &'static str)> {
+    public_routes().merge(api_routes()).routes().to_vec()
 }
 
-async fn root() -> &'static str {
-    "Hello, World!"
+/// Identifies the running build, `GIT_COMMIT` is picked up at compile time when set.
+async fn root() -> Json<Value> {
+    Json(json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": option_env!("GIT_COMMIT"),
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        repositories::label::{test_utils::LabelRepositoryForMemory, Label},
-        repositories::todo::{test_utils::TodoRepositoryForMemory, CreateTodo, TodoEntity},
+        auth::{
+            test_utils::{claims, sign_token},
+            JwtSecret,
+        },
+        config::CorsOrigins,
+        repositories::health::test_utils::HealthRepositoryForMemory,
+        repositories::label::{
+            test_utils::LabelRepositoryForMemory, CreateLabel, Label, LabelWithCount,
+        },
+        repositories::todo::{
+            test_utils::TodoRepositoryForMemory, CreateTodo, Priority, TodoEntity, UpdateTodo,
+        },
     };
 
     use super::*;
+    use crate::handlers::events::{BulkAction, DeletedTodo, TodoEvent};
     use axum::{body::Body, http::Request, response::Response};
+    use chrono::{DateTime, Utc};
     use hyper::{header, Method, StatusCode};
     use tower::ServiceExt;
 
@@ -100,6 +411,18 @@ mod tests {
             .unwrap()
     }
 
+    fn build_req_with_form(path: &str, method: Method, form_body: String) -> Request<Body> {
+        Request::builder()
+            .uri(path)
+            .method(method)
+            .header(
+                header::CONTENT_TYPE,
+                mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+            )
+            .body(Body::from(form_body))
+            .unwrap()
+    }
+
     fn build_req_with_empty(path: &str, method: Method) -> Request<Body> {
         Request::builder()
             .uri(path)
@@ -112,15 +435,23 @@ mod tests {
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body = String::from_utf8(bytes.to_vec()).unwrap();
         let todo = serde_json::from_str(&body)
-            .expect(&format!("failed to convert Todo instance. body: {}", body));
+            .unwrap_or_else(|_| panic!("failed to convert Todo instance. body: {}", body));
         todo
     }
 
+    async fn res_to_todos(res: Response) -> Vec<TodoEntity> {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        let todos = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("failed to convert Todo instances. body: {}", body));
+        todos
+    }
+
     async fn res_to_label(res: Response) -> Label {
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body = String::from_utf8(bytes.to_vec()).unwrap();
         let label = serde_json::from_str(&body)
-            .expect(&format!("failed to convert Label instance. body: {}", body));
+            .unwrap_or_else(|_| panic!("failed to convert Label instance. body: {}", body));
         label
     }
 
@@ -133,7 +464,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn should_return_hello_world() {
+    async fn should_return_service_metadata() {
         let (labels, _) = label_fixture();
         let todo_repository = TodoRepositoryForMemory::new(labels);
         let label_repository = LabelRepositoryForMemory::new();
@@ -141,175 +472,3724 @@ mod tests {
             .uri("/")
             .body(hyper::Body::empty())
             .unwrap();
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            mime::APPLICATION_JSON.as_ref()
+        );
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["name"], "my-todo");
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body.get("commit").is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shutdown_signal_resolves_on_sigterm() {
+        let handle = tokio::spawn(shutdown_signal());
+        // give the spawned task time to install its handlers
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        std::process::Command::new("kill")
+            .args(["-TERM", &std::process::id().to_string()])
+            .status()
+            .expect("failed to send SIGTERM");
+        tokio::time::timeout(std::time::Duration::from_secs(5), handle)
             .await
+            .expect("shutdown signal did not resolve")
             .unwrap();
-        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-        let body = String::from_utf8(bytes.to_vec()).unwrap();
-        assert_eq!(body, "Hello, World!");
     }
 
     #[tokio::test]
-    async fn should_created_todo() {
-        let (labels, _) = label_fixture();
-        let expected = TodoEntity::new(1, "some todo text".to_string(), labels.clone());
+    async fn should_require_bearer_token_when_auth_enabled() {
+        let secret = JwtSecret::new("test-secret".to_string());
+        let build_app = |jwt_secret: Option<JwtSecret>| {
+            create_app(
+                TodoRepositoryForMemory::new(vec![]),
+                LabelRepositoryForMemory::new(),
+                HealthRepositoryForMemory::new(),
+                HttpConfig {
+                    jwt_secret,
+                    ..HttpConfig::default()
+                },
+            )
+        };
+        let build_req = |token: Option<&str>| {
+            let mut req = Request::builder().uri("/todos");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        let app = build_app(Some(secret.clone()));
 
-        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
-        let label_repository = LabelRepositoryForMemory::new();
-        let req = build_req_with_json(
+        let token = sign_token(&secret, &claims("alice"));
+        let res = app.clone().oneshot(build_req(Some(&token))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let other_secret = JwtSecret::new("other-secret".to_string());
+        let forged = sign_token(&other_secret, &claims("alice"));
+        for token in [Some(forged.as_str()), Some("garbage"), None] {
+            let res = app.clone().oneshot(build_req(token)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        }
+
+        // health checks stay reachable for probes
+        let req = build_req_with_empty("/health", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = build_app(None).oneshot(build_req(None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn should_hide_todos_of_other_owners() {
+        let secret = JwtSecret::new("test-secret".to_string());
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig {
+                jwt_secret: Some(secret.clone()),
+                ..HttpConfig::default()
+            },
+        );
+        let build_req = |uri: &str, method: Method, token: &str, body: Body| {
+            Request::builder()
+                .uri(uri)
+                .method(method)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(body)
+                .unwrap()
+        };
+        let alice = sign_token(&secret, &claims("alice"));
+        let bob = sign_token(&secret, &claims("bob"));
+
+        let req = build_req(
             "/todos",
             Method::POST,
-            r#"{"text":"some todo text", "label_ids":[1,2]}"#.to_string(),
+            &alice,
+            Body::from(r#"{ "text": "alice's todo" }"#),
         );
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
-            .await
-            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
         assert_eq!(res.status(), StatusCode::CREATED);
-
         let todo = res_to_todo(res).await;
-        assert_eq!(todo, expected);
+
+        let req = build_req("/todos", Method::GET, &bob, Body::empty());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_todos(res).await.is_empty());
+
+        // other owners get 404 rather than 403, so ids do not leak
+        let uri = format!("/todos/{}", todo.id);
+        for method in [Method::GET, Method::DELETE] {
+            let req = build_req(&uri, method, &bob, Body::empty());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        let req = build_req(&uri, Method::GET, &alice, Body::empty());
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await, todo);
     }
 
     #[tokio::test]
-    async fn should_find_todo() {
-        let (labels, label_ids) = label_fixture();
-        let expected = TodoEntity::new(1, "some todo text".to_string(), labels.clone());
+    async fn should_echo_request_id() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
 
-        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
-        let label_repository = LabelRepositoryForMemory::new();
-        todo_repository
-            .create(CreateTodo::new("some todo text".to_string(), label_ids))
-            .await
-            .expect("failed to create todo");
-        let req = build_req_with_empty("/todos/1", Method::GET);
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
-            .await
+        let req = Request::builder()
+            .uri("/todos")
+            .header(X_REQUEST_ID, "client-chosen-id")
+            .body(Body::empty())
             .unwrap();
-        let todo = res_to_todo(res).await;
-        assert_eq!(todo, expected);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[X_REQUEST_ID], "client-chosen-id");
+
+        // also on responses that never reach a handler
+        let req = build_req_with_empty("/todos/abc", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let generated = res.headers()[X_REQUEST_ID].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
     }
 
     #[tokio::test]
-    async fn should_get_all_todos() {
-        let (labels, label_ids) = label_fixture();
-
-        let expected = TodoEntity::new(1, "some todo text".to_string(), labels.clone());
+    async fn should_negotiate_error_format() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let build_req = |accept: &str| {
+            Request::builder()
+                .uri("/todos/1")
+                .header(header::ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap()
+        };
 
-        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
-        let label_repository = LabelRepositoryForMemory::new();
-        todo_repository
-            .create(CreateTodo::new("some todo text".to_string(), label_ids))
-            .await
-            .expect("failed to create todo");
-        let req = build_req_with_empty("/todos", Method::GET);
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
+        let res = app
+            .clone()
+            .oneshot(build_req("application/json"))
             .await
             .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(
+            res_to_json(res).await,
+            json!({ "error": "not_found", "id": 1 })
+        );
+
+        let res = app.clone().oneshot(build_req("text/plain")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-        let body = String::from_utf8(bytes.to_vec()).unwrap();
-        let todo: Vec<TodoEntity> = serde_json::from_str(&body)
-            .expect(&format!("failed to convert Todo instance. body: {}", body));
-        assert_eq!(todo, vec![expected]);
+        assert_eq!(&bytes[..], b"error: not_found\nid: 1\n");
+
+        // JSON stays the default unless plain text is preferred
+        for accept in ["*/*", "text/plain;q=0.5, application/json"] {
+            let res = app.clone().oneshot(build_req(accept)).await.unwrap();
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+        }
     }
 
     #[tokio::test]
-    async fn should_update_todo() {
-        let (labels, label_ids) = label_fixture();
-        let expected = TodoEntity::new(1, "updated todo text".to_string(), labels.clone());
+    async fn should_negotiate_rejection_format() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let plain_text = |mut req: Request<Body>| {
+            req.headers_mut()
+                .insert(header::ACCEPT, "text/plain".parse().unwrap());
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                assert_eq!(
+                    res.headers()[header::CONTENT_TYPE],
+                    "text/plain; charset=utf-8"
+                );
+                let status = res.status();
+                let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                (status, String::from_utf8(bytes.to_vec()).unwrap())
+            }
+        };
 
-        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
-        let label_repository = LabelRepositoryForMemory::new();
-        todo_repository
-            .create(CreateTodo::new("some todo text".to_string(), label_ids))
-            .await
-            .expect("failed to create todo");
         let req = build_req_with_json(
-            "/todos/1",
-            Method::PATCH,
-            r#"{"text":"updated todo text"}"#.to_string(),
+            "/todos",
+            Method::POST,
+            r#"{"text":"", "label_ids":[]}"#.to_string(),
         );
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
-            .await
-            .unwrap();
-        let todo = res_to_todo(res).await;
-        assert_eq!(todo, expected);
+        assert_eq!(
+            plain_text(req).await,
+            (
+                StatusCode::BAD_REQUEST,
+                "errors: {\"text\":[\"Can not be empty\"]}\n".to_string()
+            )
+        );
+
+        let req = build_req_with_empty("/todos/abc", Method::GET);
+        assert_eq!(
+            plain_text(req).await,
+            (StatusCode::BAD_REQUEST, "error: invalid_id\n".to_string())
+        );
+
+        let req = build_req_with_empty("/todos?completed=maybe", Method::GET);
+        let (status, body) = plain_text(req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.starts_with("error: invalid_query\n"));
     }
 
     #[tokio::test]
-    async fn should_delete_todo() {
-        let (labels, label_ids) = label_fixture();
-        let todo_repository = TodoRepositoryForMemory::new(labels);
-        let label_repository = LabelRepositoryForMemory::new();
-        todo_repository
-            .create(CreateTodo::new("some todo text".to_string(), label_ids))
+    async fn should_reject_malformed_queries_as_json() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        for (method, uri) in [
+            (Method::GET, "/todos?sort=bogus"),
+            (Method::GET, "/todos?limit=abc"),
+            (Method::GET, "/todos?pretty=maybe"),
+            (Method::GET, "/todos/grouped?pretty=maybe"),
+            (Method::GET, "/todos/export?format=xml"),
+            (Method::GET, "/labels?limit=abc"),
+            (Method::GET, "/labels/stats?pretty=maybe"),
+            (Method::DELETE, "/labels/1?force=x"),
+        ] {
+            let req = build_req_with_empty(uri, method);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(res_to_json(res).await["error"], "invalid_query", "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_allow_configured_cors_origin() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig {
+                cors_origins: CorsOrigins::parse("https://todo.example").unwrap(),
+                ..HttpConfig::default()
+            },
+        );
+        let build_req = |origin: &str| {
+            Request::builder()
+                .uri("/todos")
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(build_req("https://todo.example"))
             .await
-            .expect("failed to create todo");
-        let req = build_req_with_empty("/todos/1", Method::DELETE);
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
+            .unwrap();
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://todo.example"
+        );
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            "x-total-count,x-page-limit,x-page-offset,x-request-id"
+        );
+
+        let res = app
+            .oneshot(build_req("http://localhost:3001"))
             .await
             .unwrap();
-        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(res
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
     }
 
     #[tokio::test]
-    async fn should_create_label() {
+    async fn should_compress_large_responses() {
         let todo_repository = TodoRepositoryForMemory::new(vec![]);
-        let label_repository = LabelRepositoryForMemory::new();
-        let req = build_req_with_json(
-            "/labels",
-            Method::POST,
-            r#"{"name":"some label text"}"#.to_string(),
+        for i in 0..50 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let build_req = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
         );
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
-            .await
-            .unwrap();
-        assert_eq!(res.status(), StatusCode::CREATED);
 
-        let label = res_to_label(res).await;
-        assert_eq!(label.name, "some label text");
+        let res = app.clone().oneshot(build_req("/todos")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+
+        // a single todo is below the threshold
+        let res = app.oneshot(build_req("/todos/1")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig {
+                compression_min_size: None,
+                ..HttpConfig::default()
+            },
+        );
+        let res = app.oneshot(build_req("/todos")).await.unwrap();
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    #[test]
+    fn should_list_registered_routes() {
+        let expected = vec![
+            (Method::GET, "/"),
+            (Method::GET, "/health"),
+            (Method::GET, "/api-docs/openapi.json"),
+            (Method::GET, "/metrics"),
+            (Method::POST, "/todos"),
+            (Method::GET, "/todos"),
+            (Method::POST, "/todos/batch"),
+            (Method::POST, "/todos/batch-get"),
+            (Method::POST, "/todos/batch-delete"),
+            (Method::POST, "/todos/import"),
+            (Method::GET, "/todos/count"),
+            (Method::GET, "/todos/open-count"),
+            (Method::GET, "/stats"),
+            (Method::GET, "/todos/export"),
+            (Method::GET, "/todos/grouped"),
+            (Method::POST, "/todos/suggest-labels"),
+            (Method::POST, "/todos/reorder"),
+            (Method::POST, "/todos/complete-all"),
+            (Method::POST, "/todos/incomplete-all"),
+            (Method::DELETE, "/todos/completed"),
+            (Method::DELETE, "/todos/all"),
+            (Method::GET, "/todos/:id"),
+            (Method::DELETE, "/todos/:id"),
+            (Method::PATCH, "/todos/:id"),
+            (Method::PUT, "/todos/:id"),
+            (Method::GET, "/ws"),
+            (Method::POST, "/todos/:id/restore"),
+            (Method::PATCH, "/todos/:id/status"),
+            (Method::POST, "/todos/:id/toggle"),
+            (Method::POST, "/todos/:id/duplicate"),
+            (Method::GET, "/todos/:id/children"),
+            (Method::GET, "/todos/:id/labels"),
+            (Method::POST, "/todos/:id/labels/:label_id"),
+            (Method::DELETE, "/todos/:id/labels/:label_id"),
+            (Method::POST, "/labels"),
+            (Method::GET, "/labels"),
+            (Method::GET, "/labels/stats"),
+            (Method::POST, "/labels/merge"),
+            (Method::GET, "/labels/:id"),
+            (Method::DELETE, "/labels/:id"),
+            (Method::PATCH, "/labels/:id"),
+            (Method::GET, "/labels/:id/count"),
+            (Method::POST, "/labels/:id/archive"),
+            (Method::GET, "/labels/:id/todos"),
+            (Method::POST, "/labels/:id/assign"),
+        ];
+        assert_eq!(registered_routes(), expected);
     }
 
     #[tokio::test]
-    async fn should_get_all_labels() {
-        let todo_repository = TodoRepositoryForMemory::new(vec![]);
-        let label_repository = LabelRepositoryForMemory::new();
-        label_repository
-            .create("some label text".to_string())
-            .await
-            .expect("failed to create label");
-        let req = build_req_with_empty("/labels", Method::GET);
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
-            .await
-            .unwrap();
+    async fn should_serve_openapi_spec() {
+        let req = build_req_with_empty("/api-docs/openapi.json", Method::GET);
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let spec = res_to_json(res).await;
+        let todos = &spec["paths"]["/todos"];
+        assert!(todos["get"].is_object());
+        assert!(todos["post"]["responses"]["201"].is_object());
+        assert!(spec["paths"]["/todos/{id}"]["get"]["responses"]["404"].is_object());
+        assert!(spec["paths"]["/labels"]["post"]["responses"]["409"].is_object());
+        assert!(spec["components"]["schemas"]["TodoEntity"].is_object());
+    }
+
+    #[tokio::test]
+    async fn should_count_requests_in_metrics() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .layer(Extension(Arc::new(Metrics::new())));
+
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = build_req_with_empty("/metrics", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body = String::from_utf8(bytes.to_vec()).unwrap();
-        let label_vec: Vec<Label> = serde_json::from_str(&body)
-            .expect(&format!("failed to convert Label instance. body: {}", body));
-        assert_eq!(label_vec.len(), 1);
-        assert_eq!(label_vec[0].name, "some label text");
+        // the scrape itself is recorded after the body has been rendered
+        assert!(body.contains("http_requests_total 1\n"));
+        assert!(body.contains(r#"http_requests_by_status_total{class="4xx"} 1"#));
+        assert!(body
+            .contains(r#"http_request_duration_seconds_count{method="GET",route="/todos/:id"} 1"#));
     }
 
     #[tokio::test]
-    async fn should_delete_label() {
-        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+    async fn should_return_ok_when_healthy() {
+        let req = build_req_with_empty("/health", Method::GET);
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({ "status": "ok" }));
+    }
+
+    #[tokio::test]
+    async fn should_return_service_unavailable_when_db_is_down() {
+        let req = build_req_with_empty("/health", Method::GET);
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::unavailable(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({ "status": "db_unavailable" }));
+    }
+
+    #[tokio::test]
+    async fn should_time_out_slow_request() {
+        let req = build_req_with_empty("/health", Method::GET);
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::slow(std::time::Duration::from_secs(5)),
+            HttpConfig {
+                request_timeout: std::time::Duration::from_millis(50),
+                ..HttpConfig::default()
+            },
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({ "error": "request_timeout" }));
+    }
+
+    #[tokio::test]
+    async fn should_created_todo() {
+        let (labels, _) = label_fixture();
+        let expected = TodoEntity::new(1, "some todo text".to_string(), labels.clone());
+
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
         let label_repository = LabelRepositoryForMemory::new();
-        label_repository
-            .create("some label text".to_string())
-            .await
-            .expect("failed to create label");
-        let req = build_req_with_empty("/labels/1", Method::DELETE);
-        let res = create_app(todo_repository, label_repository)
-            .oneshot(req)
-            .await
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"some todo text", "label_ids":[1,2]}"#.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()[header::LOCATION], "/todos/1");
+
+        let todo = res_to_todo(res).await;
+        assert_eq!(todo, expected);
+    }
+
+    #[tokio::test]
+    async fn should_serve_dynamically_dispatched_repositories() {
+        let (labels, _) = label_fixture();
+        let todo_repository: Arc<dyn TodoRepository> =
+            Arc::new(TodoRepositoryForMemory::new(labels.clone()));
+        let label_repository: Arc<dyn LabelRepository> = Arc::new(LabelRepositoryForMemory::new());
+        let health_repository: Arc<dyn HealthRepository> =
+            Arc::new(HealthRepositoryForMemory::new());
+        let app = create_app_dyn(
+            todo_repository.clone(),
+            label_repository,
+            health_repository,
+            TodoEvents::default(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"some todo text", "label_ids":[1]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = build_req_with_json("/labels", Method::POST, r#"{"name":"dyn"}"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = build_req_with_empty("/health", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // the app shares the repository handed in
+        let todo = todo_repository.find(None, 1).await.unwrap();
+        assert_eq!(
+            todo,
+            TodoEntity::new(1, "some todo text".to_string(), vec![labels[0].clone()])
+        );
+    }
+
+    #[tokio::test]
+    async fn should_publish_todo_events() {
+        let events = TodoEvents::default();
+        let mut subscriber = events.subscribe();
+        let app = create_app_dyn(
+            Arc::new(TodoRepositoryForMemory::new(vec![])),
+            Arc::new(LabelRepositoryForMemory::new()),
+            Arc::new(HealthRepositoryForMemory::new()),
+            events,
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "some todo text" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(
+            subscriber.try_recv().unwrap(),
+            TodoEvent::Created { todo: todo.clone() }
+        );
+
+        // bulk endpoints publish one event, and none when nothing changed
+        for _ in 0..2 {
+            let req = build_req_with_empty("/todos/complete-all", Method::POST);
+            app.clone().oneshot(req).await.unwrap();
+        }
+        assert_eq!(
+            subscriber.try_recv().unwrap(),
+            TodoEvent::Bulk {
+                action: BulkAction::CompleteAll,
+                count: 1,
+                owner_id: None
+            }
+        );
+
+        let req = build_req_with_empty(&format!("/todos/{}", todo.id), Method::DELETE);
+        app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            subscriber.try_recv().unwrap(),
+            TodoEvent::Deleted {
+                todo: DeletedTodo {
+                    id: todo.id,
+                    owner_id: None
+                }
+            }
+        );
+        assert!(subscriber.try_recv().is_err());
+
+        // a plain GET is not a handshake
+        let req = build_req_with_empty("/ws", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res_to_json(res).await["error"], "invalid_websocket_upgrade");
+    }
+
+    #[tokio::test]
+    async fn should_create_todo_once_per_idempotency_key() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let mut req = build_req_with_json(
+                "/todos",
+                Method::POST,
+                r#"{"text":"retried todo", "label_ids":[]}"#.to_string(),
+            );
+            req.headers_mut()
+                .insert("Idempotency-Key", "0d5a3c1e".parse().unwrap());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            ids.push(res_to_todo(res).await.id);
+        }
+        assert_eq!(ids, vec![1, 1]);
+        assert_eq!(todo_repository.todos().len(), 1);
+
+        for key in ["", "0d5a 3c1e", "0d5a3c1e\t"] {
+            let mut req = build_req_with_json(
+                "/todos",
+                Method::POST,
+                r#"{"text":"retried todo", "label_ids":[]}"#.to_string(),
+            );
+            req.headers_mut()
+                .insert("Idempotency-Key", key.parse().unwrap());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(todo_repository.todos().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_create_todo_from_form() {
+        let req = build_req_with_form(
+            "/todos",
+            Method::POST,
+            "text=from+form&priority=high".to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let todo = res_to_todo(res).await;
+        assert_eq!(todo.text, "from form");
+        assert_eq!(todo.priority, Priority::High);
+        assert!(todo.labels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_create_todos_in_batch() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::new();
+        let req = build_req_with_json(
+            "/todos/batch",
+            Method::POST,
+            r#"[{"text":"first todo", "label_ids":[1]}, {"text":"second todo", "label_ids":[1,2]}]"#
+                .to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let todos = res_to_todos(res).await;
+        assert_eq!(
+            todos,
+            vec![
+                TodoEntity::new(1, "first todo".to_string(), vec![labels[0].clone()]),
+                TodoEntity::new(2, "second todo".to_string(), labels),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_batch_with_invalid_todo() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/todos/batch",
+            Method::POST,
+            r#"[{"text":"valid todo", "label_ids":[]}, {"text":"", "label_ids":[]}]"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({
+                "error": "invalid_item",
+                "index": 1,
+                "errors": { "text": ["Can not be empty"] },
+            })
+        );
+
+        let req = build_req_with_json(
+            "/todos/batch",
+            Method::POST,
+            r#"[{"text":"valid todo", "label_ids":[]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res_to_json(res).await["error"], "invalid_json");
+
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert!(res_to_todos(res).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_import_todos_sharing_new_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/todos/import",
+            Method::POST,
+            r#"[
+                {"text":"buy milk", "label_names":["shopping"]},
+                {"text":"buy bread", "completed":true, "label_names":["Shopping"]}
+            ]"#
+            .to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "created": 2, "labels_created": 1 })
+        );
+
+        let mut todos = todo_repository.todos();
+        todos.sort_by_key(|todo| todo.id);
+        assert_eq!(todos.len(), 2);
+        assert!(todos[1].completed);
+        assert_eq!(todos[0].labels, vec![Label::new(1, "shopping".to_string())]);
+        assert_eq!(todos[0].labels, todos[1].labels);
+    }
+
+    #[tokio::test]
+    async fn should_reject_import_with_invalid_item() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        for (body, index) in [
+            (r#"[{"text":"valid"}, {"text":""}]"#, 1),
+            (r#"[{"completed":true}, {"text":"valid"}]"#, 0),
+        ] {
+            let req = build_req_with_json("/todos/import", Method::POST, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body = res_to_json(res).await;
+            assert_eq!(body["error"], "invalid_item");
+            assert_eq!(body["index"], index);
+        }
+        assert!(todo_repository.todos().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_find_todos_in_batch_skipping_unknown_ids() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for text in ["first todo", "second todo", "third todo"] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let req = build_req_with_json(
+            "/todos/batch-get",
+            Method::POST,
+            r#"{"ids":[3, 1, 99]}"#.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut ids: Vec<i32> = res_to_todos(res).await.iter().map(|todo| todo.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn should_reject_body_over_limit() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig {
+                body_limit: 32,
+                ..HttpConfig::default()
+            },
+        );
+        let body = format!(r#"{{"text":"{}","label_ids":[]}}"#, "a".repeat(64));
+
+        let req = build_req_with_json("/todos", Method::POST, body.clone());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut req = build_req_with_json("/todos", Method::POST, body.clone());
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, body.len().into());
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn should_find_todo() {
+        let (labels, label_ids) = label_fixture();
+        let expected = TodoEntity::new(1, "some todo text".to_string(), labels.clone());
+
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(todo, expected);
+    }
+
+    #[tokio::test]
+    async fn should_return_not_found_json_for_missing_todo() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let requests = vec![
+            build_req_with_empty("/todos/42", Method::GET),
+            build_req_with_json(
+                "/todos/42",
+                Method::PATCH,
+                r#"{"completed":true}"#.to_string(),
+            ),
+            build_req_with_empty("/todos/42", Method::DELETE),
+        ];
+        for req in requests {
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            assert_eq!(
+                res_to_json(res).await,
+                serde_json::json!({ "error": "not_found", "id": 42 })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_path_id() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        for (uri, method) in [
+            ("/todos/not-a-number", Method::GET),
+            ("/todos/1.5", Method::DELETE),
+            ("/todos/1/labels/abc", Method::POST),
+            ("/labels/abc", Method::GET),
+            ("/labels/abc/todos", Method::GET),
+        ] {
+            let req = build_req_with_empty(uri, method);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(
+                res_to_json(res).await,
+                serde_json::json!({ "error": "invalid_id" })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn should_honor_if_none_match() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(None, CreateTodo::new("some todo text".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let build_req = |etag: &str| {
+            Request::builder()
+                .uri("/todos/1")
+                .header(header::IF_NONE_MATCH, etag)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+
+        // matching etag
+        let res = app.clone().oneshot(build_req(&etag)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], etag.as_str());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(bytes.is_empty());
+
+        // stale etag after an update
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"completed":true}"#.to_string(),
+        );
+        app.clone().oneshot(req).await.unwrap();
+        let res = app.oneshot(build_req(&etag)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(res.headers()[header::ETAG], etag.as_str());
+        assert!(res_to_todo(res).await.completed);
+    }
+
+    #[tokio::test]
+    async fn should_get_all_todos() {
+        let (labels, label_ids) = label_fixture();
+
+        let expected = TodoEntity::new(1, "some todo text".to_string(), labels.clone());
+
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        let todo: Vec<TodoEntity> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("failed to convert Todo instance. body: {}", body));
+        assert_eq!(todo, vec![expected]);
+    }
+
+    #[tokio::test]
+    async fn should_filter_todos_by_completed() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(None, CreateTodo::new("open todo".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        todo_repository
+            .create(None, CreateTodo::new("completed todo".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        todo_repository
+            .update(None, 2, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("failed to update todo");
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos?completed=true", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].text, "completed todo");
+        assert!(todos[0].completed);
+
+        let req = build_req_with_empty("/todos?completed=false", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].text, "open todo");
+        assert!(!todos[0].completed);
+
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(todos.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_search_todos_by_text() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(None, CreateTodo::new("Buy Milk".to_string(), label_ids))
+            .await
+            .expect("failed to create todo");
+        todo_repository
+            .create(None, CreateTodo::new("Walk the dog".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos?q=mil", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(
+            todos,
+            vec![TodoEntity::new(1, "Buy Milk".to_string(), labels)]
+        );
+
+        let req = build_req_with_empty("/todos?q=", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(todos.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_reject_past_due_date() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"late todo", "label_ids":[], "due_date":"2000-01-01T00:00:00Z"}"#
+                .to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    async fn res_to_json(res: Response) -> serde_json::Value {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).expect("failed to parse response body as json")
+    }
+
+    #[tokio::test]
+    async fn should_return_validation_errors_as_json() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("should_update_todo".to_string(), vec![]),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"", "label_ids":[]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "errors": { "text": ["Can not be empty"] } })
+        );
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            format!(r#"{{"text":"{}"}}"#, "a".repeat(101)),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "errors": { "text": ["Text is too long"] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_count_characters_not_bytes() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        // 400 bytes, but 100 characters
+        let text = "🎉".repeat(100);
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            format!(r#"{{"text":"{}", "label_ids":[]}}"#, text),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res_to_todo(res).await.text, text);
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            format!(r#"{{"text":"{}", "label_ids":[]}}"#, "🎉".repeat(101)),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "errors": { "text": ["Text is too long"] } })
+        );
+
+        let name = "日本語".repeat(6);
+        let req = build_req_with_json("/labels", Method::POST, format!(r#"{{"name":"{}"}}"#, name));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res_to_label(res).await.name, name);
+
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            format!(r#"{{"name":"{}"}}"#, "日本語".repeat(7)),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "errors": { "name": ["Name is too long"] } })
+        );
+
+        // imported label names are bound the same way
+        let family = "👨‍👩‍👧";
+        let req = build_req_with_json(
+            "/todos/import",
+            Method::POST,
+            format!(
+                r#"[{{"text":"imported", "label_names":["{}"]}}]"#,
+                family.repeat(20)
+            ),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let req = build_req_with_json(
+            "/todos/import",
+            Method::POST,
+            format!(
+                r#"[{{"text":"imported", "label_names":["{}"]}}]"#,
+                family.repeat(21)
+            ),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res_to_json(res).await["error"], "invalid_item");
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_label_ids() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"labeled todo", "label_ids":[1, 99, 2, 42]}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "error": "unknown_labels", "ids": [42, 99] })
+        );
+        assert!(todo_repository.todos().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_reject_too_many_labels() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let label_ids: Vec<i32> = (1..=11).collect();
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            serde_json::json!({ "text": "labeled todo", "label_ids": label_ids }).to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "errors": { "label_ids": ["Can not have more than 10 labels"] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_set_keep_and_clear_due_date() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"due todo", "label_ids":[], "due_date":"2999-01-01T00:00:00Z"}"#.to_string(),
+        );
+        app.clone().oneshot(req).await.unwrap();
+        let due_date = |value: &str| Some(value.parse::<DateTime<Utc>>().unwrap());
+
+        // omitted
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"text":"renamed"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todo(res).await.due_date,
+            due_date("2999-01-01T00:00:00Z")
+        );
+
+        // set
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"due_date":"2999-12-31T00:00:00Z"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todo(res).await.due_date,
+            due_date("2999-12-31T00:00:00Z")
+        );
+
+        // cleared
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"due_date":null}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res_to_todo(res).await.due_date, None);
+    }
+
+    #[tokio::test]
+    async fn should_filter_overdue_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let payloads = [
+            r#"{"text":"overdue", "label_ids":[], "due_date":"2000-01-01T00:00:00Z", "allow_past_due":true}"#,
+            r#"{"text":"upcoming", "label_ids":[], "due_date":"2999-01-01T00:00:00Z"}"#,
+            r#"{"text":"no due date", "label_ids":[]}"#,
+        ];
+        for payload in payloads {
+            let req = build_req_with_json("/todos", Method::POST, payload.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+
+        let req = build_req_with_empty("/todos?overdue=true", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].text, "overdue");
+    }
+
+    #[tokio::test]
+    async fn should_summarize_todos() {
+        let (labels, _) = label_fixture();
+        let app = create_app(
+            TodoRepositoryForMemory::new(labels),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let payloads = [
+            r#"{"text":"overdue", "label_ids":[1, 2], "due_date":"2000-01-01T00:00:00Z", "allow_past_due":true}"#,
+            r#"{"text":"done late", "label_ids":[1], "due_date":"2000-01-01T00:00:00Z", "allow_past_due":true}"#,
+            r#"{"text":"upcoming", "label_ids":[], "due_date":"2999-01-01T00:00:00Z"}"#,
+            r#"{"text":"done", "label_ids":[]}"#,
+        ];
+        for payload in payloads {
+            let req = build_req_with_json("/todos", Method::POST, payload.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+        for id in [2, 4] {
+            let req = build_req_with_empty(&format!("/todos/{}/toggle", id), Method::POST);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let req = build_req_with_empty("/stats", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res_to_json(res).await,
+            json!({ "total": 4, "completed": 2, "open": 2, "overdue": 1, "labels": 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_sort_todos_by_priority() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let payloads = [
+            r#"{"text":"high", "label_ids":[], "priority":"high"}"#,
+            r#"{"text":"low", "label_ids":[], "priority":"low"}"#,
+            r#"{"text":"medium", "label_ids":[]}"#,
+        ];
+        for payload in payloads {
+            let req = build_req_with_json("/todos", Method::POST, payload.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+
+        let req = build_req_with_empty("/todos?sort=priority", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        let priorities: Vec<Priority> = todos.iter().map(|todo| todo.priority).collect();
+        assert_eq!(
+            priorities,
+            vec![Priority::High, Priority::Medium, Priority::Low]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_sort_todos_by_text() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for text in ["banana", "cherry", "apple"] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos?sort=text", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let texts: Vec<String> = res_to_todos(res)
+            .await
+            .into_iter()
+            .map(|todo| todo.text)
+            .collect();
+        assert_eq!(texts, vec!["apple", "banana", "cherry"]);
+
+        let req = build_req_with_empty("/todos?sort=-text", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let texts: Vec<String> = res_to_todos(res)
+            .await
+            .into_iter()
+            .map(|todo| todo.text)
+            .collect();
+        assert_eq!(texts, vec!["cherry", "banana", "apple"]);
+
+        let req = build_req_with_empty("/todos?sort=created_at", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let ids: Vec<i32> = res_to_todos(res).await.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let req = build_req_with_empty("/todos?sort=name", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert!(res.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn should_reorder_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for text in ["first", "second", "third"] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/reorder",
+            Method::POST,
+            r#"{"ids":[3,1,2]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = build_req_with_empty("/todos?sort=position", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let texts: Vec<String> = res_to_todos(res)
+            .await
+            .into_iter()
+            .map(|todo| todo.text)
+            .collect();
+        assert_eq!(texts, vec!["third", "first", "second"]);
+
+        for ids in ["[3,1]", "[3,1,2,4]", "[3,1,1]"] {
+            let req = build_req_with_json(
+                "/todos/reorder",
+                Method::POST,
+                format!(r#"{{"ids":{}}}"#, ids),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_export_todos_as_csv() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        todo_repository
+            .create(None, CreateTodo::new("milk, eggs".to_string(), label_ids))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/export?format=csv", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/csv; charset=utf-8"
+        );
+        assert_eq!(
+            res.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"todos.csv\""
+        );
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let csv = String::from_utf8(bytes.to_vec()).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            rows,
+            vec![
+                "id,text,completed,labels",
+                r#"1,"milk, eggs",false,"label_1,label_2""#,
+            ]
+        );
+
+        let req = build_req_with_empty("/todos/export", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res_to_todos(res).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_count_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository
+            .toggle(None, 2)
+            .await
+            .expect("failed to complete todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/count", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "count": 3 }));
+
+        let req = build_req_with_empty("/todos/count?completed=false", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "count": 2 }));
+    }
+
+    #[tokio::test]
+    async fn should_group_todos_by_completion() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        for i in 1..=3 {
+            todo_repository
+                .create(
+                    None,
+                    CreateTodo::new(format!("todo {}", i), label_ids.clone()),
+                )
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository
+            .toggle(None, 2)
+            .await
+            .expect("failed to complete todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/grouped", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_to_json(res).await;
+        let active: Vec<TodoEntity> = serde_json::from_value(body["active"].clone()).unwrap();
+        let completed: Vec<TodoEntity> = serde_json::from_value(body["completed"].clone()).unwrap();
+        assert_eq!(
+            active,
+            vec![
+                TodoEntity::new(3, "todo 3".to_string(), labels.clone()),
+                TodoEntity::new(1, "todo 1".to_string(), labels.clone()),
+            ]
+        );
+        assert!(completed[0].completed_at.is_some());
+        assert_eq!(
+            completed,
+            vec![TodoEntity {
+                completed: true,
+                completed_at: completed[0].completed_at,
+                version: 2,
+                ..TodoEntity::new(2, "todo 2".to_string(), labels)
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_group_todos_beyond_page_limit() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=30 {
+            let todo = todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+            if i % 3 == 0 {
+                todo_repository
+                    .toggle(None, todo.id)
+                    .await
+                    .expect("failed to complete todo");
+            }
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/grouped", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let body = res_to_json(res).await;
+        assert_eq!(body["active"].as_array().unwrap().len(), 20);
+        assert_eq!(body["completed"].as_array().unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn should_paginate_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        for i in 1..=3 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos?limit=2", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, vec![3, 2]);
+
+        let req = build_req_with_empty("/todos?limit=2&offset=2", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, vec![1]);
+
+        let req = build_req_with_empty("/todos?offset=3", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert!(todos.is_empty());
+
+        let req = build_req_with_empty("/todos?limit=1&with_total=true", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["todos"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_set_pagination_headers() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=5 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository.toggle(None, 5).await.unwrap();
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let headers = |res: &Response| -> Vec<String> {
+            [X_TOTAL_COUNT, X_PAGE_LIMIT, X_PAGE_OFFSET]
+                .iter()
+                .map(|name| res.headers()[name].to_str().unwrap().to_string())
+                .collect()
+        };
+
+        let req = build_req_with_empty("/todos?limit=2&offset=1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(headers(&res), vec!["5", "2", "1"]);
+        assert_eq!(res_to_todos(res).await.len(), 2);
+
+        // the total follows the filter, limit and offset fall back to their defaults
+        let req = build_req_with_empty("/todos?completed=false", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(headers(&res), vec!["4", "20", "0"]);
+    }
+
+    #[tokio::test]
+    async fn should_walk_todos_with_cursor() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=5 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let mut ids = vec![];
+        let mut uri = "/todos?with_cursor=true&limit=2".to_string();
+        let mut pages = 0;
+        loop {
+            let req = build_req_with_empty(&uri, Method::GET);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = res_to_json(res).await;
+            let todos: Vec<TodoEntity> = serde_json::from_value(body["todos"].clone()).unwrap();
+            ids.extend(todos.iter().map(|todo| todo.id));
+            pages += 1;
+            match body["next_cursor"].as_i64() {
+                Some(cursor) => uri = format!("/todos?with_cursor=true&limit=2&after={}", cursor),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(ids, vec![5, 4, 3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn should_update_todo() {
+        let (labels, label_ids) = label_fixture();
+        let expected = TodoEntity {
+            version: 2,
+            ..TodoEntity::new(1, "updated todo text".to_string(), labels.clone())
+        };
+
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"text":"updated todo text"}"#.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(todo, expected);
+    }
+
+    #[tokio::test]
+    async fn should_reject_update_with_stale_version() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(None, CreateTodo::new("some todo text".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"text":"first edit", "version":1}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await.version, 2);
+
+        // a second client still holding version 1
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{"text":"second edit", "version":1}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "error": "version_conflict", "version": 2 })
+        );
+
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res_to_todo(res).await.text, "first edit");
+    }
+
+    #[tokio::test]
+    async fn should_delete_todo() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let req = build_req_with_empty("/todos/1", Method::DELETE);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn should_replace_todo() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        todo_repository
+            .create(None, CreateTodo::new("some todo text".to_string(), vec![1]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PUT,
+            r#"{"text":"replaced", "completed":true, "label_ids":[2]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let todo = res_to_todo(res).await;
+        assert!(todo.completed_at.is_some());
+        assert_eq!(
+            todo,
+            TodoEntity {
+                completed: true,
+                completed_at: todo.completed_at,
+                version: 2,
+                ..TodoEntity::new(1, "replaced".to_string(), vec![labels[1].clone()])
+            }
+        );
+
+        // every required field must be sent, unlike PATCH
+        for body in [
+            r#"{"completed":false, "label_ids":[]}"#,
+            r#"{"text":"replaced", "label_ids":[]}"#,
+            r#"{"text":"replaced", "completed":false}"#,
+        ] {
+            let req = build_req_with_json("/todos/1", Method::PUT, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "body: {}", body);
+        }
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PUT,
+            r#"{"text":"", "completed":false, "label_ids":[]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = build_req_with_json(
+            "/todos/2",
+            Method::PUT,
+            r#"{"text":"replaced", "completed":false, "label_ids":[]}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_toggle_todo() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("should_toggle_todo".to_string(), vec![]),
+            )
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let expected = TodoEntity::new(1, "should_toggle_todo".to_string(), vec![]);
+
+        let req = build_req_with_empty("/todos/1/toggle", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let todo = res_to_todo(res).await;
+        assert!(todo.completed_at.is_some());
+        assert_eq!(
+            todo,
+            TodoEntity {
+                completed: true,
+                completed_at: todo.completed_at,
+                version: 2,
+                ..expected.clone()
+            }
+        );
+
+        let req = build_req_with_empty("/todos/1/toggle", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todo(res).await,
+            TodoEntity {
+                version: 3,
+                ..expected
+            }
+        );
+
+        let req = build_req_with_empty("/todos/2/toggle", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_update_todo_status() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("should_update_todo_status".to_string(), vec![]),
+            )
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let expected = TodoEntity::new(1, "should_update_todo_status".to_string(), vec![]);
+
+        let req = build_req_with_json(
+            "/todos/1/status",
+            Method::PATCH,
+            r#"{"completed":true}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let todo = res_to_todo(res).await;
+        assert!(todo.completed_at.is_some());
+        assert_eq!(
+            todo,
+            TodoEntity {
+                completed: true,
+                completed_at: todo.completed_at,
+                version: 2,
+                ..expected.clone()
+            }
+        );
+
+        let req = build_req_with_json(
+            "/todos/1/status",
+            Method::PATCH,
+            r#"{"completed":false}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todo(res).await,
+            TodoEntity {
+                version: 3,
+                ..expected
+            }
+        );
+
+        // only the completed flag is accepted
+        for body in [r#"{}"#, r#"{"completed":true, "text":"changed"}"#] {
+            let req = build_req_with_json("/todos/1/status", Method::PATCH, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "body: {}", body);
+        }
+
+        let req = build_req_with_json(
+            "/todos/2/status",
+            Method::PATCH,
+            r#"{"completed":true}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_duplicate_todo() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        todo_repository
+            .create(None, CreateTodo::new("labeled todo".to_string(), label_ids))
+            .await
+            .expect("failed to create todo");
+        let source = todo_repository
+            .toggle(None, 1)
+            .await
+            .expect("failed to complete todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/1/duplicate", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()[header::LOCATION], "/todos/2");
+        let copy = res_to_todo(res).await;
+        assert_eq!(
+            copy,
+            TodoEntity {
+                id: 2,
+                completed: false,
+                completed_at: None,
+                position: copy.position,
+                version: 1,
+                ..source.clone()
+            }
+        );
+        assert_eq!(copy.labels, labels);
+
+        // the source is left as it was
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_todo(res).await, source);
+
+        let req = build_req_with_empty("/todos/99/duplicate", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_find_todo_labels() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        todo_repository
+            .create(None, CreateTodo::new("labeled todo".to_string(), label_ids))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/1/labels", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let found: Vec<Label> = serde_json::from_value(res_to_json(res).await).unwrap();
+        assert_eq!(found, labels);
+
+        let req = build_req_with_empty("/todos/99/labels", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_list_children_of_todo() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        for body in [
+            r#"{"text":"parent"}"#,
+            r#"{"text":"first child", "parent_id":1}"#,
+            r#"{"text":"second child", "parent_id":1}"#,
+            r#"{"text":"grandchild", "parent_id":2}"#,
+        ] {
+            let req = build_req_with_json("/todos", Method::POST, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+
+        let req = build_req_with_empty("/todos/1/children", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let children = res_to_todos(res).await;
+        let ids: Vec<i32> = children.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert!(children.iter().all(|todo| todo.parent_id == Some(1)));
+
+        let req = build_req_with_empty("/todos/4/children", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_todos(res).await.is_empty());
+
+        let req = build_req_with_empty("/todos/99/children", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"orphan", "parent_id":99}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "error": "unknown_parent", "parent_id": 99 })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_parent_cycle() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        for body in [
+            r#"{"text":"root"}"#,
+            r#"{"text":"child", "parent_id":1}"#,
+            r#"{"text":"grandchild", "parent_id":2}"#,
+        ] {
+            let req = build_req_with_json("/todos", Method::POST, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+
+        for (id, parent_id) in [(1, 3), (1, 1), (2, 3)] {
+            let req = build_req_with_json(
+                &format!("/todos/{}", id),
+                Method::PATCH,
+                format!(r#"{{"parent_id":{}}}"#, parent_id),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(
+                res_to_json(res).await,
+                serde_json::json!({ "error": "parent_cycle", "parent_id": parent_id })
+            );
+        }
+
+        // moving a subtree elsewhere and detaching are fine
+        let req = build_req_with_json("/todos/3", Method::PATCH, r#"{"parent_id":1}"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await.parent_id, Some(1));
+
+        let req = build_req_with_json(
+            "/todos/2",
+            Method::PATCH,
+            r#"{"parent_id":null}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await.parent_id, None);
+    }
+
+    #[tokio::test]
+    async fn should_add_and_remove_single_label() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        todo_repository
+            .create(None, CreateTodo::new("some todo text".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/1/labels/2", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await.labels, vec![labels[1].clone()]);
+
+        // adding the same label twice is a no-op
+        let req = build_req_with_empty("/todos/1/labels/2", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_todo(res).await.labels, vec![labels[1].clone()]);
+
+        let req = build_req_with_empty("/todos/1/labels/2", Method::DELETE);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res_to_todo(res).await.labels.is_empty());
+
+        let req = build_req_with_empty("/todos/1/labels/99", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = build_req_with_empty("/todos/99/labels/1", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_complete_and_reopen_all_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository.toggle(None, 2).await.unwrap();
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/complete-all", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "updated": 2 }));
+        let todos = todo_repository.todos();
+        assert_eq!(todos.len(), 3);
+        assert!(todos
+            .iter()
+            .all(|todo| todo.completed && todo.completed_at.is_some()));
+
+        let req = build_req_with_empty("/todos/incomplete-all", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "updated": 3 }));
+        assert!(todo_repository
+            .todos()
+            .iter()
+            .all(|todo| !todo.completed && todo.completed_at.is_none()));
+    }
+
+    #[tokio::test]
+    async fn should_delete_only_completed_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository.toggle(None, 1).await.unwrap();
+        todo_repository.toggle(None, 3).await.unwrap();
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/completed", Method::DELETE);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "deleted": 2 }));
+
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todos(res).await,
+            vec![TodoEntity::new(2, "todo 2".to_string(), vec![])]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_refuse_to_purge_todos_unless_allowed() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        todo_repository
+            .create(None, CreateTodo::new("kept".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/all?confirm=yes", Method::DELETE);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            res_to_json(res).await,
+            serde_json::json!({ "error": "destructive_disabled" })
+        );
+        assert_eq!(todo_repository.todos().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_purge_todos_with_their_labels() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        for text in ["first", "second"] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), label_ids.clone()))
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository
+            .create(
+                Some("someone else"),
+                CreateTodo::new("third".to_string(), vec![]),
+            )
+            .await
+            .expect("failed to create todo");
+        todo_repository.delete(None, 2).await.unwrap();
+        let app = create_app(
+            todo_repository.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig {
+                allow_destructive: true,
+                ..HttpConfig::default()
+            },
+        );
+
+        for uri in ["/todos/all", "/todos/all?confirm=no"] {
+            let req = build_req_with_empty(uri, Method::DELETE);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(
+                res_to_json(res).await,
+                serde_json::json!({ "error": "confirmation_required" })
+            );
+        }
+        assert_eq!(todo_repository.todos().len(), 2);
+
+        let req = build_req_with_empty("/todos/all?confirm=yes", Method::DELETE);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "deleted": 2 }));
+        // todos of other owners are left alone
+        let todos = todo_repository.todos();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].owner_id.as_deref(), Some("someone else"));
+
+        let req = build_req_with_empty("/labels/1/todos", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_todos(res).await.is_empty());
+
+        let req = build_req_with_empty("/todos/2/restore", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_pretty_print_todo_list_on_request() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=2 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let fetch = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(build_req_with_empty(uri, Method::GET))
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    res.headers()[header::CONTENT_TYPE],
+                    mime::APPLICATION_JSON.as_ref()
+                );
+                let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                String::from_utf8(bytes.to_vec()).unwrap()
+            }
+        };
+
+        let compact = fetch("/todos").await;
+        let pretty = fetch("/todos?pretty=true").await;
+        assert!(!compact.contains('\n'));
+        assert!(pretty.contains("\n  {\n    \"id\": 1,"));
+        assert_eq!(fetch("/todos?pretty=false").await, compact);
+        assert_eq!(
+            serde_json::from_str::<Value>(&compact).unwrap(),
+            serde_json::from_str::<Value>(&pretty).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn should_filter_todos_by_labels() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        for (text, label_ids) in [("both", vec![1, 2]), ("first", vec![1]), ("none", vec![])] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), label_ids))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let texts = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(build_req_with_empty(uri, Method::GET))
+                    .await
+                    .unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                let total = res.headers()[X_TOTAL_COUNT].to_str().unwrap().to_string();
+                let texts: Vec<String> = res_to_todos(res)
+                    .await
+                    .into_iter()
+                    .map(|todo| todo.text)
+                    .collect();
+                assert_eq!(total, texts.len().to_string());
+                texts
+            }
+        };
+
+        assert_eq!(texts("/todos?label_ids=1,2&match=all").await, vec!["both"]);
+        assert_eq!(
+            texts("/todos?label_ids=1,2&match=any").await,
+            vec!["first", "both"]
+        );
+        // any is the default
+        assert_eq!(texts("/todos?label_ids=2").await, vec!["both"]);
+        assert_eq!(texts("/todos?label_ids=").await.len(), 3);
+
+        let req = build_req_with_empty("/todos?label_ids=1,x", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert!(res.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn should_filter_todos_by_creation_date() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for day in 1..=3 {
+            let todo = todo_repository
+                .create(None, CreateTodo::new(format!("day {}", day), vec![]))
+                .await
+                .expect("failed to create todo");
+            let created_at = format!("2024-01-0{}T12:00:00Z", day).parse().unwrap();
+            todo_repository.set_created_at(todo.id, created_at);
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        // both bounds are inclusive
+        let req = build_req_with_empty(
+            "/todos?created_after=2024-01-01T12:00:00Z&created_before=2024-01-02T12:00:00%2B00:00",
+            Method::GET,
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let texts: Vec<String> = res_to_todos(res)
+            .await
+            .into_iter()
+            .map(|todo| todo.text)
+            .collect();
+        assert_eq!(texts, vec!["day 2", "day 1"]);
+
+        let req = build_req_with_empty(
+            "/todos/count?created_after=2024-01-02T00:00:00Z",
+            Method::GET,
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_json(res).await, json!({ "count": 2 }));
+
+        let req = build_req_with_empty("/todos?created_after=yesterday", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res_to_json(res).await["error"], "invalid_query");
+    }
+
+    #[tokio::test]
+    async fn should_return_sparse_fieldsets() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        todo_repository
+            .create(None, CreateTodo::new("labeled todo".to_string(), label_ids))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let get = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(build_req_with_empty(uri, Method::GET))
+                    .await
+                    .unwrap();
+                (res.status(), res_to_json(res).await)
+            }
+        };
+
+        let (status, todos) = get("/todos?fields=id,completed").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(todos, json!([{ "id": 1, "completed": false }]));
+        let (_, todo) = get("/todos/1?fields=id,completed").await;
+        assert_eq!(todo, json!({ "id": 1, "completed": false }));
+        let (_, page) = get("/todos?fields=id&with_total=true").await;
+        assert_eq!(page, json!({ "todos": [{ "id": 1 }], "total": 1 }));
+
+        // every field by default, and when all of them are listed
+        let (_, full) = get("/todos/1").await;
+        assert!(full.get("text").is_some() && full.get("labels").is_some());
+        let (_, listed) = get(
+            "/todos/1?fields=id,text,completed,completed_at,due_date,priority,position,version,parent_id,labels",
+        )
+        .await;
+        assert_eq!(listed, full);
+
+        let (status, body) = get("/todos?fields=id,secret").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            json!({ "error": "unknown_fields", "fields": ["secret"] })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_update_labels_by_mode() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        todo_repository
+            .create(None, CreateTodo::new("labeled todo".to_string(), vec![1]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let patch = |uri: &'static str, label_ids: &'static str| {
+            let app = app.clone();
+            async move {
+                let req = build_req_with_json(
+                    uri,
+                    Method::PATCH,
+                    format!(r#"{{"label_ids":{}}}"#, label_ids),
+                );
+                app.oneshot(req).await.unwrap()
+            }
+        };
+
+        let res = patch("/todos/1?label_mode=add", "[2]").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await.labels, labels);
+
+        let res = patch("/todos/1?label_mode=remove", "[1, 99]").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_todo(res).await.labels, vec![labels[1].clone()]);
+
+        // replace is the default
+        let res = patch("/todos/1", "[1]").await;
+        assert_eq!(res_to_todo(res).await.labels, vec![labels[0].clone()]);
+        let res = patch("/todos/1?label_mode=replace", "[2]").await;
+        assert_eq!(res_to_todo(res).await.labels, vec![labels[1].clone()]);
+
+        let res = patch("/todos/1?label_mode=add", "[99]").await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let res = patch("/todos/1?label_mode=merge", "[1]").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_count_open_todos() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        todo_repository.toggle(None, 2).await.unwrap();
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/open-count", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "open": 2 }));
+    }
+
+    #[tokio::test]
+    async fn should_delete_todos_by_id() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repository
+                .create(None, CreateTodo::new(format!("todo {}", i), vec![]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/batch-delete",
+            Method::POST,
+            r#"{ "ids": [1, 3, 42] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "deleted": 2 }));
+
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todos(res).await,
+            vec![TodoEntity::new(2, "todo 2".to_string(), vec![])]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_restore_deleted_todo() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        todo_repository
+            .create(None, CreateTodo::new("some todo text".to_string(), vec![]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/todos/1", Method::DELETE);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_todos(res).await.is_empty());
+
+        let req = build_req_with_empty("/todos/1/restore", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let todo = res_to_todo(res).await;
+        assert_eq!(
+            todo,
+            TodoEntity::new(1, "some todo text".to_string(), vec![])
+        );
+
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_todos(res).await, vec![todo]);
+
+        let req = build_req_with_empty("/todos/1/restore", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_create_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{"name":"some label text"}"#.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()[header::LOCATION], "/labels/1");
+
+        let label = res_to_label(res).await;
+        assert_eq!(label.name, "some label text");
+    }
+
+    #[tokio::test]
+    async fn should_create_label_from_json_or_form() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{"name":"from json"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res_to_label(res).await.name, "from json");
+
+        let req = build_req_with_form(
+            "/labels",
+            Method::POST,
+            "name=from+form&color=%23ff8800".to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let label = res_to_label(res).await;
+        assert_eq!(label.name, "from form");
+        assert_eq!(label.color, "#ff8800");
+
+        let req = build_req_with_form("/labels", Method::POST, "name=".to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = Request::builder()
+            .uri("/labels")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, mime::TEXT_PLAIN.as_ref())
+            .body(Body::from("name"))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_label_name() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        for (name, message) in [
+            (String::new(), "Can not be empty"),
+            ("a".repeat(21), "Name is too long"),
+        ] {
+            let req = build_req_with_json(
+                "/labels",
+                Method::POST,
+                serde_json::json!({ "name": name }).to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(
+                res_to_json(res).await,
+                serde_json::json!({ "errors": { "name": [message] } })
+            );
+        }
+
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            serde_json::json!({ "name": "a".repeat(20) }).to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn should_not_create_duplicate_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{"name":"some label text"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{"name":"some label text"}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({ "error": "duplicate", "id": 1 }));
+    }
+
+    #[tokio::test]
+    async fn should_normalize_whitespace_of_names() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json("/labels", Method::POST, r#"{"name":"Work"}"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = build_req_with_json("/labels", Method::POST, r#"{"name":" Work "}"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{"name":"  home \t office "}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res_to_label(res).await.name, "home office");
+
+        // blank once normalized
+        let req = build_req_with_json("/labels", Method::POST, r#"{"name":"   "}"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text":"  buy   milk "}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res_to_todo(res).await.text, "buy milk");
+    }
+
+    #[tokio::test]
+    async fn should_round_trip_label_color() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r##"{"name":"colored","color":"#ff8800"}"##.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res_to_label(res).await.color, "#ff8800");
+
+        let req = build_req_with_empty("/labels/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_label(res).await.color, "#ff8800");
+
+        let req = build_req_with_json(
+            "/labels/1",
+            Method::PATCH,
+            r##"{"name":"colored","color":"#00AA11"}"##.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_label(res).await.color, "#00AA11");
+
+        let req = build_req_with_json(
+            "/labels/1",
+            Method::PATCH,
+            r#"{"name":"renamed"}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        let label = res_to_label(res).await;
+        assert_eq!(label.name, "renamed");
+        assert_eq!(label.color, "#00AA11");
+    }
+
+    #[tokio::test]
+    async fn should_reject_malformed_label_color() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r##"{"name":"colored","color":"#12345g"}"##.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let body = res_to_json(res).await;
+        assert_eq!(
+            body,
+            serde_json::json!({ "errors": { "color": ["Color must be a #RRGGBB hex string"] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_find_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        label_repository
+            .create(CreateLabel::new("some label text".to_string()))
+            .await
+            .expect("failed to create label");
+        let req = build_req_with_empty("/labels/1", Method::GET);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let label = res_to_label(res).await;
+        assert_eq!(label, Label::new(1, "some label text".to_string()));
+    }
+
+    #[tokio::test]
+    async fn should_not_find_missing_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let req = build_req_with_empty("/labels/1", Method::GET);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_get_all_labels() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        label_repository
+            .create(CreateLabel::new("some label text".to_string()))
+            .await
+            .expect("failed to create label");
+        let req = build_req_with_empty("/labels", Method::GET);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        let label_vec: Vec<Label> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("failed to convert Label instance. body: {}", body));
+        assert_eq!(label_vec.len(), 1);
+        assert_eq!(label_vec[0].name, "some label text");
+    }
+
+    #[tokio::test]
+    async fn should_search_and_paginate_labels() {
+        let label_repository = LabelRepositoryForMemory::new();
+        for name in ["work", "home", "homework"] {
+            label_repository
+                .create(CreateLabel::new(name.to_string()))
+                .await
+                .expect("failed to create label");
+        }
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/labels?q=WORK&limit=1&offset=1", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let names: Vec<String> = serde_json::from_value::<Vec<Label>>(res_to_json(res).await)
+            .unwrap()
+            .into_iter()
+            .map(|label| label.name)
+            .collect();
+        assert_eq!(names, vec!["homework"]);
+    }
+
+    #[tokio::test]
+    async fn should_update_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        label_repository
+            .create(CreateLabel::new("some label text".to_string()))
+            .await
+            .expect("failed to create label");
+        let req = build_req_with_json(
+            "/labels/1",
+            Method::PATCH,
+            r#"{"name":"updated label"}"#.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let label = res_to_label(res).await;
+        assert_eq!(label, Label::new(1, "updated label".to_string()));
+    }
+
+    #[tokio::test]
+    async fn should_round_trip_label_description() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+        let send = |uri: &str, method: Method, body: &str| {
+            app.clone()
+                .oneshot(build_req_with_json(uri, method, body.to_string()))
+        };
+
+        let res = send(
+            "/labels",
+            Method::POST,
+            r#"{"name":"work","description":"paid work"}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let label = res_to_label(res).await;
+        assert_eq!(label.description.as_deref(), Some("paid work"));
+
+        let res = send("/labels", Method::POST, r#"{"name":"home"}"#)
+            .await
+            .unwrap();
+        let body = res_to_json(res).await;
+        assert_eq!(body["description"], Value::Null);
+
+        // omitted keeps the description, null clears it
+        let uri = format!("/labels/{}", label.id);
+        let res = send(&uri, Method::PATCH, r#"{"name":"job"}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            res_to_label(res).await.description.as_deref(),
+            Some("paid work")
+        );
+        let res = send(&uri, Method::PATCH, r#"{"name":"job","description":null}"#)
+            .await
             .unwrap();
+        assert_eq!(res_to_label(res).await.description, None);
+
+        let body = format!(r#"{{"name":"long","description":"{}"}}"#, "a".repeat(201));
+        let res = send("/labels", Method::POST, &body).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_not_update_missing_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        let req = build_req_with_json(
+            "/labels/1",
+            Method::PATCH,
+            r#"{"name":"updated label"}"#.to_string(),
+        );
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_get_label_stats() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        for text in ["first todo", "second todo"] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), vec![1]))
+                .await
+                .expect("failed to create todo");
+        }
+        let req = build_req_with_empty("/labels/stats", Method::GET);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let stats: Vec<LabelWithCount> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                LabelWithCount {
+                    label: labels[0].clone(),
+                    todo_count: 2,
+                },
+                LabelWithCount {
+                    label: labels[1].clone(),
+                    todo_count: 0,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_count_todos_of_label() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        for text in ["first todo", "second todo"] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), vec![1]))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/labels/1/count", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "count": 2 }));
+
+        let req = build_req_with_empty("/labels/99/count", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_count_label_todos_of_caller_only() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        for owner_id in ["alice", "alice", "bob"] {
+            todo_repository
+                .create(
+                    Some(owner_id),
+                    CreateTodo::new(format!("{}'s todo", owner_id), vec![1]),
+                )
+                .await
+                .expect("failed to create todo");
+        }
+        let secret = JwtSecret::new("test-secret".to_string());
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig {
+                jwt_secret: Some(secret.clone()),
+                ..HttpConfig::default()
+            },
+        );
+
+        for (owner_id, expected) in [("alice", 2), ("bob", 1), ("carol", 0)] {
+            let token = sign_token(&secret, &claims(owner_id));
+            let build_req = |uri: &str| {
+                Request::builder()
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(Body::empty())
+                    .unwrap()
+            };
+
+            let res = app
+                .clone()
+                .oneshot(build_req("/labels/1/count"))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_json(res).await,
+                serde_json::json!({ "count": expected })
+            );
+
+            let res = app
+                .clone()
+                .oneshot(build_req("/labels/stats"))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let stats: Vec<LabelWithCount> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(stats[0].todo_count, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_show_renamed_label_on_todos() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        todo_repository
+            .create(None, CreateTodo::new("some todo".to_string(), vec![1]))
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/labels/1",
+            Method::PATCH,
+            r#"{"name":"renamed"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let renamed = res_to_label(res).await;
+
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_todo(res).await.labels, vec![renamed.clone()]);
+        let req = build_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res_to_todos(res).await[0].labels, vec![renamed]);
+    }
+
+    #[tokio::test]
+    async fn should_find_todos_by_label() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        for (text, label_ids) in [
+            ("both labels", label_ids.clone()),
+            ("first label", vec![1]),
+            ("second label", vec![2]),
+        ] {
+            todo_repository
+                .create(None, CreateTodo::new(text.to_string(), label_ids))
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/labels/1/todos", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let todos = res_to_todos(res).await;
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert_eq!(todos[1].labels, labels);
+
+        let req = build_req_with_empty("/labels/99/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res_to_todos(res).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_assign_label_to_many_todos() {
+        let (labels, _) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        for label_ids in [vec![1], vec![], vec![2]] {
+            todo_repository
+                .create(
+                    None,
+                    CreateTodo::new("some todo text".to_string(), label_ids),
+                )
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/labels/1/assign",
+            Method::POST,
+            r#"{"todo_ids":[1,2,3]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_json(res).await, serde_json::json!({ "assigned": 2 }));
+
+        let req = build_req_with_empty("/labels/1/todos", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res_to_todos(res).await.len(), 3);
+
+        let req = build_req_with_json(
+            "/labels/99/assign",
+            Method::POST,
+            r#"{"todo_ids":[1]}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = build_req_with_json(
+            "/labels/2/assign",
+            Method::POST,
+            r#"{"todo_ids":[1,99]}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_not_delete_label_in_use() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let req = build_req_with_empty("/labels/1", Method::DELETE);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({ "error": "in_use", "id": 1 }));
+    }
+
+    #[tokio::test]
+    async fn should_force_delete_label_in_use() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/labels/1?force=true", Method::DELETE);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(todo.labels, vec![labels[1].clone()]);
+    }
+
+    #[tokio::test]
+    async fn should_merge_labels() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        for label_ids in [label_ids, vec![1]] {
+            todo_repository
+                .create(
+                    None,
+                    CreateTodo::new("some todo text".to_string(), label_ids),
+                )
+                .await
+                .expect("failed to create todo");
+        }
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/labels/merge",
+            Method::POST,
+            r#"{"from":1,"into":1}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = build_req_with_json(
+            "/labels/merge",
+            Method::POST,
+            r#"{"from":1,"into":2}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_to_label(res).await, labels[1]);
+
+        let req = build_req_with_empty("/labels/2/todos", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_todos(res).await;
+        assert_eq!(todos.len(), 2);
+        for todo in todos {
+            assert_eq!(todo.labels, vec![labels[1].clone()]);
+        }
+
+        let req = build_req_with_empty("/labels/1", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_suggest_labels_from_text() {
+        let label_repository = LabelRepositoryForMemory::new();
+        for name in ["Work", "home", "urgent"] {
+            label_repository
+                .create(CreateLabel::new(name.to_string()))
+                .await
+                .expect("failed to create label");
+        }
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/suggest-labels",
+            Method::POST,
+            r#"{"text":"Urgent: send the work report from home(office)"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let found: Vec<Label> = serde_json::from_value(res_to_json(res).await).unwrap();
+        let names: Vec<_> = found.iter().map(|label| label.name.as_str()).collect();
+        assert_eq!(names, vec!["Work", "home", "urgent"]);
+
+        let req = build_req_with_json(
+            "/todos/suggest-labels",
+            Method::POST,
+            r#"{"text":"homework"}"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res_to_json(res).await, json!([]));
+    }
+
+    #[tokio::test]
+    async fn should_hide_archived_labels() {
+        let (labels, label_ids) = label_fixture();
+        let todo_repository = TodoRepositoryForMemory::new(labels.clone());
+        let label_repository = LabelRepositoryForMemory::with_todos(todo_repository.clone());
+        for label in labels.iter() {
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+        }
+        todo_repository
+            .create(
+                None,
+                CreateTodo::new("some todo text".to_string(), label_ids),
+            )
+            .await
+            .expect("failed to create todo");
+        let app = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        );
+
+        let req = build_req_with_empty("/labels/1/archive", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let archived = res_to_label(res).await;
+        assert!(archived.archived);
+
+        let req = build_req_with_empty("/labels", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let found: Vec<Label> = serde_json::from_value(res_to_json(res).await).unwrap();
+        assert_eq!(found, vec![labels[1].clone()]);
+
+        let req = build_req_with_empty("/labels?include_archived=true", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let found: Vec<Label> = serde_json::from_value(res_to_json(res).await).unwrap();
+        assert_eq!(found, vec![archived.clone(), labels[1].clone()]);
+
+        // todos keep showing the archived label
+        let req = build_req_with_empty("/todos/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todo(res).await.labels,
+            vec![archived, labels[1].clone()]
+        );
+
+        let req = build_req_with_empty("/labels/99/archive", Method::POST);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_delete_label() {
+        let todo_repository = TodoRepositoryForMemory::new(vec![]);
+        let label_repository = LabelRepositoryForMemory::new();
+        label_repository
+            .create(CreateLabel::new("some label text".to_string()))
+            .await
+            .expect("failed to create label");
+        let req = build_req_with_empty("/labels/1", Method::DELETE);
+        let res = create_app(
+            todo_repository,
+            label_repository,
+            HealthRepositoryForMemory::new(),
+            HttpConfig::default(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
         assert_eq!(res.status(), StatusCode::NO_CONTENT);
     }
 }
@@ -0,0 +1,105 @@
+use axum::{
+    extract::MatchedPath,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::{sync::Arc, time::Instant};
+
+/// Prometheus registry with the HTTP metrics recorded by `track_metrics`.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests_total: IntCounter,
+    requests_by_status: IntCounterVec,
+    request_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let requests_total =
+            IntCounter::new("http_requests_total", "Total number of HTTP requests")
+                .expect("failed to create metric");
+        let requests_by_status = IntCounterVec::new(
+            Opts::new(
+                "http_requests_by_status_total",
+                "Number of HTTP requests per status class",
+            ),
+            &["class"],
+        )
+        .expect("failed to create metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency per route",
+            ),
+            &["method", "route"],
+        )
+        .expect("failed to create metric");
+        registry
+            .register(Box::new(requests_total.clone()))
+            .expect("failed to register metric");
+        registry
+            .register(Box::new(requests_by_status.clone()))
+            .expect("failed to register metric");
+        registry
+            .register(Box::new(request_duration.clone()))
+            .expect("failed to register metric");
+
+        Self {
+            registry,
+            requests_total,
+            requests_by_status,
+            request_duration,
+        }
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> (String, Vec<u8>) {
+        let encoder = TextEncoder::new();
+        let mut buffer = vec![];
+        encoder
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("failed to encode metrics");
+        (encoder.format_type().to_string(), buffer)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records the request count, status class and latency when `Metrics` is available as an extension.
+pub async fn track_metrics<B>(req: Request<B>, next: Next<B>) -> Response {
+    let metrics = match req.extensions().get::<Arc<Metrics>>().cloned() {
+        Some(metrics) => metrics,
+        None => return next.run(req).await.into_response(),
+    };
+    let method = req.method().to_string();
+    // use the route template so that ids do not blow up the label cardinality
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+
+    let start = Instant::now();
+    let res = next.run(req).await.into_response();
+
+    metrics.requests_total.inc();
+    metrics
+        .requests_by_status
+        .with_label_values(&[&format!("{}xx", res.status().as_u16() / 100)])
+        .inc();
+    metrics
+        .request_duration
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
@@ -0,0 +1,64 @@
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    PgPool,
+};
+
+/// Migrations embedded from the `migrations/` directory at compile time.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies every pending migration and logs the ones that were new to this database.
+/// Migrations applied earlier, e.g. by `sqlx migrate run`, are skipped.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
+    let applied = {
+        let mut conn = pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        conn.list_applied_migrations().await?
+    };
+
+    MIGRATOR.run(pool).await?;
+
+    let mut count = 0;
+    for migration in MIGRATOR
+        .iter()
+        .filter(|migration| !applied.iter().any(|m| m.version == migration.version))
+    {
+        tracing::info!(
+            "applied migration {} ({})",
+            migration.version,
+            migration.description
+        );
+        count += 1;
+    }
+    tracing::info!("database is up to date, {} migrations applied", count);
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn tables_exist_after_migration() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+
+        run_migrations(&pool).await.expect("failed to migrate");
+        // running them again is a no-op
+        run_migrations(&pool).await.expect("failed to migrate");
+
+        for table in ["todos", "labels", "todo_labels"] {
+            let exists = sqlx::query_scalar::<_, bool>("SELECT to_regclass($1) IS NOT NULL")
+                .bind(table)
+                .fetch_one(&pool)
+                .await
+                .expect("[select] returned Err");
+            assert!(exists, "table {} does not exist", table);
+        }
+    }
+}
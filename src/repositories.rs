@@ -1,14 +1,434 @@
+pub mod health;
 pub mod label;
 pub mod todo;
 
+use serde::{Deserialize, Deserializer};
+use sqlx::{pool::PoolConnection, PgPool, Postgres, Transaction};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
+use utoipa::IntoParams;
+use validator::ValidationError;
+
+const DEFAULT_PAGE_LIMIT: u32 = 20;
+pub const DEFAULT_SLOW_QUERY: Duration = Duration::from_millis(200);
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
 
 #[derive(Error, Debug)]
-enum RepositoryError {
+pub enum RepositoryError {
     #[error("Unexpected Error: [{0}]")]
     Unexpected(String),
     #[error("Not Found Error (id: {0})")]
     NotFound(i32),
     #[error("Duplicate data Error (id: {0})")]
     Duplicate(i32),
+    #[error("In Use Error (id: {0})")]
+    InUse(i32),
+    #[error("Unknown Labels Error (ids: {0:?})")]
+    UnknownLabels(Vec<i32>),
+    #[error("Unique Violation Error (constraint: {0})")]
+    UniqueViolation(String),
+    #[error("Foreign Key Violation Error (constraint: {0})")]
+    ForeignKeyViolation(String),
+    #[error("Order Mismatch Error")]
+    OrderMismatch,
+    #[error("Unknown Parent Error (id: {0})")]
+    UnknownParent(i32),
+    #[error("Parent Cycle Error (parent id: {0})")]
+    ParentCycle(i32),
+    /// Carries the stored version the update was expected to match.
+    #[error("Version Conflict Error (current version: {0})")]
+    VersionConflict(i32),
+}
+
+/// Postgres SQLSTATE codes, see https://www.postgresql.org/docs/current/errcodes-appendix.html
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const CONNECTION_EXCEPTION_CLASS: &str = "08";
+const ADMIN_SHUTDOWN: &str = "57P01";
+const CANNOT_CONNECT_NOW: &str = "57P03";
+
+/// Failure to check out a connection or to open a transaction on it. Nothing reached the
+/// database yet, so unlike a failed statement or commit it is safe to retry.
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub struct Unsent(sqlx::Error);
+
+/// Checks out the connection an attempt of a `retry` operation runs its statements on.
+pub async fn acquire(pool: &PgPool) -> anyhow::Result<PoolConnection<Postgres>> {
+    pool.acquire().await.map_err(|err| Unsent(err).into())
+}
+
+/// Opens the transaction of an attempt of a `retry` operation.
+pub async fn begin(pool: &PgPool) -> anyhow::Result<Transaction<'static, Postgres>> {
+    pool.begin().await.map_err(|err| Unsent(err).into())
+}
+
+/// Turns a plain or `Unsent` `sqlx::Error` into a `RepositoryError`, other errors are left as
+/// they are.
+pub fn classify(err: anyhow::Error) -> anyhow::Error {
+    let err = match err.downcast::<Unsent>() {
+        Ok(Unsent(err)) => return RepositoryError::from(err).into(),
+        Err(err) => err,
+    };
+    match err.downcast::<sqlx::Error>() {
+        Ok(err) => RepositoryError::from(err).into(),
+        Err(err) => err,
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(err: sqlx::Error) -> Self {
+        if let sqlx::Error::Database(db_err) = &err {
+            let constraint = db_err.constraint().unwrap_or_default().to_string();
+            match db_err.code().as_deref() {
+                Some(UNIQUE_VIOLATION) => return RepositoryError::UniqueViolation(constraint),
+                Some(FOREIGN_KEY_VIOLATION) => {
+                    return RepositoryError::ForeignKeyViolation(constraint)
+                }
+                _ => {}
+            }
+        }
+        RepositoryError::Unexpected(err.to_string())
+    }
+}
+
+/// Trims and collapses inner runs of whitespace into a single space.
+pub fn normalize_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Characters as users see them, so an emoji made of several code points counts once.
+pub fn char_count(value: &str) -> usize {
+    value.graphemes(true).count()
+}
+
+fn validate_char_count(
+    value: &str,
+    max: usize,
+    too_long: &'static str,
+) -> Result<(), ValidationError> {
+    let message = match char_count(value) {
+        0 => "Can not be empty",
+        count if count > max => too_long,
+        _ => return Ok(()),
+    };
+    let mut error = ValidationError::new("length");
+    error.message = Some(message.into());
+    error.add_param("min".into(), &1);
+    error.add_param("max".into(), &max);
+    Err(error)
+}
+
+/// Text of a todo, 1 to 100 characters counted by `char_count`.
+pub fn validate_text_length(text: &str) -> Result<(), ValidationError> {
+    validate_char_count(text, 100, "Text is too long")
+}
+
+/// Name of a label, 1 to 20 characters counted by `char_count`.
+pub fn validate_name_length(name: &str) -> Result<(), ValidationError> {
+    validate_char_count(name, 20, "Name is too long")
+}
+
+/// Values whose text goes through `normalize_whitespace` on the way in.
+pub trait Normalize {
+    fn normalize(self) -> Self;
+}
+
+impl Normalize for String {
+    fn normalize(self) -> Self {
+        normalize_whitespace(&self)
+    }
+}
+
+impl<T: Normalize> Normalize for Option<T> {
+    fn normalize(self) -> Self {
+        self.map(Normalize::normalize)
+    }
+}
+
+impl<T: Normalize> Normalize for Vec<T> {
+    fn normalize(self) -> Self {
+        self.into_iter().map(Normalize::normalize).collect()
+    }
+}
+
+/// Normalizes names and texts while deserializing, i.e. before they are validated.
+pub fn deserialize_normalized<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Deserialize<'de> + Normalize,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Normalize::normalize)
+}
+
+/// Distinguishes an explicit `null` from an omitted field, which `#[serde(default)]` maps to `None`.
+pub fn deserialize_nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+    #[serde(default)]
+    pub offset: u32,
+    /// Keyset cursor, only ids below it are returned.
+    pub after: Option<i32>,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_PAGE_LIMIT,
+            offset: 0,
+            after: None,
+        }
+    }
+}
+
+impl Pagination {
+    /// Every row at once, for responses that must not be cut off.
+    pub fn unbounded() -> Self {
+        Self {
+            limit: u32::MAX,
+            ..Self::default()
+        }
+    }
+}
+
+fn default_limit() -> u32 {
+    DEFAULT_PAGE_LIMIT
+}
+
+/// Awaits `query` and warns with its `label` when it ran longer than `threshold`.
+pub async fn timed<T>(label: &str, threshold: Duration, query: impl Future<Output = T>) -> T {
+    let started = Instant::now();
+    let output = query.await;
+    warn_if_slow(label, started.elapsed(), threshold);
+    output
+}
+
+fn warn_if_slow(label: &str, elapsed: Duration, threshold: Duration) {
+    if elapsed > threshold {
+        tracing::warn!("slow query [{}] took {:?}", label, elapsed);
+    }
+}
+
+/// How often `retry` runs an operation again, the delay doubles after every attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay * 2u32.saturating_pow(retry)
+    }
+}
+
+/// Runs `operation` again while it fails with a transient error, at most `policy.max_retries`
+/// times. Only failures to get a connection or open a transaction through `acquire` or `begin`
+/// are retried: once a statement or commit was sent, a lost connection does not tell whether
+/// the server applied it, and repeating a write could apply it twice. Reads following a commit
+/// must not be part of `operation` either, as failing them would repeat the write.
+pub async fn retry<T, F, Fut>(
+    policy: RetryPolicy,
+    label: &str,
+    mut operation: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match operation().await {
+            Err(err) if retries < policy.max_retries && is_transient(&err) => {
+                let delay = policy.delay(retries);
+                tracing::warn!("retrying [{}] in {:?} after: {}", label, delay, err);
+                tokio::time::sleep(delay).await;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Lost or refused connections before anything was sent, which may work on the next attempt
+/// unlike missing rows or violated constraints. Only an `Unsent` or a pool timeout is
+/// recognized, so repositories pass those on and turn them into a `RepositoryError` with
+/// `classify` once `retry` returned.
+fn is_transient(err: &anyhow::Error) -> bool {
+    if let Some(Unsent(err)) = err.downcast_ref::<Unsent>() {
+        return is_connection_error(err);
+    }
+    // waiting for a free connection never sends anything
+    matches!(
+        err.downcast_ref::<sqlx::Error>(),
+        Some(sqlx::Error::PoolTimedOut)
+    )
+}
+
+fn is_connection_error(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            code.starts_with(CONNECTION_EXCEPTION_CLASS)
+                || code == ADMIN_SHUTDOWN
+                || code == CANNOT_CONNECT_NOW
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn timed_warns_about_slow_queries() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let output = timed("fast", Duration::from_secs(60), async { 1 }).await;
+        assert_eq!(output, 1);
+        assert!(logs.0.lock().unwrap().is_empty());
+
+        let output = timed("slow", Duration::ZERO, async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            2
+        })
+        .await;
+        assert_eq!(output, 2);
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("slow query [slow] took"), "{}", logs);
+    }
+
+    #[test]
+    fn count_characters_rather_than_bytes() {
+        assert_eq!(char_count("abc"), 3);
+        assert_eq!(char_count("äöü"), 3);
+        // thumbs up with a skin tone, a family joined by zero width joiners and a flag
+        assert_eq!(char_count("👍🏽👨‍👩‍👧🇯🇵"), 3);
+        // `e` followed by a combining acute accent
+        assert_eq!(char_count("e\u{301}"), 1);
+
+        assert!(validate_text_length(&"🎉".repeat(100)).is_ok());
+        assert!(validate_text_length(&"👨‍👩‍👧".repeat(100)).is_ok());
+        let error = validate_text_length(&"🎉".repeat(101)).unwrap_err();
+        assert_eq!(error.message.as_deref(), Some("Text is too long"));
+        let error = validate_text_length("").unwrap_err();
+        assert_eq!(error.message.as_deref(), Some("Can not be empty"));
+
+        assert!(validate_name_length(&"日本語".repeat(6)).is_ok());
+        assert!(validate_name_length(&"日本語".repeat(7)).is_err());
+    }
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_transient_errors_until_success() {
+        let attempts = Mutex::new(0);
+        let result = retry(retry_policy(), "flaky", || async {
+            let mut attempts = attempts.lock().unwrap();
+            *attempts += 1;
+            if *attempts <= 2 {
+                return Err(sqlx::Error::PoolTimedOut.into());
+            }
+            Ok(*attempts)
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up_after_max_retries() {
+        let attempts = Mutex::new(0);
+        let result: anyhow::Result<()> = retry(retry_policy(), "down", || async {
+            *attempts.lock().unwrap() += 1;
+            let err = sqlx::Error::Io(io::Error::from(io::ErrorKind::ConnectionRefused));
+            Err(Unsent(err).into())
+        })
+        .await;
+        assert!(matches!(
+            classify(result.unwrap_err()).downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Unexpected(_))
+        ));
+        assert_eq!(*attempts.lock().unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn retry_skips_errors_after_sending() {
+        // the connection dropped during a commit, which may have been applied
+        let attempts = Mutex::new(0);
+        let result: anyhow::Result<()> = retry(retry_policy(), "commit", || async {
+            *attempts.lock().unwrap() += 1;
+            Err(sqlx::Error::Io(io::Error::from(io::ErrorKind::ConnectionReset)).into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(*attempts.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_skips_permanent_errors() {
+        let attempts = Mutex::new(0);
+        let result: anyhow::Result<()> = retry(retry_policy(), "missing", || async {
+            *attempts.lock().unwrap() += 1;
+            Err(sqlx::Error::RowNotFound.into())
+        })
+        .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<sqlx::Error>(),
+            Some(sqlx::Error::RowNotFound)
+        ));
+        assert_eq!(*attempts.lock().unwrap(), 1);
+    }
 }
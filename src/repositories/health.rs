@@ -0,0 +1,77 @@
+use axum::async_trait;
+use sqlx::PgPool;
+
+#[async_trait]
+pub trait HealthRepository: std::marker::Send + std::marker::Sync + 'static {
+    async fn check(&self) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthRepositoryForDb {
+    pool: PgPool,
+}
+
+impl HealthRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthRepository for HealthRepositoryForDb {
+    async fn check(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+    use anyhow::anyhow;
+    use std::time::Duration;
+
+    #[derive(Debug, Clone)]
+    pub struct HealthRepositoryForMemory {
+        healthy: bool,
+        delay: Option<Duration>,
+    }
+
+    impl HealthRepositoryForMemory {
+        pub fn new() -> Self {
+            Self {
+                healthy: true,
+                delay: None,
+            }
+        }
+
+        pub fn unavailable() -> Self {
+            Self {
+                healthy: false,
+                delay: None,
+            }
+        }
+
+        /// Healthy, but every check takes `delay` to answer.
+        pub fn slow(delay: Duration) -> Self {
+            Self {
+                healthy: true,
+                delay: Some(delay),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HealthRepository for HealthRepositoryForMemory {
+        async fn check(&self) -> anyhow::Result<()> {
+            if let Some(delay) = self.delay {
+                tokio::time::sleep(delay).await;
+            }
+            if self.healthy {
+                Ok(())
+            } else {
+                Err(anyhow!("database unavailable"))
+            }
+        }
+    }
+}
@@ -1,85 +1,466 @@
 use axum::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{FromRow, PgPool};
+use std::future::Future;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
 
-use super::RepositoryError;
+use super::{
+    acquire, begin, deserialize_normalized, deserialize_nullable, normalize_whitespace, retry,
+    validate_name_length, RepositoryError, RetryPolicy,
+};
 
 #[async_trait]
-pub trait LabelRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
-    async fn create(&self, name: String) -> anyhow::Result<Label>;
-    async fn all(&self) -> anyhow::Result<Vec<Label>>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+pub trait LabelRepository: std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label>;
+    async fn find(&self, id: i32) -> anyhow::Result<Label>;
+    async fn all(&self, query: LabelQuery) -> anyhow::Result<Vec<Label>>;
+    /// Counts only the todos of `owner_id`, labels themselves are shared.
+    async fn all_with_counts(&self, owner_id: Option<&str>) -> anyhow::Result<Vec<LabelWithCount>>;
+    async fn todo_count(&self, owner_id: Option<&str>, label_id: i32) -> anyhow::Result<i64>;
+    async fn update(&self, id: i32, payload: UpdateLabel) -> anyhow::Result<Label>;
+    async fn delete(&self, id: i32, force: bool) -> anyhow::Result<()>;
+    /// Moves every todo of `from` over to `into`, deletes `from` and returns `into`.
+    async fn merge(&self, from: i32, into: i32) -> anyhow::Result<Label>;
+    async fn archive(&self, id: i32) -> anyhow::Result<Label>;
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow, ToSchema)]
 pub struct Label {
     pub id: i32,
     pub name: String,
+    pub color: String,
+    /// Longer text shown as a tooltip.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Hidden from `GET /labels` but still shown on the todos carrying it.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Name search and paging of `GET /labels`, all labels are returned by default.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LabelQuery {
+    /// Case-insensitive part of the name.
+    q: Option<String>,
+    limit: Option<u32>,
+    #[serde(default)]
+    offset: u32,
+    /// List archived labels too.
+    #[serde(default)]
+    include_archived: bool,
+}
+
+impl LabelQuery {
+    fn query(&self) -> Option<&str> {
+        self.q.as_deref().filter(|q| !q.is_empty())
+    }
+}
+
+pub const DEFAULT_LABEL_COLOR: &str = "#cccccc";
+
+fn default_color() -> String {
+    DEFAULT_LABEL_COLOR.to_string()
+}
+
+/// Accepts `#RRGGBB` hex colors only.
+fn validate_color(color: &str) -> Result<(), ValidationError> {
+    let hex = color.strip_prefix('#').unwrap_or_default();
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        let mut error = ValidationError::new("invalid_color");
+        error.message = Some("Color must be a #RRGGBB hex string".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct LabelWithCount {
+    pub label: Label,
+    pub todo_count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+struct LabelWithCountFromRow {
+    id: i32,
+    name: String,
+    color: String,
+    description: Option<String>,
+    archived: bool,
+    todo_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
+pub struct CreateLabel {
+    #[serde(deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_name_length")]
+    name: String,
+    #[serde(default = "default_color")]
+    #[validate(custom = "validate_color")]
+    color: String,
+    #[serde(default)]
+    #[validate(length(max = 200, message = "Description is too long"))]
+    description: Option<String>,
+}
+
+impl CreateLabel {
+    pub fn new(name: String) -> Self {
+        Self {
+            name: normalize_whitespace(&name),
+            color: default_color(),
+            description: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
+pub struct UpdateLabel {
+    #[serde(deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_name_length")]
+    name: String,
+    #[serde(default)]
+    #[validate(custom = "validate_color")]
+    color: Option<String>,
+    /// Omit to keep the current description, send `null` to clear it.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(length(max = 200, message = "Description is too long"))]
+    description: Option<Option<String>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct LabelRepositoryForDb {
     pool: PgPool,
+    retry: RetryPolicy,
 }
 
 impl LabelRepositoryForDb {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Methods failing with a connection error are run again following `policy`.
+    pub fn with_retry(self, policy: RetryPolicy) -> Self {
+        Self {
+            retry: policy,
+            ..self
+        }
+    }
+
+    async fn retry<T, F, Fut>(&self, method: &str, operation: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        retry(self.retry, &format!("labels.{}", method), operation).await
     }
 }
 
 #[async_trait]
 impl LabelRepository for LabelRepositoryForDb {
-    async fn create(&self, name: String) -> anyhow::Result<Label> {
-        let optional_label = sqlx::query_as::<_, Label>(
-            r#"
-            SELECT * FROM LABELS WHERE NAME = $1
-            "#,
-        )
-        .bind(name.clone())
-        .fetch_optional(&self.pool)
-        .await?;
-        if let Some(label) = optional_label {
-            return Err(RepositoryError::Duplicate(label.id).into());
-        }
+    async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
+        self.retry("create", || {
+            let payload = payload.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                // the unique index on LOWER(NAME) settles concurrent creates of the same name
+                let inserted = sqlx::query_as::<_, Label>(
+                    r#"
+                    INSERT INTO LABELS (NAME, COLOR, DESCRIPTION) VALUES ($1, $2, $3)
+                    ON CONFLICT (LOWER(NAME)) DO NOTHING
+                    RETURNING *
+                    "#,
+                )
+                .bind(payload.name.clone())
+                .bind(payload.color)
+                .bind(payload.description)
+                .fetch_optional(&mut conn)
+                .await?;
+                if let Some(label) = inserted {
+                    return Ok(label);
+                }
 
-        let label = sqlx::query_as::<_, Label>(
-            r#"
-            INSERT INTO LABELS (NAME) VALUES ($1) RETURNING *
-            "#,
-        )
-        .bind(name.clone())
-        .fetch_one(&self.pool)
-        .await?;
+                let existing_id = sqlx::query_scalar::<_, i32>(
+                    r#"
+                    SELECT ID FROM LABELS WHERE LOWER(NAME) = LOWER($1)
+                    "#,
+                )
+                .bind(payload.name)
+                .fetch_one(&mut conn)
+                .await?;
+                Err(RepositoryError::Duplicate(existing_id).into())
+            }
+        })
+        .await
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Label> {
+        self.retry("find", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let label = sqlx::query_as::<_, Label>(
+                r#"
+                SELECT * FROM LABELS WHERE ID = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_one(&mut conn)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => RepositoryError::NotFound(id).into(),
+                _ => anyhow::Error::from(e),
+            })?;
 
-        Ok(label)
+            Ok(label)
+        })
+        .await
     }
-    async fn all(&self) -> anyhow::Result<Vec<Label>> {
-        let label_vec = sqlx::query_as::<_, Label>(
-            r#"
-            SELECT * FROM LABELS ORDER BY ID ASC
-            "#,
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    async fn all(&self, query: LabelQuery) -> anyhow::Result<Vec<Label>> {
+        self.retry("all", || {
+            let query = query.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                let label_vec = sqlx::query_as::<_, Label>(
+                    r#"
+                    SELECT * FROM LABELS
+                    WHERE ($1::TEXT IS NULL OR NAME ILIKE '%' || $1 || '%')
+                    AND ($4 OR NOT ARCHIVED)
+                    ORDER BY ID ASC
+                    LIMIT $2 OFFSET $3
+                    "#,
+                )
+                .bind(query.query())
+                .bind(query.limit.map(i64::from))
+                .bind(i64::from(query.offset))
+                .bind(query.include_archived)
+                .fetch_all(&mut conn)
+                .await?;
 
-        Ok(label_vec)
+                Ok(label_vec)
+            }
+        })
+        .await
     }
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        sqlx::query(
-            r#"
-            DELETE FROM LABELS WHERE ID = $1
-            "#,
-        )
-        .bind(id)
-        .execute(&self.pool)
+    async fn all_with_counts(&self, owner_id: Option<&str>) -> anyhow::Result<Vec<LabelWithCount>> {
+        self.retry("all_with_counts", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let rows = sqlx::query_as::<_, LabelWithCountFromRow>(
+                r#"
+                SELECT LABELS.ID, LABELS.NAME, LABELS.COLOR, LABELS.DESCRIPTION, LABELS.ARCHIVED,
+                COUNT(TODOS.ID) AS TODO_COUNT
+                FROM LABELS
+                LEFT OUTER JOIN TODO_LABELS TL ON LABELS.ID = TL.LABEL_ID
+                LEFT OUTER JOIN TODOS ON TODOS.ID = TL.TODO_ID AND TODOS.DELETED_AT IS NULL
+                AND TODOS.OWNER_ID IS NOT DISTINCT FROM $1
+                GROUP BY LABELS.ID
+                ORDER BY LABELS.ID ASC
+                "#,
+            )
+            .bind(owner_id)
+            .fetch_all(&mut conn)
+            .await?;
+
+            let labels = rows
+                .into_iter()
+                .map(|row| LabelWithCount {
+                    label: Label {
+                        id: row.id,
+                        name: row.name,
+                        color: row.color,
+                        description: row.description,
+                        archived: row.archived,
+                    },
+                    todo_count: row.todo_count,
+                })
+                .collect();
+            Ok(labels)
+        })
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()).into(),
-        })?;
+    }
+    async fn todo_count(&self, owner_id: Option<&str>, label_id: i32) -> anyhow::Result<i64> {
+        let count = self
+            .retry("todo_count", || async move {
+                let mut conn = acquire(&self.pool).await?;
+                let count = sqlx::query_scalar::<_, i64>(
+                    r#"
+                    SELECT COUNT(*) FROM TODO_LABELS TL
+                    JOIN TODOS ON TODOS.ID = TL.TODO_ID AND TODOS.DELETED_AT IS NULL
+                    AND TODOS.OWNER_ID IS NOT DISTINCT FROM $2
+                    WHERE TL.LABEL_ID = $1
+                    "#,
+                )
+                .bind(label_id)
+                .bind(owner_id)
+                .fetch_one(&mut conn)
+                .await?;
 
-        Ok(())
+                Ok(count)
+            })
+            .await?;
+        if count == 0 {
+            // a label nobody uses and a missing label both count zero
+            self.find(label_id).await?;
+        }
+        Ok(count)
+    }
+    async fn update(&self, id: i32, payload: UpdateLabel) -> anyhow::Result<Label> {
+        self.retry("update", || {
+            let payload = payload.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                let label = sqlx::query_as::<_, Label>(
+                    r#"
+                    UPDATE LABELS SET NAME = $1, COLOR = COALESCE($2, COLOR),
+                    DESCRIPTION = CASE WHEN $3 THEN $4 ELSE DESCRIPTION END
+                    WHERE ID = $5
+                    RETURNING *
+                    "#,
+                )
+                .bind(payload.name)
+                .bind(payload.color)
+                .bind(payload.description.is_some())
+                .bind(payload.description.flatten())
+                .bind(id)
+                .fetch_one(&mut conn)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::RowNotFound => RepositoryError::NotFound(id).into(),
+                    _ => anyhow::Error::from(e),
+                })?;
+
+                Ok(label)
+            }
+        })
+        .await
+    }
+    async fn delete(&self, id: i32, force: bool) -> anyhow::Result<()> {
+        self.retry("delete", || async move {
+            let mut tx = begin(&self.pool).await?;
+            let in_use = sqlx::query_scalar::<_, bool>(
+                r#"
+                SELECT EXISTS(SELECT 1 FROM TODO_LABELS WHERE LABEL_ID = $1)
+                "#,
+            )
+            .bind(id)
+            .fetch_one(&mut tx)
+            .await?;
+            if in_use {
+                if !force {
+                    return Err(RepositoryError::InUse(id).into());
+                }
+                sqlx::query(
+                    r#"
+                    DELETE FROM TODO_LABELS WHERE LABEL_ID = $1
+                    "#,
+                )
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+            }
+
+            sqlx::query(
+                r#"
+                DELETE FROM LABELS WHERE ID = $1
+                "#,
+            )
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => RepositoryError::NotFound(id).into(),
+                _ => anyhow::Error::from(e),
+            })?;
+            tx.commit().await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn merge(&self, from: i32, into: i32) -> anyhow::Result<Label> {
+        self.retry("merge", || async move {
+            let mut tx = begin(&self.pool).await?;
+            for id in [from, into] {
+                let exists = sqlx::query_scalar::<_, bool>(
+                    r#"
+                    SELECT EXISTS(SELECT 1 FROM LABELS WHERE ID = $1)
+                    "#,
+                )
+                .bind(id)
+                .fetch_one(&mut tx)
+                .await?;
+                if !exists {
+                    return Err(RepositoryError::NotFound(id).into());
+                }
+            }
+
+            sqlx::query(
+                r#"
+                INSERT INTO TODO_LABELS (TODO_ID, LABEL_ID)
+                SELECT TODO_ID, $2 FROM TODO_LABELS WHERE LABEL_ID = $1
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(from)
+            .bind(into)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                r#"
+                DELETE FROM TODO_LABELS WHERE LABEL_ID = $1
+                "#,
+            )
+            .bind(from)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                r#"
+                DELETE FROM LABELS WHERE ID = $1
+                "#,
+            )
+            .bind(from)
+            .execute(&mut tx)
+            .await?;
+            let label = sqlx::query_as::<_, Label>(
+                r#"
+                SELECT * FROM LABELS WHERE ID = $1
+                "#,
+            )
+            .bind(into)
+            .fetch_one(&mut tx)
+            .await?;
+            tx.commit().await?;
+
+            Ok(label)
+        })
+        .await
+    }
+
+    async fn archive(&self, id: i32) -> anyhow::Result<Label> {
+        self.retry("archive", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let label = sqlx::query_as::<_, Label>(
+                r#"
+                UPDATE LABELS SET ARCHIVED = TRUE WHERE ID = $1
+                RETURNING *
+                "#,
+            )
+            .bind(id)
+            .fetch_one(&mut conn)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => RepositoryError::NotFound(id).into(),
+                _ => anyhow::Error::from(e),
+            })?;
+
+            Ok(label)
+        })
+        .await
     }
 }
 
@@ -97,23 +478,459 @@ mod test {
         let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
         let pool = PgPool::connect(&database_url)
             .await
-            .expect(&format!("failed to connect to db, url: [{}]", database_url));
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
         let repository = LabelRepositoryForDb::new(pool);
         let label_text = "test_label".to_string();
 
         // create
         let label = repository
-            .create(label_text.to_string())
+            .create(CreateLabel::new(label_text.to_string()))
             .await
-            .expect(&format!("[create] failed to create label"));
+            .expect("[create] failed to create label");
         assert_eq!(label.name, label_text);
 
+        // all with counts
+        let labels = repository
+            .all_with_counts(None)
+            .await
+            .expect("[all_with_counts] failed to count todos per label");
+        let stats = labels
+            .iter()
+            .find(|stats| stats.label.id == label.id)
+            .expect("[all_with_counts] created label is missing");
+        assert_eq!(stats.todo_count, 0);
+
+        // update
+        let updated_text = "updated_label".to_string();
+        let updated_label = repository
+            .update(
+                label.id,
+                UpdateLabel {
+                    name: updated_text.clone(),
+                    color: None,
+                    description: None,
+                },
+            )
+            .await
+            .expect("[update] failed to update label");
+        assert_eq!(updated_label, Label::new(label.id, updated_text.clone()));
+
+        // update color
+        let colored_label = repository
+            .update(
+                label.id,
+                UpdateLabel {
+                    name: updated_text.clone(),
+                    color: Some("#ff8800".to_string()),
+                    description: Some(Some("shown as tooltip".to_string())),
+                },
+            )
+            .await
+            .expect("[update] failed to update label color");
+        assert_eq!(colored_label.color, "#ff8800");
+        assert_eq!(
+            colored_label.description.as_deref(),
+            Some("shown as tooltip")
+        );
+
+        // omitting the description keeps it, null clears it
+        let kept = repository
+            .update(
+                label.id,
+                UpdateLabel {
+                    name: updated_text.clone(),
+                    color: None,
+                    description: None,
+                },
+            )
+            .await
+            .expect("[update] failed to update label");
+        assert_eq!(kept.description, colored_label.description);
+        let cleared = repository
+            .update(
+                label.id,
+                UpdateLabel {
+                    name: updated_text,
+                    color: None,
+                    description: Some(None),
+                },
+            )
+            .await
+            .expect("[update] failed to clear description");
+        assert_eq!(cleared.description, None);
+
+        // archive
+        let archived = repository
+            .archive(label.id)
+            .await
+            .expect("[archive] failed to archive label");
+        assert!(archived.archived);
+        let labels = repository
+            .all(LabelQuery::default())
+            .await
+            .expect("[all] failed to get labels");
+        assert!(labels.iter().all(|label| label.id != archived.id));
+        let labels = repository
+            .all(LabelQuery {
+                include_archived: true,
+                ..LabelQuery::default()
+            })
+            .await
+            .expect("[all] failed to get archived labels");
+        assert!(labels.contains(&archived));
+
         // delete
         repository
-            .delete(label.id)
+            .delete(label.id, false)
+            .await
+            .expect("[delete] failed to delete label");
+    }
+
+    #[tokio::test]
+    async fn delete_in_use_scenario() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = LabelRepositoryForDb::new(pool.clone());
+        let label = repository
+            .create(CreateLabel::new("in_use_label".to_string()))
+            .await
+            .expect("[create] failed to create label");
+        sqlx::query(
+            r#"
+            WITH TODO AS (
+                INSERT INTO TODOS (TEXT) VALUES ('[delete_in_use_scenario] todo') RETURNING ID
+            )
+            INSERT INTO TODO_LABELS (TODO_ID, LABEL_ID) SELECT ID, $1 FROM TODO
+            "#,
+        )
+        .bind(label.id)
+        .execute(&pool)
+        .await
+        .expect("failed to attach label");
+
+        // todo_count, only the todos of the caller count
+        let count = repository
+            .todo_count(None, label.id)
+            .await
+            .expect("[todo_count] failed to count todos");
+        assert_eq!(count, 1);
+        let count = repository
+            .todo_count(Some("delete_in_use_scenario"), label.id)
+            .await
+            .expect("[todo_count] failed to count todos");
+        assert_eq!(count, 0);
+        let stats = repository
+            .all_with_counts(Some("delete_in_use_scenario"))
+            .await
+            .expect("[all_with_counts] failed to count todos per label");
+        let stats = stats
+            .iter()
+            .find(|stats| stats.label.id == label.id)
+            .expect("[all_with_counts] created label is missing");
+        assert_eq!(stats.todo_count, 0);
+
+        // delete without force
+        let err = repository
+            .delete(label.id, false)
+            .await
+            .expect_err("[delete] in-use label must not be deleted");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::InUse(id)) if *id == label.id
+        ));
+
+        // delete with force
+        repository
+            .delete(label.id, true)
+            .await
+            .expect("[delete] failed to force delete label");
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM TODO_LABELS WHERE LABEL_ID = $1
+            "#,
+        )
+        .bind(label.id)
+        .fetch_all(&pool)
+        .await
+        .expect("failed to fetch todo_labels");
+        assert!(rows.is_empty());
+
+        // todo_count of a deleted label
+        let err = repository
+            .todo_count(None, label.id)
+            .await
+            .expect_err("[todo_count] deleted label must not be counted");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rename_scenario() {
+        use crate::repositories::{
+            todo::{CreateTodo, TodoFilter, TodoRepository, TodoRepositoryForDb, TodoSort},
+            Pagination,
+        };
+
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = LabelRepositoryForDb::new(pool.clone());
+        let todo_repository = TodoRepositoryForDb::new(pool);
+        let label = repository
+            .create(CreateLabel::new("rename_label".to_string()))
+            .await
+            .expect("[create] failed to create label");
+        // a dedicated owner keeps todos of concurrent tests out of `all`
+        let owner_id = Some("[rename_scenario]");
+        let todo = todo_repository
+            .create(
+                owner_id,
+                CreateTodo::new("[rename_scenario] todo".to_string(), vec![label.id]),
+            )
+            .await
+            .expect("[create] failed to create todo");
+
+        let renamed = repository
+            .update(
+                label.id,
+                UpdateLabel {
+                    name: "renamed_label".to_string(),
+                    color: None,
+                    description: None,
+                },
+            )
+            .await
+            .expect("[update] failed to rename label");
+
+        // todos only reference labels, so they see the new name right away
+        let found = todo_repository
+            .find(owner_id, todo.id)
+            .await
+            .expect("[find] failed to find todo");
+        assert_eq!(found.labels, vec![renamed.clone()]);
+        let todos = todo_repository
+            .all(
+                owner_id,
+                TodoFilter::default(),
+                TodoSort::default(),
+                Pagination::default(),
+            )
+            .await
+            .expect("[all] failed to list todos");
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].labels, vec![renamed]);
+
+        todo_repository
+            .delete(owner_id, todo.id)
+            .await
+            .expect("[delete] failed to delete todo");
+        repository
+            .delete(label.id, true)
             .await
             .expect("[delete] failed to delete label");
     }
+
+    #[tokio::test]
+    async fn merge_scenario() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = LabelRepositoryForDb::new(pool.clone());
+        let from = repository
+            .create(CreateLabel::new("merge_from".to_string()))
+            .await
+            .expect("[create] failed to create label");
+        let into = repository
+            .create(CreateLabel::new("merge_into".to_string()))
+            .await
+            .expect("[create] failed to create label");
+        // the first todo carries both labels, the second only the source one
+        let todo_ids = sqlx::query_scalar::<_, i32>(
+            r#"
+            INSERT INTO TODOS (TEXT) VALUES ('[merge_scenario] todo'), ('[merge_scenario] todo')
+            RETURNING ID
+            "#,
+        )
+        .fetch_all(&pool)
+        .await
+        .expect("failed to insert todos");
+        sqlx::query(
+            r#"
+            INSERT INTO TODO_LABELS (TODO_ID, LABEL_ID)
+            VALUES ($1, $3), ($1, $4), ($2, $3)
+            "#,
+        )
+        .bind(todo_ids[0])
+        .bind(todo_ids[1])
+        .bind(from.id)
+        .bind(into.id)
+        .execute(&pool)
+        .await
+        .expect("failed to attach labels");
+
+        let merged = repository
+            .merge(from.id, into.id)
+            .await
+            .expect("[merge] failed to merge labels");
+        assert_eq!(merged, into);
+
+        let rows = sqlx::query_as::<_, (i32, i32)>(
+            r#"
+            SELECT TODO_ID, LABEL_ID FROM TODO_LABELS WHERE TODO_ID = ANY($1) ORDER BY TODO_ID
+            "#,
+        )
+        .bind(&todo_ids)
+        .fetch_all(&pool)
+        .await
+        .expect("failed to fetch todo_labels");
+        assert_eq!(rows, vec![(todo_ids[0], into.id), (todo_ids[1], into.id)]);
+        let err = repository
+            .find(from.id)
+            .await
+            .expect_err("[find] merged label must be deleted");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+
+        repository
+            .delete(into.id, true)
+            .await
+            .expect("[delete] failed to delete label");
+    }
+
+    #[tokio::test]
+    async fn duplicate_name_ignoring_case_scenario() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = LabelRepositoryForDb::new(pool);
+        let label = repository
+            .create(CreateLabel::new("CaseWork".to_string()))
+            .await
+            .expect("[create] failed to create label");
+        assert_eq!(label.name, "CaseWork");
+
+        let err = repository
+            .create(CreateLabel::new("casework".to_string()))
+            .await
+            .expect_err("[create] name differing only in case must be a duplicate");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Duplicate(id)) if *id == label.id
+        ));
+
+        repository
+            .delete(label.id, false)
+            .await
+            .expect("[delete] failed to delete label");
+    }
+
+    #[tokio::test]
+    async fn concurrent_create_scenario() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = LabelRepositoryForDb::new(pool.clone());
+        let name = "concurrent_create_scenario";
+
+        let creates = ["Concurrent_Create_Scenario", name].map(|name| {
+            let repository = repository.clone();
+            tokio::spawn(async move { repository.create(CreateLabel::new(name.to_string())).await })
+        });
+        let mut created = vec![];
+        let mut duplicates = vec![];
+        for create in creates {
+            match create.await.expect("[create] task panicked") {
+                Ok(label) => created.push(label),
+                Err(err) => match err.downcast_ref::<RepositoryError>() {
+                    Some(RepositoryError::Duplicate(id)) => duplicates.push(*id),
+                    _ => panic!("[create] unexpected error: {:?}", err),
+                },
+            }
+        }
+        assert_eq!(created.len(), 1);
+        assert_eq!(duplicates, vec![created[0].id]);
+
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM LABELS WHERE LOWER(NAME) = $1
+            "#,
+        )
+        .bind(name)
+        .fetch_one(&pool)
+        .await
+        .expect("[count] failed to count labels");
+        assert_eq!(count, 1);
+
+        repository
+            .delete(created[0].id, false)
+            .await
+            .expect("[delete] failed to delete label");
+    }
+
+    #[tokio::test]
+    async fn search_scenario() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = LabelRepositoryForDb::new(pool);
+        let mut labels = vec![];
+        for name in [
+            "search_scenario_a",
+            "Search_Scenario_b",
+            "search_scenario_c",
+        ] {
+            labels.push(
+                repository
+                    .create(CreateLabel::new(name.to_string()))
+                    .await
+                    .expect("[create] failed to create label"),
+            );
+        }
+
+        let found = repository
+            .all(LabelQuery {
+                q: Some("SEARCH_SCENARIO".to_string()),
+                ..LabelQuery::default()
+            })
+            .await
+            .expect("[all] failed to search labels");
+        assert_eq!(found, labels);
+
+        let page = repository
+            .all(LabelQuery {
+                q: Some("search_scenario".to_string()),
+                limit: Some(1),
+                offset: 1,
+                include_archived: false,
+            })
+            .await
+            .expect("[all] failed to page labels");
+        assert_eq!(page, vec![labels[1].clone()]);
+
+        for label in labels {
+            repository
+                .delete(label.id, false)
+                .await
+                .expect("[delete] failed to delete label");
+        }
+    }
 }
 
 #[cfg(test)]
@@ -121,12 +938,22 @@ pub mod test_utils {
     use anyhow::Ok;
 
     use super::*;
+    use crate::repositories::todo::{test_utils::TodoRepositoryForMemory, TodoEntity};
     use std::collections::HashMap;
-    use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+    use std::sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    };
 
     impl Label {
         pub fn new(id: i32, name: String) -> Self {
-            Self { id, name }
+            Self {
+                id,
+                name,
+                color: DEFAULT_LABEL_COLOR.to_string(),
+                description: None,
+                archived: false,
+            }
         }
     }
 
@@ -135,45 +962,192 @@ pub mod test_utils {
     #[derive(Debug, Clone)]
     pub struct LabelRepositoryForMemory {
         data: Arc<RwLock<LabelData>>,
+        last_id: Arc<AtomicI32>,
+        todo_repository: Option<TodoRepositoryForMemory>,
     }
 
     impl LabelRepositoryForMemory {
         pub fn new() -> Self {
             Self {
                 data: Arc::default(),
+                last_id: Arc::default(),
+                todo_repository: None,
+            }
+        }
+
+        pub fn with_todos(todo_repository: TodoRepositoryForMemory) -> Self {
+            Self {
+                data: Arc::default(),
+                last_id: Arc::default(),
+                todo_repository: Some(todo_repository),
             }
         }
 
-        fn write_store_ref(&self) -> RwLockWriteGuard<LabelData> {
+        fn labeled_todos(&self, label_id: i32) -> Vec<TodoEntity> {
+            self.todo_repository.as_ref().map_or(vec![], |repository| {
+                repository
+                    .todos()
+                    .into_iter()
+                    .filter(|todo| todo.labels.iter().any(|label| label.id == label_id))
+                    .collect()
+            })
+        }
+
+        fn count_todos(&self, owner_id: Option<&str>, label_id: i32) -> i64 {
+            self.labeled_todos(label_id)
+                .iter()
+                .filter(|todo| todo.owner_id.as_deref() == owner_id)
+                .count() as i64
+        }
+
+        fn next_id(&self) -> i32 {
+            self.last_id.fetch_add(1, Ordering::SeqCst) + 1
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, LabelData> {
             self.data.write().unwrap()
         }
 
-        fn read_store_ref(&self) -> RwLockReadGuard<LabelData> {
+        fn read_store_ref(&self) -> RwLockReadGuard<'_, LabelData> {
             self.data.read().unwrap()
         }
     }
 
     #[async_trait]
     impl LabelRepository for LabelRepositoryForMemory {
-        async fn create(&self, name: String) -> anyhow::Result<Label> {
+        async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
             let mut store = self.write_store_ref();
-            let id = store.len() as i32 + 1;
-            let label = Label::new(id, name.clone());
+            if let Some(label) = store
+                .values()
+                .find(|label| label.name.to_lowercase() == payload.name.to_lowercase())
+            {
+                return Err(RepositoryError::Duplicate(label.id).into());
+            }
+            let id = self.next_id();
+            let label = Label {
+                id,
+                name: payload.name,
+                color: payload.color,
+                description: payload.description,
+                archived: false,
+            };
             store.insert(id, label.clone());
             Ok(label)
         }
 
-        async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        async fn find(&self, id: i32) -> anyhow::Result<Label> {
             let store = self.read_store_ref();
-            let labels = store.values().cloned().collect();
+            let label = store
+                .get(&id)
+                .cloned()
+                .ok_or(RepositoryError::NotFound(id))?;
+            Ok(label)
+        }
+
+        async fn all(&self, query: LabelQuery) -> anyhow::Result<Vec<Label>> {
+            let store = self.read_store_ref();
+            let q = query.query().map(str::to_lowercase);
+            let mut labels: Vec<Label> = store
+                .values()
+                .filter(|label| query.include_archived || !label.archived)
+                .filter(|label| {
+                    q.as_ref()
+                        .is_none_or(|q| label.name.to_lowercase().contains(q))
+                })
+                .cloned()
+                .collect();
+            labels.sort_by_key(|label| label.id);
+            let labels = labels
+                .into_iter()
+                .skip(query.offset as usize)
+                .take(query.limit.map_or(usize::MAX, |limit| limit as usize))
+                .collect();
             Ok(labels)
         }
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        async fn all_with_counts(
+            &self,
+            owner_id: Option<&str>,
+        ) -> anyhow::Result<Vec<LabelWithCount>> {
+            let store = self.read_store_ref();
+            let mut labels: Vec<LabelWithCount> = store
+                .values()
+                .map(|label| LabelWithCount {
+                    label: label.clone(),
+                    todo_count: self.count_todos(owner_id, label.id),
+                })
+                .collect();
+            labels.sort_by_key(|label| label.label.id);
+            Ok(labels)
+        }
+
+        async fn todo_count(&self, owner_id: Option<&str>, label_id: i32) -> anyhow::Result<i64> {
+            if !self.read_store_ref().contains_key(&label_id) {
+                return Err(RepositoryError::NotFound(label_id).into());
+            }
+            Ok(self.count_todos(owner_id, label_id))
+        }
+
+        async fn update(&self, id: i32, payload: UpdateLabel) -> anyhow::Result<Label> {
             let mut store = self.write_store_ref();
+            let label = match store.get(&id) {
+                Some(label) => Label {
+                    id,
+                    name: payload.name,
+                    color: payload.color.unwrap_or(label.color.clone()),
+                    description: payload.description.unwrap_or(label.description.clone()),
+                    archived: label.archived,
+                },
+                None => return Err(RepositoryError::NotFound(id).into()),
+            };
+            store.insert(id, label.clone());
+            if let Some(repository) = &self.todo_repository {
+                repository.refresh_label(&label);
+            }
+            Ok(label)
+        }
+
+        async fn delete(&self, id: i32, force: bool) -> anyhow::Result<()> {
+            let mut store = self.write_store_ref();
+            // in use by anyone, not only the caller
+            if !self.labeled_todos(id).is_empty() {
+                if !force {
+                    return Err(RepositoryError::InUse(id).into());
+                }
+                if let Some(repository) = &self.todo_repository {
+                    repository.detach_label(id);
+                }
+            }
             store.remove(&id);
             Ok(())
         }
+
+        async fn merge(&self, from: i32, into: i32) -> anyhow::Result<Label> {
+            let mut store = self.write_store_ref();
+            if !store.contains_key(&from) {
+                return Err(RepositoryError::NotFound(from).into());
+            }
+            let label = store
+                .get(&into)
+                .cloned()
+                .ok_or(RepositoryError::NotFound(into))?;
+            if let Some(repository) = &self.todo_repository {
+                repository.replace_label(from, &label);
+            }
+            store.remove(&from);
+            Ok(label)
+        }
+
+        async fn archive(&self, id: i32) -> anyhow::Result<Label> {
+            let mut store = self.write_store_ref();
+            let label = store.get_mut(&id).ok_or(RepositoryError::NotFound(id))?;
+            label.archived = true;
+            let label = label.clone();
+            if let Some(repository) = &self.todo_repository {
+                repository.refresh_label(&label);
+            }
+            Ok(label)
+        }
     }
 
     mod test {
@@ -186,26 +1160,150 @@ pub mod test_utils {
 
             // create
             let label = repository
-                .create(label_text.to_string())
+                .create(CreateLabel::new(label_text.to_string()))
                 .await
-                .expect(&format!("[create] failed to create label"));
+                .expect("[create] failed to create label");
             assert_eq!(label.name, label_text);
 
+            // create duplicate
+            let result = repository
+                .create(CreateLabel::new(label_text.to_string()))
+                .await;
+            assert!(result.is_err());
+
             // all
             let labels = repository
-                .all()
+                .all(LabelQuery::default())
                 .await
                 .expect("[all] failed to get all labels");
             assert_eq!(labels.len(), 1);
             assert_eq!(labels[0].name, label_text);
 
+            // update
+            let updated_text = "updated_label".to_string();
+            let updated_label = repository
+                .update(
+                    label.id,
+                    UpdateLabel {
+                        name: updated_text.clone(),
+                        color: None,
+                        description: None,
+                    },
+                )
+                .await
+                .expect("[update] failed to update label");
+            assert_eq!(updated_label, Label::new(label.id, updated_text));
+
+            // update not found
+            let result = repository
+                .update(
+                    100,
+                    UpdateLabel {
+                        name: "missing".to_string(),
+                        color: None,
+                        description: None,
+                    },
+                )
+                .await;
+            assert!(result.is_err());
+
             // delete
             repository
-                .delete(label.id)
+                .delete(label.id, false)
                 .await
                 .expect("[delete] failed to delete label");
-            let labels = repository.all().await.unwrap();
+            let labels = repository.all(LabelQuery::default()).await.unwrap();
             assert_eq!(labels.len(), 0);
         }
+
+        #[tokio::test]
+        async fn ids_are_not_reused_after_delete() {
+            let repository = LabelRepositoryForMemory::new();
+            for name in ["first", "second", "third"] {
+                repository
+                    .create(CreateLabel::new(name.to_string()))
+                    .await
+                    .unwrap();
+            }
+            repository.delete(2, false).await.unwrap();
+            let fourth = repository
+                .create(CreateLabel::new("fourth".to_string()))
+                .await
+                .unwrap();
+            assert_eq!(fourth, Label::new(4, "fourth".to_string()));
+
+            let mut labels = repository.all(LabelQuery::default()).await.unwrap();
+            labels.sort_by_key(|label| label.id);
+            assert_eq!(
+                labels,
+                vec![
+                    Label::new(1, "first".to_string()),
+                    Label::new(3, "third".to_string()),
+                    Label::new(4, "fourth".to_string()),
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn reject_duplicate_name_ignoring_case() {
+            let repository = LabelRepositoryForMemory::new();
+            let label = repository
+                .create(CreateLabel::new("Work".to_string()))
+                .await
+                .unwrap();
+            assert_eq!(label.name, "Work");
+            let err = repository
+                .create(CreateLabel::new("work".to_string()))
+                .await
+                .expect_err("name differing only in case must be a duplicate");
+            assert!(matches!(
+                err.downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Duplicate(1))
+            ));
+
+            let err = repository
+                .create(CreateLabel::new(" Work ".to_string()))
+                .await
+                .expect_err("name differing only in surrounding spaces must be a duplicate");
+            assert!(matches!(
+                err.downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Duplicate(1))
+            ));
+        }
+
+        #[tokio::test]
+        async fn search_and_paginate() {
+            let repository = LabelRepositoryForMemory::new();
+            for name in ["work", "Home", "homework", "errand"] {
+                repository
+                    .create(CreateLabel::new(name.to_string()))
+                    .await
+                    .unwrap();
+            }
+            let names = |labels: Vec<Label>| -> Vec<String> {
+                labels.into_iter().map(|label| label.name).collect()
+            };
+
+            let all = repository.all(LabelQuery::default()).await.unwrap();
+            assert_eq!(names(all), vec!["work", "Home", "homework", "errand"]);
+
+            let query = |q: Option<&str>, limit: Option<u32>, offset: u32| LabelQuery {
+                q: q.map(str::to_string),
+                limit,
+                offset,
+                include_archived: false,
+            };
+            let found = repository.all(query(Some("HOME"), None, 0)).await.unwrap();
+            assert_eq!(names(found), vec!["Home", "homework"]);
+            let found = repository
+                .all(query(Some("home"), Some(1), 1))
+                .await
+                .unwrap();
+            assert_eq!(names(found), vec!["homework"]);
+            let found = repository.all(query(None, Some(2), 1)).await.unwrap();
+            assert_eq!(names(found), vec!["Home", "homework"]);
+            let found = repository.all(query(Some("work"), None, 5)).await.unwrap();
+            assert!(found.is_empty());
+        }
     }
 }
@@ -1,18 +1,121 @@
-use super::{label::Label, RepositoryError};
+use super::{
+    acquire, begin, classify, deserialize_normalized, deserialize_nullable, label::Label,
+    normalize_whitespace, retry, timed, validate_name_length, validate_text_length, Pagination,
+    RepositoryError, RetryPolicy, DEFAULT_SLOW_QUERY,
+};
 use anyhow::Ok;
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
-use validator::Validate;
+use sqlx::{FromRow, PgExecutor, PgPool, Postgres, Transaction};
+use std::{collections::HashMap, future::Future, time::Duration};
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError, ValidationErrors};
 
 #[async_trait]
-pub trait TodoRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+pub trait TodoRepository: std::marker::Send + std::marker::Sync + 'static {
     // anyhow::Result<Todo> を返すよう修正
-    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity>;
-    async fn find(&self, id: i32) -> anyhow::Result<TodoEntity>;
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>>;
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn create(
+        &self,
+        owner_id: Option<&str>,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn create_many(
+        &self,
+        owner_id: Option<&str>,
+        payloads: Vec<CreateTodo>,
+    ) -> anyhow::Result<Vec<TodoEntity>>;
+    /// Creates the todo once per `key`, repeating a key within `ttl` returns the todo created first.
+    async fn create_idempotent(
+        &self,
+        owner_id: Option<&str>,
+        key: String,
+        ttl: Duration,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn find(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity>;
+    /// Whether the live todo exists, without loading it and its labels like `find` does.
+    async fn exists(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<bool>;
+    /// Ids that do not exist are left out of the result.
+    async fn find_many(
+        &self,
+        owner_id: Option<&str>,
+        ids: Vec<i32>,
+    ) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn all(
+        &self,
+        owner_id: Option<&str>,
+        filter: TodoFilter,
+        sort: TodoSort,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn count(&self, owner_id: Option<&str>, filter: TodoFilter) -> anyhow::Result<i64>;
+    /// Number of todos that are not completed yet.
+    async fn open_count(&self, owner_id: Option<&str>) -> anyhow::Result<i64>;
+    async fn stats(&self, owner_id: Option<&str>) -> anyhow::Result<TodoStats>;
+    async fn by_label(
+        &self,
+        owner_id: Option<&str>,
+        label_id: i32,
+    ) -> anyhow::Result<Vec<TodoEntity>>;
+    /// Direct subtasks of the todo, in manual order.
+    async fn children(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn update(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn replace(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        payload: ReplaceTodo,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn toggle(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity>;
+    /// Creates an open copy of the todo with its text, due date, priority and labels.
+    async fn duplicate(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity>;
+    async fn add_label(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        label_id: i32,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn remove_label(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        label_id: i32,
+    ) -> anyhow::Result<TodoEntity>;
+    /// Rewrites the position of every todo, `ids` must list each live todo exactly once.
+    async fn reorder(&self, owner_id: Option<&str>, ids: Vec<i32>) -> anyhow::Result<()>;
+    /// Returns the number of newly created associations.
+    async fn assign_label_many(
+        &self,
+        owner_id: Option<&str>,
+        label_id: i32,
+        todo_ids: Vec<i32>,
+    ) -> anyhow::Result<u64>;
+    /// Creates every todo and their missing labels at once, or nothing when one of them fails.
+    async fn import(
+        &self,
+        owner_id: Option<&str>,
+        items: Vec<ImportTodo>,
+    ) -> anyhow::Result<ImportSummary>;
+    /// Returns the number of todos whose state changed.
+    async fn set_all_completed(
+        &self,
+        owner_id: Option<&str>,
+        completed: bool,
+    ) -> anyhow::Result<u64>;
+    async fn delete(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<()>;
+    /// Deletes the todos among `ids`, unknown ids are skipped. Returns how many were deleted.
+    async fn delete_many(&self, owner_id: Option<&str>, ids: Vec<i32>) -> anyhow::Result<u64>;
+    async fn delete_completed(&self, owner_id: Option<&str>) -> anyhow::Result<u64>;
+    /// Removes every todo of the owner for good, deleted ones included, along with their
+    /// label associations. Labels themselves are kept. Returns how many todos were removed.
+    async fn purge(&self, owner_id: Option<&str>) -> anyhow::Result<u64>;
+    async fn restore(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, FromRow)]
@@ -20,8 +123,29 @@ pub struct TodoWithLabelFromRow {
     id: i32,
     text: String,
     completed: bool,
+    completed_at: Option<DateTime<Utc>>,
+    due_date: Option<DateTime<Utc>>,
+    priority: Priority,
+    position: i32,
+    version: i32,
+    parent_id: Option<i32>,
+    owner_id: Option<String>,
     label_id: Option<i32>,
     label_name: Option<String>,
+    label_color: Option<String>,
+    label_description: Option<String>,
+    label_archived: Option<bool>,
+}
+
+/// Stored values a partial update keeps for the fields it leaves out.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+struct TodoStateFromRow {
+    text: String,
+    completed: bool,
+    due_date: Option<DateTime<Utc>>,
+    priority: Priority,
+    parent_id: Option<i32>,
+    version: i32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, FromRow)]
@@ -31,24 +155,72 @@ struct TodoFromRow {
     completed: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
 pub struct TodoEntity {
     pub id: i32,
     pub text: String,
     pub completed: bool,
+    /// Set when the todo gets completed, cleared when it is reopened.
+    pub completed_at: Option<DateTime<Utc>>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub priority: Priority,
+    /// Manual ordering, see `POST /todos/reorder`.
+    pub position: i32,
+    /// Incremented on every update, see `UpdateTodo::version`.
+    pub version: i32,
+    /// Todo this one is a subtask of.
+    pub parent_id: Option<i32>,
+    /// Subject of the user the todo belongs to, `None` when created without authentication.
+    #[serde(skip)]
+    pub owner_id: Option<String>,
     pub labels: Vec<Label>,
 }
 
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Default,
+    sqlx::Type,
+    ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[repr(i16)]
+pub enum Priority {
+    Low = 0,
+    #[default]
+    Medium = 1,
+    High = 2,
+}
+
 fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
     rows.iter().fold(vec![], |mut acc: Vec<TodoEntity>, cur| {
+        let label = match (cur.label_id, &cur.label_name, &cur.label_color) {
+            (Some(id), Some(name), Some(color)) => Some(Label {
+                id,
+                name: name.clone(),
+                color: color.clone(),
+                description: cur.label_description.clone(),
+                archived: cur.label_archived.unwrap_or_default(),
+            }),
+            (Some(label_id), _, _) => {
+                tracing::warn!("skip label {} of todo {} without a name", label_id, cur.id);
+                None
+            }
+            (None, _, _) => None,
+        };
         // 同一id のtodoを畳み込み
         // 同一id の場合、Labelを作成し`labels`にpush
         if let Some(todo) = acc.iter_mut().find(|todo| todo.id == cur.id) {
-            if let Some(label_id) = cur.label_id {
-                let label = Label {
-                    id: label_id,
-                    name: cur.label_name.clone().unwrap(),
-                };
+            // 重複した行のLabelは無視
+            if let Some(label) = label.filter(|label| !todo.labels.iter().any(|l| l.id == label.id))
+            {
                 todo.labels.push(label);
             }
         // 同一id がない場合、新規todoを作成し`acc`にpush
@@ -57,13 +229,16 @@ fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
                 id: cur.id,
                 text: cur.text.clone(),
                 completed: cur.completed,
+                completed_at: cur.completed_at,
+                due_date: cur.due_date,
+                priority: cur.priority,
+                position: cur.position,
+                version: cur.version,
+                parent_id: cur.parent_id,
+                owner_id: cur.owner_id.clone(),
                 labels: vec![],
             };
-            if let Some(label_id) = cur.label_id {
-                let label = Label {
-                    id: label_id,
-                    name: cur.label_name.clone().unwrap(),
-                };
+            if let Some(label) = label {
                 todo.labels.push(label);
             }
             acc.push(todo);
@@ -72,224 +247,1621 @@ fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
     })
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+/// Upper bound of labels a single todo can carry.
+pub const MAX_LABELS_PER_TODO: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
+#[validate(schema(function = "validate_due_date"))]
 pub struct CreateTodo {
-    #[validate(length(min = 1, message = "Can not be empty"))]
-    #[validate(length(max = 100, message = "Text is too long"))]
+    #[serde(deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_text_length")]
+    text: String,
+    #[serde(default)]
+    #[validate(custom = "validate_label_count")]
+    label_ids: Vec<i32>,
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    allow_past_due: bool,
+    #[serde(default)]
+    priority: Priority,
+    parent_id: Option<i32>,
+}
+
+impl CreateTodo {
+    /// Shorthand for tests, code builds payloads with `builder` so that they are validated.
+    #[cfg(test)]
+    pub fn new(text: String, label_ids: Vec<i32>) -> Self {
+        Self {
+            text: normalize_whitespace(&text),
+            label_ids,
+            due_date: None,
+            allow_past_due: false,
+            priority: Priority::default(),
+            parent_id: None,
+        }
+    }
+
+    /// Starts a payload built in code rather than deserialized from a request.
+    pub fn builder() -> CreateTodoBuilder {
+        CreateTodoBuilder::default()
+    }
+
+    /// Payload creating a copy of `todo`, which starts out open and at the top level.
+    fn copy_of(todo: &TodoEntity) -> Result<Self, ValidationErrors> {
+        CreateTodo::builder()
+            .text(todo.text.clone())
+            .label_ids(todo.labels.iter().map(|label| label.id).collect())
+            .due_date(todo.due_date)
+            // copying an overdue todo keeps its due date
+            .allow_past_due(true)
+            .priority(todo.priority)
+            .build()
+    }
+}
+
+/// See `CreateTodo::builder`.
+#[derive(Debug, Clone, Default)]
+pub struct CreateTodoBuilder {
     text: String,
     label_ids: Vec<i32>,
+    due_date: Option<DateTime<Utc>>,
+    allow_past_due: bool,
+    priority: Priority,
+}
+
+impl CreateTodoBuilder {
+    pub fn text(self, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..self
+        }
+    }
+
+    pub fn label_ids(self, label_ids: Vec<i32>) -> Self {
+        Self { label_ids, ..self }
+    }
+
+    pub fn due_date(self, due_date: Option<DateTime<Utc>>) -> Self {
+        Self { due_date, ..self }
+    }
+
+    pub fn allow_past_due(self, allow_past_due: bool) -> Self {
+        Self {
+            allow_past_due,
+            ..self
+        }
+    }
+
+    pub fn priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Normalizes the text like request payloads and fails with their validation errors.
+    pub fn build(self) -> Result<CreateTodo, ValidationErrors> {
+        let payload = CreateTodo {
+            text: normalize_whitespace(&self.text),
+            label_ids: self.label_ids,
+            due_date: self.due_date,
+            allow_past_due: self.allow_past_due,
+            priority: self.priority,
+            parent_id: None,
+        };
+        payload.validate()?;
+        Result::Ok(payload)
+    }
+}
+
+fn validate_due_date(payload: &CreateTodo) -> Result<(), ValidationError> {
+    match payload.due_date {
+        Some(due_date) if !payload.allow_past_due && due_date < Utc::now() => {
+            let mut error = ValidationError::new("past_due_date");
+            error.message = Some("Due date is in the past".into());
+            Err(error)
+        }
+        _ => Result::Ok(()),
+    }
+}
+
+fn validate_label_count(label_ids: &[i32]) -> Result<(), ValidationError> {
+    if label_ids.len() > MAX_LABELS_PER_TODO {
+        let mut error = ValidationError::new("too_many_labels");
+        error.message =
+            Some(format!("Can not have more than {} labels", MAX_LABELS_PER_TODO).into());
+        return Err(error);
+    }
+    Result::Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
 pub struct UpdateTodo {
-    #[validate(length(min = 1, message = "Can not be empty"))]
-    #[validate(length(max = 100, message = "Text is too long"))]
+    #[serde(default, deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_text_length")]
     text: Option<String>,
     completed: Option<bool>,
+    #[validate(custom = "validate_label_count")]
     label_ids: Option<Vec<i32>>,
+    /// Omit to keep the current due date, send `null` to clear it.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    due_date: Option<Option<DateTime<Utc>>>,
+    priority: Option<Priority>,
+    /// Omit to keep the current parent, send `null` to make it a top-level todo.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    parent_id: Option<Option<i32>>,
+    /// Only update when the stored version still matches, otherwise fail with 412.
+    version: Option<i32>,
+    /// How `label_ids` is applied, taken from the query rather than the body.
+    #[serde(skip)]
+    label_mode: LabelMode,
+}
+
+impl UpdateTodo {
+    pub fn with_label_mode(self, label_mode: LabelMode) -> Self {
+        Self { label_mode, ..self }
+    }
+}
+
+/// How `PATCH /todos/:id?label_mode=` applies `label_ids` to the labels of the todo.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelMode {
+    /// The todo ends up with exactly `label_ids`.
+    #[default]
+    Replace,
+    /// `label_ids` are attached, labels already there are kept.
+    Add,
+    /// `label_ids` are detached, unknown ids are ignored.
+    Remove,
+}
+
+/// Payload of `PUT /todos/:id`, every field overwrites the stored value.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
+pub struct ReplaceTodo {
+    #[serde(deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_text_length")]
+    text: String,
+    completed: bool,
+    #[validate(custom = "validate_label_count")]
+    label_ids: Vec<i32>,
+    #[serde(default)]
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    priority: Priority,
+}
+
+/// Payload of `PATCH /todos/:id/status`, other fields are rejected rather than ignored.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateTodoStatus {
+    pub completed: bool,
+}
+
+impl From<UpdateTodoStatus> for UpdateTodo {
+    fn from(status: UpdateTodoStatus) -> Self {
+        Self {
+            text: None,
+            completed: Some(status.completed),
+            label_ids: None,
+            due_date: None,
+            priority: None,
+            parent_id: None,
+            version: None,
+            label_mode: LabelMode::default(),
+        }
+    }
+}
+
+/// Item of `POST /todos/import`, labels are referenced by name and created when missing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate, ToSchema)]
+pub struct ImportTodo {
+    #[serde(deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_text_length")]
+    text: String,
+    #[serde(default)]
+    completed: bool,
+    #[serde(default, deserialize_with = "deserialize_normalized")]
+    #[validate(custom = "validate_label_names")]
+    label_names: Vec<String>,
+}
+
+fn validate_label_names(label_names: &[String]) -> Result<(), ValidationError> {
+    if label_names.len() > MAX_LABELS_PER_TODO {
+        let mut error = ValidationError::new("too_many_labels");
+        error.message =
+            Some(format!("Can not have more than {} labels", MAX_LABELS_PER_TODO).into());
+        return Err(error);
+    }
+    // same bounds as `CreateLabel::name`
+    if label_names
+        .iter()
+        .any(|name| validate_name_length(name).is_err())
+    {
+        let mut error = ValidationError::new("invalid_label_name");
+        error.message = Some("Label names must have 1 to 20 characters".into());
+        return Err(error);
+    }
+    Result::Ok(())
+}
+
+/// Counts over the todos of an owner, as returned by `GET /stats`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, FromRow, ToSchema)]
+pub struct TodoStats {
+    pub total: i64,
+    pub completed: i64,
+    pub open: i64,
+    /// Open todos whose due date has passed.
+    pub overdue: i64,
+    /// Distinct labels carried by the todos.
+    pub labels: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub struct ImportSummary {
+    pub created: u64,
+    pub labels_created: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TodoFilter {
+    completed: Option<bool>,
+    q: Option<String>,
+    #[serde(default)]
+    overdue: bool,
+    /// Comma separated label ids, e.g. `label_ids=1,2`.
+    #[serde(default, deserialize_with = "deserialize_id_list")]
+    #[param(value_type = Option<String>)]
+    label_ids: Vec<i32>,
+    /// Whether todos need `all` of `label_ids` or `any` of them.
+    #[serde(default, rename = "match")]
+    label_match: LabelMatch,
+    /// RFC 3339 timestamp, only todos created at or after it.
+    created_after: Option<DateTime<Utc>>,
+    /// RFC 3339 timestamp, only todos created at or before it.
+    created_before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelMatch {
+    All,
+    #[default]
+    Any,
+}
+
+fn deserialize_id_list<'de, D>(deserializer: D) -> Result<Vec<i32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let list = String::deserialize(deserializer)?;
+    list.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Sort order of `GET /todos?sort=`, where a leading `-` means descending.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoSort {
+    #[serde(rename = "id")]
+    IdAsc,
+    #[default]
+    #[serde(rename = "-id")]
+    IdDesc,
+    #[serde(rename = "text")]
+    TextAsc,
+    #[serde(rename = "-text")]
+    TextDesc,
+    #[serde(rename = "created_at")]
+    CreatedAtAsc,
+    #[serde(rename = "-created_at")]
+    CreatedAtDesc,
+    Priority,
+    Position,
+}
+
+impl TodoSort {
+    // every clause ends with the id so that rows of the same todo stay adjacent
+    fn order_by(&self) -> &'static str {
+        match self {
+            TodoSort::IdAsc => "todos.id asc",
+            TodoSort::IdDesc => "todos.id desc",
+            TodoSort::TextAsc => "todos.text asc, todos.id asc",
+            TodoSort::TextDesc => "todos.text desc, todos.id desc",
+            TodoSort::CreatedAtAsc => "todos.created_at asc, todos.id asc",
+            TodoSort::CreatedAtDesc => "todos.created_at desc, todos.id desc",
+            TodoSort::Priority => "todos.priority desc, todos.id desc",
+            TodoSort::Position => "todos.position asc, todos.id asc",
+        }
+    }
+}
+
+/// Whether `ids` lists every id of `known_ids` exactly once.
+fn same_ids(ids: &[i32], known_ids: &mut [i32]) -> bool {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    known_ids.sort_unstable();
+    ids == known_ids
+}
+
+fn unknown_label_ids(label_ids: &[i32], known_ids: &[i32]) -> Vec<i32> {
+    let mut unknown_ids: Vec<i32> = label_ids
+        .iter()
+        .filter(|id| !known_ids.contains(id))
+        .copied()
+        .collect();
+    unknown_ids.sort_unstable();
+    unknown_ids.dedup();
+    unknown_ids
+}
+
+impl TodoFilter {
+    fn query(&self) -> Option<&str> {
+        self.q.as_deref().filter(|q| !q.is_empty())
+    }
+
+    fn label_ids(&self) -> Vec<i32> {
+        let mut label_ids = self.label_ids.clone();
+        label_ids.sort_unstable();
+        label_ids.dedup();
+        label_ids
+    }
 }
 
+/// `IdempotencyKey` only lets visible ASCII through, so the key is whatever follows the last
+/// space and keys of different owners stay apart.
+fn owner_scoped_key(owner_id: Option<&str>, key: String) -> String {
+    match owner_id {
+        Some(owner_id) => format!("{} {}", owner_id, key),
+        None => key,
+    }
+}
+
+/// New `completed_at` when `completed` is set to `$2`: set on completion,
+/// cleared on reopening and kept when the flag does not change.
+const COMPLETED_AT_TRANSITION: &str =
+    "CASE WHEN completed = $2 THEN completed_at WHEN $2 THEN now() ELSE NULL END";
+
 #[derive(Debug, Clone)]
 pub struct TodoRepositoryForDb {
     pub pool: PgPool,
+    slow_query: Duration,
+    retry: RetryPolicy,
 }
 
 impl TodoRepositoryForDb {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            slow_query: DEFAULT_SLOW_QUERY,
+            retry: RetryPolicy::default(),
+        }
     }
-}
 
-#[async_trait]
-impl TodoRepository for TodoRepositoryForDb {
-    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
+    /// Methods running longer than `threshold` are logged as warnings.
+    pub fn with_slow_query(self, threshold: Duration) -> Self {
+        Self {
+            slow_query: threshold,
+            ..self
+        }
+    }
+
+    /// Methods failing with a connection error are run again following `policy`.
+    pub fn with_retry(self, policy: RetryPolicy) -> Self {
+        Self {
+            retry: policy,
+            ..self
+        }
+    }
+
+    /// Runs every attempt of `operation` under `retry`, timing each one. An attempt runs on a
+    /// connection from `acquire` or a transaction from `begin`, anything following its commit
+    /// belongs after `timed`.
+    /// Other `timed` methods are not called from `operation`, so that retries do not multiply.
+    async fn timed<T, F, Fut>(&self, method: &str, mut operation: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let label = format!("todos.{}", method);
+        retry(self.retry, &label, || {
+            timed(&label, self.slow_query, operation())
+        })
+        .await
+        .map_err(classify)
+    }
+
+    async fn insert(
+        tx: &mut Transaction<'_, Postgres>,
+        owner_id: Option<&str>,
+        payload: CreateTodo,
+    ) -> anyhow::Result<i32> {
+        payload.validate()?;
+        if let Some(parent_id) = payload.parent_id {
+            Self::check_parent(&mut *tx, owner_id, parent_id, None).await?;
+        }
         let row = sqlx::query_as::<_, TodoFromRow>(
             r#"
-            insert into todos (text, completed)
-            values ($1, false)
+            insert into todos (text, completed, due_date, priority, position, parent_id, owner_id)
+            values ($1, false, $2, $3, (select coalesce(max(position), 0) + 1 from todos), $4, $5)
             returning *;
             "#,
         )
         .bind(payload.text.clone())
-        .fetch_one(&self.pool)
+        .bind(payload.due_date)
+        .bind(payload.priority)
+        .bind(payload.parent_id)
+        .bind(owner_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        Self::attach_labels(tx, row.id, payload.label_ids).await?;
+
+        Ok(row.id)
+    }
+
+    async fn attach_labels(
+        tx: &mut Transaction<'_, Postgres>,
+        todo_id: i32,
+        label_ids: Vec<i32>,
+    ) -> anyhow::Result<()> {
+        let known_ids = sqlx::query_scalar::<_, i32>(
+            r#"
+            select id from labels where id = any($1)
+            "#,
+        )
+        .bind(&label_ids)
+        .fetch_all(&mut *tx)
         .await?;
+        let unknown_ids = unknown_label_ids(&label_ids, &known_ids);
+        if !unknown_ids.is_empty() {
+            return Err(RepositoryError::UnknownLabels(unknown_ids).into());
+        }
 
         sqlx::query(
             r#"
             insert into todo_labels (todo_id, label_id)
-            select $1, id 
+            select $1, id
             from unnest($2) as t(id)
+            on conflict do nothing
+            "#,
+        )
+        .bind(todo_id)
+        .bind(label_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fails unless `parent_id` is a live todo of the owner that `id`, when given, is not an ancestor of.
+    async fn check_parent(
+        executor: impl PgExecutor<'_>,
+        owner_id: Option<&str>,
+        parent_id: i32,
+        id: Option<i32>,
+    ) -> anyhow::Result<()> {
+        // `union` stops at rows already seen, so a broken chain can not loop forever
+        let (exists, cycle) = sqlx::query_as::<_, (bool, bool)>(
+            r#"
+            with recursive ancestors (id, parent_id) as (
+                select id, parent_id from todos
+                where id = $1 and deleted_at is null and owner_id is not distinct from $3
+                union
+                select todos.id, todos.parent_id from todos
+                join ancestors on todos.id = ancestors.parent_id
+            )
+            select exists(select 1 from ancestors where id = $1),
+                   exists(select 1 from ancestors where id = $2)
+            "#,
+        )
+        .bind(parent_id)
+        .bind(id)
+        .bind(owner_id)
+        .fetch_one(executor)
+        .await?;
+        if !exists {
+            return Err(RepositoryError::UnknownParent(parent_id).into());
+        }
+        if cycle {
+            return Err(RepositoryError::ParentCycle(parent_id).into());
+        }
+
+        Ok(())
+    }
+
+    async fn ensure_label_exists(&self, label_id: i32) -> anyhow::Result<()> {
+        let mut conn = acquire(&self.pool).await?;
+        let exists = sqlx::query_scalar::<_, bool>(
+            r#"
+            select exists(select 1 from labels where id = $1)
             "#,
         )
-        .bind(row.id)
-        .bind(payload.label_ids)
-        .execute(&self.pool)
+        .bind(label_id)
+        .fetch_one(&mut conn)
         .await?;
+        if !exists {
+            return Err(RepositoryError::NotFound(label_id).into());
+        }
+
+        Ok(())
+    }
 
-        tx.commit().await?;
+    /// Calls `exists`, so it must not run inside `timed`.
+    async fn ensure_exists(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<()> {
+        if !self.exists(owner_id, id).await? {
+            return Err(RepositoryError::NotFound(id).into());
+        }
 
-        let todo = self.find(row.id).await?;
-        Ok(todo)
+        Ok(())
     }
 
-    async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
+    /// The query behind `find`, run once per attempt.
+    async fn fetch(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+        let mut conn = acquire(&self.pool).await?;
         let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
             r#"
-            select todos.*, labels.id as label_id, labels.name as label_name
+            select todos.*, labels.id as label_id, labels.name as label_name, labels.color as label_color,
+            labels.description as label_description, labels.archived as label_archived
             from todos
             left outer join todo_labels tl on todos.id = tl.todo_id
             left outer join labels on labels.id = tl.label_id 
-            where todos.id = $1;
+            where todos.id = $1 and todos.deleted_at is null
+            and todos.owner_id is not distinct from $2;
             "#,
         )
         .bind(id)
-        .fetch_all(&self.pool)
+        .bind(owner_id)
+        .fetch_all(&mut conn)
         .await
         .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
+            sqlx::Error::RowNotFound => RepositoryError::NotFound(id).into(),
+            _ => anyhow::Error::from(e),
         })?;
         let todos = fold_entities(items);
         let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
 
         Ok(todo.clone())
     }
+}
 
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
-        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
-            r#"
-            select todos.*, labels.id as label_id, labels.name as label_name
-            from todos
-            left outer join todo_labels tl on todos.id = tl.todo_id
-            left outer join labels on labels.id = tl.label_id 
-            order by todos.id desc;
-            "#,
-        )
-        .fetch_all(&self.pool)
-        .await?;
+#[async_trait]
+impl TodoRepository for TodoRepositoryForDb {
+    async fn create(
+        &self,
+        owner_id: Option<&str>,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let id = self
+            .timed("create", || {
+                let payload = payload.clone();
+                async move {
+                    let mut tx = begin(&self.pool).await?;
+                    let id = Self::insert(&mut tx, owner_id, payload).await?;
+                    tx.commit().await?;
 
-        Ok(fold_entities(items))
+                    Ok(id)
+                }
+            })
+            .await?;
+        self.find(owner_id, id).await
     }
 
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
+    async fn create_many(
+        &self,
+        owner_id: Option<&str>,
+        payloads: Vec<CreateTodo>,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        let ids = self
+            .timed("create_many", || {
+                let payloads = payloads.clone();
+                async move {
+                    let mut tx = begin(&self.pool).await?;
+                    let mut ids = vec![];
+                    for payload in payloads {
+                        ids.push(Self::insert(&mut tx, owner_id, payload).await?);
+                    }
+                    tx.commit().await?;
 
-        // update todo
-        let old_todo = self.find(id).await?;
-        sqlx::query(
-            r#"
-            UPDATE todos SET text = $1, completed = $2 WHERE id = $3
-            RETURNING *
-            "#,
-        )
-        .bind(payload.text.unwrap_or(old_todo.text))
-        .bind(payload.completed.unwrap_or(old_todo.completed))
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
+                    Ok(ids)
+                }
+            })
+            .await?;
+        let mut todos = vec![];
+        for id in ids {
+            todos.push(self.find(owner_id, id).await?);
+        }
+        Ok(todos)
+    }
 
-        // update labels
-        if let Some(label_ids) = payload.label_ids {
-            sqlx::query(
+    async fn create_idempotent(
+        &self,
+        owner_id: Option<&str>,
+        key: String,
+        ttl: Duration,
+        payload: CreateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        let id = self
+            .timed("create_idempotent", || {
+                let key = key.clone();
+                let payload = payload.clone();
+                async move {
+                    let key = owner_scoped_key(owner_id, key);
+                    let mut tx = begin(&self.pool).await?;
+                    // serialize concurrent requests carrying the same key
+                    sqlx::query("select pg_advisory_xact_lock(hashtext($1))")
+                        .bind(&key)
+                        .execute(&mut tx)
+                        .await?;
+                    sqlx::query(
+                        r#"
+                        delete from idempotency_keys where created_at < now() - make_interval(secs => $1)
+                        "#,
+                    )
+                    .bind(ttl.as_secs_f64())
+                    .execute(&mut tx)
+                    .await?;
+                    let processed_id = sqlx::query_scalar::<_, i32>(
+                        r#"
+                        select todo_id from idempotency_keys where key = $1
+                        "#,
+                    )
+                    .bind(&key)
+                    .fetch_optional(&mut tx)
+                    .await?;
+                    let id = match processed_id {
+                        Some(id) => id,
+                        None => {
+                            let id = Self::insert(&mut tx, owner_id, payload).await?;
+                            sqlx::query(
+                                r#"
+                                insert into idempotency_keys (key, todo_id) values ($1, $2)
+                                "#,
+                            )
+                            .bind(&key)
+                            .bind(id)
+                            .execute(&mut tx)
+                            .await?;
+                            id
+                        }
+                    };
+                    tx.commit().await?;
+
+                    Ok(id)
+                }
+            })
+            .await?;
+        self.find(owner_id, id).await
+    }
+
+    async fn find(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+        self.timed("find", || self.fetch(owner_id, id)).await
+    }
+
+    async fn exists(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<bool> {
+        self.timed("exists", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let exists = sqlx::query_scalar::<_, bool>(
                 r#"
-                delete from todo_labels where todo_id=$1
+                select exists(
+                    select 1 from todos
+                    where id = $1 and deleted_at is null and owner_id is not distinct from $2
+                )
                 "#,
             )
             .bind(id)
-            .execute(&self.pool)
+            .bind(owner_id)
+            .fetch_one(&mut conn)
             .await?;
 
-            sqlx::query(
+            Ok(exists)
+        })
+        .await
+    }
+
+    async fn find_many(
+        &self,
+        owner_id: Option<&str>,
+        ids: Vec<i32>,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        self.timed("find_many", || {
+            let ids = ids.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                let sql = format!(
+                    r#"
+                    select todos.*, labels.id as label_id, labels.name as label_name, labels.color as label_color,
+                    labels.description as label_description, labels.archived as label_archived
+                    from todos
+                    left outer join todo_labels tl on todos.id = tl.todo_id
+                    left outer join labels on labels.id = tl.label_id
+                    where todos.id = ANY($1) and todos.deleted_at is null
+                    and todos.owner_id is not distinct from $2
+                    order by {order_by};
+                    "#,
+                    order_by = TodoSort::default().order_by()
+                );
+                let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&sql)
+                    .bind(ids)
+                    .bind(owner_id)
+                    .fetch_all(&mut conn)
+                    .await?;
+
+                Ok(fold_entities(items))
+            }
+        })
+        .await
+    }
+
+    async fn all(
+        &self,
+        owner_id: Option<&str>,
+        filter: TodoFilter,
+        sort: TodoSort,
+        pagination: Pagination,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        self.timed("all", || {
+            let filter = filter.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                // `order_by` only yields fixed clauses, so no user input is interpolated
+                let sql = format!(
+                    r#"
+                    select todos.*, labels.id as label_id, labels.name as label_name, labels.color as label_color,
+                    labels.description as label_description, labels.archived as label_archived
+                    from (
+                        select * from todos
+                        where deleted_at is null
+                        and ($1::boolean is null or completed = $1)
+                        and ($2::text is null or text ilike '%' || $2 || '%')
+                        and (not $3 or (not completed and due_date < now()))
+                        and ($6::integer is null or id < $6)
+                        and owner_id is not distinct from $7
+                        and (cardinality($8::integer[]) = 0 or (
+                            select count(*) from todo_labels
+                            where todo_id = todos.id and label_id = any($8)
+                        ) >= case when $9 then cardinality($8::integer[]) else 1 end)
+                        and ($10::timestamptz is null or created_at >= $10)
+                        and ($11::timestamptz is null or created_at <= $11)
+                        order by {order_by}
+                        limit $4 offset $5
+                    ) todos
+                    left outer join todo_labels tl on todos.id = tl.todo_id
+                    left outer join labels on labels.id = tl.label_id 
+                    order by {order_by};
+                    "#,
+                    order_by = sort.order_by()
+                );
+                let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&sql)
+                    .bind(filter.completed)
+                    .bind(filter.query())
+                    .bind(filter.overdue)
+                    .bind(pagination.limit as i64)
+                    .bind(pagination.offset as i64)
+                    .bind(pagination.after)
+                    .bind(owner_id)
+                    .bind(filter.label_ids())
+                    .bind(filter.label_match == LabelMatch::All)
+                    .bind(filter.created_after)
+                    .bind(filter.created_before)
+                    .fetch_all(&mut conn)
+                    .await?;
+
+                Ok(fold_entities(items))
+            }
+        })
+        .await
+    }
+
+    async fn count(&self, owner_id: Option<&str>, filter: TodoFilter) -> anyhow::Result<i64> {
+        self.timed("count", || {
+            let filter = filter.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                let count = sqlx::query_scalar::<_, i64>(
+                    r#"
+                    select count(*) from todos
+                    where deleted_at is null
+                    and ($1::boolean is null or completed = $1)
+                    and ($2::text is null or text ilike '%' || $2 || '%')
+                    and (not $3 or (not completed and due_date < now()))
+                    and owner_id is not distinct from $4
+                    and (cardinality($5::integer[]) = 0 or (
+                        select count(*) from todo_labels
+                        where todo_id = todos.id and label_id = any($5)
+                    ) >= case when $6 then cardinality($5::integer[]) else 1 end)
+                    and ($7::timestamptz is null or created_at >= $7)
+                    and ($8::timestamptz is null or created_at <= $8);
+                    "#,
+                )
+                .bind(filter.completed)
+                .bind(filter.query())
+                .bind(filter.overdue)
+                .bind(owner_id)
+                .bind(filter.label_ids())
+                .bind(filter.label_match == LabelMatch::All)
+                .bind(filter.created_after)
+                .bind(filter.created_before)
+                .fetch_one(&mut conn)
+                .await?;
+
+                Ok(count)
+            }
+        })
+        .await
+    }
+
+    async fn open_count(&self, owner_id: Option<&str>) -> anyhow::Result<i64> {
+        self.timed("open_count", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let count = sqlx::query_scalar::<_, i64>(
                 r#"
-                insert into todo_labels (todo_id, label_id)
-                select $1, id
-                from unnest($2) as t(id)
+                select count(*) from todos
+                where completed = false and deleted_at is null
+                and owner_id is not distinct from $1;
                 "#,
             )
-            .bind(id)
-            .bind(label_ids)
-            .execute(&self.pool)
+            .bind(owner_id)
+            .fetch_one(&mut conn)
             .await?;
-        };
-
-        tx.commit().await?;
-        let todo = self.find(id).await?;
 
-        Ok(todo)
+            Ok(count)
+        })
+        .await
     }
 
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        let tx = self.pool.begin().await?;
-        // delete todo's label
-        sqlx::query(
-            r#"
-            DELETE FROM todo_labels WHERE todo_id = $1
-            "#,
-        )
-        .bind(id)
-        .execute(&self.pool)
+    async fn stats(&self, owner_id: Option<&str>) -> anyhow::Result<TodoStats> {
+        self.timed("stats", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let stats = sqlx::query_as::<_, TodoStats>(
+                r#"
+                select
+                    count(*) as total,
+                    count(*) filter (where completed) as completed,
+                    count(*) filter (where not completed) as open,
+                    count(*) filter (where not completed and due_date < now()) as overdue,
+                    (
+                        select count(distinct tl.label_id) from todo_labels tl
+                        join todos t on t.id = tl.todo_id
+                        where t.deleted_at is null and t.owner_id is not distinct from $1
+                    ) as labels
+                from todos
+                where deleted_at is null and owner_id is not distinct from $1;
+                "#,
+            )
+            .bind(owner_id)
+            .fetch_one(&mut conn)
+            .await?;
+
+            Ok(stats)
+        })
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
-        // delete todo
-        sqlx::query(
-            r#"
-            DELETE FROM todos WHERE id = $1
-            "#,
-        )
-        .bind(id)
-        .execute(&self.pool)
+    }
+
+    async fn by_label(
+        &self,
+        owner_id: Option<&str>,
+        label_id: i32,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        self.timed("by_label", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            // 対象のtodoが持つ全てのLabelを返すため、絞り込みはサブクエリで行う
+            let sql = format!(
+                r#"
+                select todos.*, labels.id as label_id, labels.name as label_name, labels.color as label_color,
+                labels.description as label_description, labels.archived as label_archived
+                from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+                where todos.deleted_at is null
+                and todos.id in (select todo_id from todo_labels where label_id = $1)
+                and todos.owner_id is not distinct from $2
+                order by {order_by};
+                "#,
+                order_by = TodoSort::default().order_by()
+            );
+            let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&sql)
+                .bind(label_id)
+                .bind(owner_id)
+                .fetch_all(&mut conn)
+                .await?;
+
+            Ok(fold_entities(items))
+        })
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+    }
 
-        tx.commit().await?;
+    async fn children(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        self.ensure_exists(owner_id, id).await?;
+        self.timed("children", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let sql = format!(
+                r#"
+                select todos.*, labels.id as label_id, labels.name as label_name, labels.color as label_color,
+                labels.description as label_description, labels.archived as label_archived
+                from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+                where todos.deleted_at is null and todos.parent_id = $1
+                and todos.owner_id is not distinct from $2
+                order by {order_by};
+                "#,
+                order_by = TodoSort::Position.order_by()
+            );
+            let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&sql)
+                .bind(id)
+                .bind(owner_id)
+                .fetch_all(&mut conn)
+                .await?;
 
-        Ok(())
+            Ok(fold_entities(items))
+        })
+        .await
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    async fn update(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        payload: UpdateTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.timed("update", || {
+            let payload = payload.clone();
+            async move {
+                payload.validate()?;
+                let mut tx = begin(&self.pool).await?;
 
-    #[test]
-    fn fold_entities_test() {
-        let label_1 = Label {
-            id: 1,
-            name: "label_1".to_string(),
-        };
-        let label_2 = Label {
-            id: 2,
-            name: "label_2".to_string(),
-        };
+                // the lock keeps the todo from being deleted or changed until the commit
+                let old_todo = sqlx::query_as::<_, TodoStateFromRow>(
+                    r#"
+                    SELECT text, completed, due_date, priority, parent_id, version FROM todos
+                    WHERE id = $1 AND owner_id IS NOT DISTINCT FROM $2 AND deleted_at IS NULL
+                    FOR UPDATE
+                    "#,
+                )
+                .bind(id)
+                .bind(owner_id)
+                .fetch_optional(&mut tx)
+                .await?
+                .ok_or(RepositoryError::NotFound(id))?;
+
+                // update todo
+                if let Some(Some(parent_id)) = payload.parent_id {
+                    Self::check_parent(&mut tx, owner_id, parent_id, Some(id)).await?;
+                }
+                sqlx::query(&format!(
+                    r#"
+                    UPDATE todos SET text = $1, completed = $2, due_date = $3, priority = $4,
+                    completed_at = {completed_at}, version = version + 1, parent_id = $7
+                    WHERE id = $5 AND ($6::integer IS NULL OR version = $6)
+                    AND owner_id IS NOT DISTINCT FROM $8 AND deleted_at IS NULL
+                    RETURNING *
+                    "#,
+                    completed_at = COMPLETED_AT_TRANSITION
+                ))
+                .bind(payload.text.unwrap_or(old_todo.text))
+                .bind(payload.completed.unwrap_or(old_todo.completed))
+                .bind(payload.due_date.unwrap_or(old_todo.due_date))
+                .bind(payload.priority.unwrap_or(old_todo.priority))
+                .bind(id)
+                .bind(payload.version)
+                .bind(payload.parent_id.unwrap_or(old_todo.parent_id))
+                .bind(owner_id)
+                .fetch_optional(&mut tx)
+                .await?
+                .ok_or(RepositoryError::VersionConflict(old_todo.version))?;
+
+                // update labels
+                if let Some(label_ids) = payload.label_ids {
+                    if payload.label_mode != LabelMode::Add {
+                        // replacing detaches every label, removing only the given ones
+                        sqlx::query(
+                            r#"
+                            delete from todo_labels
+                            where todo_id = $1 and ($2 or label_id = any($3))
+                            "#,
+                        )
+                        .bind(id)
+                        .bind(payload.label_mode == LabelMode::Replace)
+                        .bind(&label_ids)
+                        .execute(&mut tx)
+                        .await?;
+                    }
+                    if payload.label_mode != LabelMode::Remove {
+                        Self::attach_labels(&mut tx, id, label_ids).await?;
+                    }
+                };
+
+                tx.commit().await?;
+
+                Ok(())
+            }
+        })
+        .await?;
+        self.find(owner_id, id).await
+    }
+
+    async fn replace(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        payload: ReplaceTodo,
+    ) -> anyhow::Result<TodoEntity> {
+        self.timed("replace", || {
+            let payload = payload.clone();
+            async move {
+                payload.validate()?;
+                let mut tx = begin(&self.pool).await?;
+
+                sqlx::query_as::<_, TodoFromRow>(&format!(
+                    r#"
+                    UPDATE todos SET text = $1, completed = $2, due_date = $3, priority = $4,
+                    completed_at = {completed_at}, version = version + 1
+                    WHERE id = $5 AND deleted_at IS NULL AND owner_id IS NOT DISTINCT FROM $6
+                    RETURNING *
+                    "#,
+                    completed_at = COMPLETED_AT_TRANSITION
+                ))
+                .bind(payload.text)
+                .bind(payload.completed)
+                .bind(payload.due_date)
+                .bind(payload.priority)
+                .bind(id)
+                .bind(owner_id)
+                .fetch_optional(&mut tx)
+                .await?
+                .ok_or(RepositoryError::NotFound(id))?;
+
+                sqlx::query(
+                    r#"
+                    delete from todo_labels where todo_id=$1
+                    "#,
+                )
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+                Self::attach_labels(&mut tx, id, payload.label_ids).await?;
+
+                tx.commit().await?;
+                Ok(())
+            }
+        })
+        .await?;
+        self.find(owner_id, id).await
+    }
+
+    async fn toggle(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+        self.timed("toggle", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            sqlx::query_as::<_, TodoFromRow>(
+                r#"
+                UPDATE todos SET completed = NOT completed,
+                completed_at = CASE WHEN completed THEN NULL ELSE now() END,
+                version = version + 1
+                WHERE id = $1 AND deleted_at IS NULL AND owner_id IS NOT DISTINCT FROM $2
+                RETURNING *
+                "#,
+            )
+            .bind(id)
+            .bind(owner_id)
+            .fetch_optional(&mut conn)
+            .await?
+            .ok_or(RepositoryError::NotFound(id))?;
+
+            Ok(())
+        })
+        .await?;
+        self.find(owner_id, id).await
+    }
+
+    async fn duplicate(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+        let source = self.find(owner_id, id).await?;
+        self.create(owner_id, CreateTodo::copy_of(&source)?).await
+    }
+
+    async fn add_label(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        label_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        self.ensure_exists(owner_id, id).await?;
+        self.timed("add_label", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            self.ensure_label_exists(label_id).await?;
+
+            sqlx::query(
+                r#"
+                insert into todo_labels (todo_id, label_id)
+                values ($1, $2)
+                on conflict do nothing
+                "#,
+            )
+            .bind(id)
+            .bind(label_id)
+            .execute(&mut conn)
+            .await?;
+
+            Ok(())
+        })
+        .await?;
+        self.find(owner_id, id).await
+    }
+
+    async fn remove_label(
+        &self,
+        owner_id: Option<&str>,
+        id: i32,
+        label_id: i32,
+    ) -> anyhow::Result<TodoEntity> {
+        self.ensure_exists(owner_id, id).await?;
+        self.timed("remove_label", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            self.ensure_label_exists(label_id).await?;
+
+            sqlx::query(
+                r#"
+                delete from todo_labels where todo_id = $1 and label_id = $2
+                "#,
+            )
+            .bind(id)
+            .bind(label_id)
+            .execute(&mut conn)
+            .await?;
+
+            Ok(())
+        })
+        .await?;
+        self.find(owner_id, id).await
+    }
+
+    async fn assign_label_many(
+        &self,
+        owner_id: Option<&str>,
+        label_id: i32,
+        todo_ids: Vec<i32>,
+    ) -> anyhow::Result<u64> {
+        self.timed("assign_label_many", || {
+            let todo_ids = todo_ids.clone();
+            async move {
+                self.ensure_label_exists(label_id).await?;
+
+                let mut tx = begin(&self.pool).await?;
+                let known_ids = sqlx::query_scalar::<_, i32>(
+                    r#"
+                    select id from todos
+                    where id = any($1) and deleted_at is null and owner_id is not distinct from $2
+                    "#,
+                )
+                .bind(&todo_ids)
+                .bind(owner_id)
+                .fetch_all(&mut tx)
+                .await?;
+                if let Some(id) = todo_ids.iter().find(|id| !known_ids.contains(id)) {
+                    return Err(RepositoryError::NotFound(*id).into());
+                }
+
+                let result = sqlx::query(
+                    r#"
+                    insert into todo_labels (todo_id, label_id)
+                    select distinct id, $2
+                    from unnest($1) as t(id)
+                    on conflict do nothing
+                    "#,
+                )
+                .bind(&todo_ids)
+                .bind(label_id)
+                .execute(&mut tx)
+                .await?;
+                tx.commit().await?;
+
+                Ok(result.rows_affected())
+            }
+        })
+        .await
+    }
+
+    async fn reorder(&self, owner_id: Option<&str>, ids: Vec<i32>) -> anyhow::Result<()> {
+        self.timed("reorder", || {
+            let ids = ids.clone();
+            async move {
+                let mut tx = begin(&self.pool).await?;
+                let mut known_ids = sqlx::query_scalar::<_, i32>(
+                    r#"
+                    select id from todos
+                    where deleted_at is null and owner_id is not distinct from $1
+                    for update
+                    "#,
+                )
+                .bind(owner_id)
+                .fetch_all(&mut tx)
+                .await?;
+                if !same_ids(&ids, &mut known_ids) {
+                    return Err(RepositoryError::OrderMismatch.into());
+                }
+
+                sqlx::query(
+                    r#"
+                    update todos set position = t.position::integer
+                    from unnest($1::integer[]) with ordinality as t(id, position)
+                    where todos.id = t.id
+                    "#,
+                )
+                .bind(&ids)
+                .execute(&mut tx)
+                .await?;
+                tx.commit().await?;
+
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    async fn import(
+        &self,
+        owner_id: Option<&str>,
+        items: Vec<ImportTodo>,
+    ) -> anyhow::Result<ImportSummary> {
+        self.timed("import", || {
+            let items = items.clone();
+            async move {
+                for item in &items {
+                    item.validate()?;
+                }
+                let mut tx = begin(&self.pool).await?;
+
+                // label ids by lowercase name, names are unique ignoring case
+                let mut label_ids: HashMap<String, i32> = HashMap::new();
+                let mut labels_created = 0;
+                for name in items.iter().flat_map(|item| &item.label_names) {
+                    if label_ids.contains_key(&name.to_lowercase()) {
+                        continue;
+                    }
+                    let existing_id = sqlx::query_scalar::<_, i32>(
+                        r#"
+                        select id from labels where lower(name) = lower($1)
+                        "#,
+                    )
+                    .bind(name)
+                    .fetch_optional(&mut tx)
+                    .await?;
+                    let id = match existing_id {
+                        Some(id) => id,
+                        None => {
+                            labels_created += 1;
+                            sqlx::query_scalar::<_, i32>(
+                                r#"
+                                insert into labels (name) values ($1) returning id
+                                "#,
+                            )
+                            .bind(name)
+                            .fetch_one(&mut tx)
+                            .await?
+                        }
+                    };
+                    label_ids.insert(name.to_lowercase(), id);
+                }
+
+                let created = items.len() as u64;
+                for item in items {
+                    let ids = item
+                        .label_names
+                        .iter()
+                        .map(|name| label_ids[&name.to_lowercase()])
+                        .collect();
+                    let payload = CreateTodo::builder()
+                        .text(item.text)
+                        .label_ids(ids)
+                        .build()?;
+                    let id = Self::insert(&mut tx, owner_id, payload).await?;
+                    if item.completed {
+                        sqlx::query(
+                            r#"
+                            update todos set completed = true, completed_at = now() where id = $1
+                            "#,
+                        )
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await?;
+                    }
+                }
+                tx.commit().await?;
+
+                Ok(ImportSummary {
+                    created,
+                    labels_created,
+                })
+            }
+        })
+        .await
+    }
+
+    async fn delete(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<()> {
+        self.timed("delete", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let result = sqlx::query(
+                r#"
+                UPDATE todos SET deleted_at = now()
+                WHERE id = $1 AND deleted_at IS NULL AND owner_id IS NOT DISTINCT FROM $2
+                "#,
+            )
+            .bind(id)
+            .bind(owner_id)
+            .execute(&mut conn)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(RepositoryError::NotFound(id).into());
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_many(&self, owner_id: Option<&str>, ids: Vec<i32>) -> anyhow::Result<u64> {
+        self.timed("delete_many", || {
+            let ids = ids.clone();
+            async move {
+                let mut conn = acquire(&self.pool).await?;
+                // a single statement, so either all of the todos are deleted or none
+                let result = sqlx::query(
+                    r#"
+                    UPDATE todos SET deleted_at = now()
+                    WHERE id = ANY($1) AND deleted_at IS NULL AND owner_id IS NOT DISTINCT FROM $2
+                    "#,
+                )
+                .bind(ids)
+                .bind(owner_id)
+                .execute(&mut conn)
+                .await?;
+
+                Ok(result.rows_affected())
+            }
+        })
+        .await
+    }
+
+    async fn set_all_completed(
+        &self,
+        owner_id: Option<&str>,
+        completed: bool,
+    ) -> anyhow::Result<u64> {
+        self.timed("set_all_completed", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let result = sqlx::query(
+                r#"
+                UPDATE todos SET completed = $1,
+                completed_at = CASE WHEN $1 THEN now() ELSE NULL END,
+                version = version + 1
+                WHERE completed <> $1 AND deleted_at IS NULL AND owner_id IS NOT DISTINCT FROM $2
+                "#,
+            )
+            .bind(completed)
+            .bind(owner_id)
+            .execute(&mut conn)
+            .await?;
+
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    async fn delete_completed(&self, owner_id: Option<&str>) -> anyhow::Result<u64> {
+        self.timed("delete_completed", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let result = sqlx::query(
+                r#"
+                UPDATE todos SET deleted_at = now()
+                WHERE completed AND deleted_at IS NULL AND owner_id IS NOT DISTINCT FROM $1
+                "#,
+            )
+            .bind(owner_id)
+            .execute(&mut conn)
+            .await?;
+
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    async fn purge(&self, owner_id: Option<&str>) -> anyhow::Result<u64> {
+        self.timed("purge", || async move {
+            let mut tx = begin(&self.pool).await?;
+            sqlx::query(
+                r#"
+                DELETE FROM todo_labels
+                WHERE todo_id IN (SELECT id FROM todos WHERE owner_id IS NOT DISTINCT FROM $1)
+                "#,
+            )
+            .bind(owner_id)
+            .execute(&mut tx)
+            .await?;
+            // idempotency keys go along through `ON DELETE CASCADE`
+            let result = sqlx::query("DELETE FROM todos WHERE owner_id IS NOT DISTINCT FROM $1")
+                .bind(owner_id)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    async fn restore(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+        self.timed("restore", || async move {
+            let mut conn = acquire(&self.pool).await?;
+            let result = sqlx::query(
+                r#"
+                UPDATE todos SET deleted_at = NULL
+                WHERE id = $1 AND deleted_at IS NOT NULL AND owner_id IS NOT DISTINCT FROM $2
+                "#,
+            )
+            .bind(id)
+            .bind(owner_id)
+            .execute(&mut conn)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(RepositoryError::NotFound(id).into());
+            }
+
+            Ok(())
+        })
+        .await?;
+        self.find(owner_id, id).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fold_entities_skips_label_without_name() {
+        let row = vec![TodoWithLabelFromRow {
+            id: 1,
+            text: "todo_1".to_string(),
+            completed: false,
+            completed_at: None,
+            due_date: None,
+            priority: Priority::Medium,
+            position: 1,
+            version: 1,
+            parent_id: None,
+            owner_id: None,
+            label_id: Some(1),
+            label_name: None,
+            label_color: None,
+            label_description: None,
+            label_archived: None,
+        }];
+        let res = fold_entities(row);
+        assert_eq!(
+            res,
+            vec![TodoEntity {
+                id: 1,
+                text: "todo_1".to_string(),
+                completed: false,
+                completed_at: None,
+                due_date: None,
+                priority: Priority::Medium,
+                position: 1,
+                version: 1,
+                parent_id: None,
+                owner_id: None,
+                labels: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn fold_entities_deduplicates_labels() {
+        let label_1 = Label {
+            id: 1,
+            name: "label_1".to_string(),
+            color: "#cccccc".to_string(),
+            description: None,
+            archived: false,
+        };
+        let row = TodoWithLabelFromRow {
+            id: 1,
+            text: "todo_1".to_string(),
+            completed: false,
+            completed_at: None,
+            due_date: None,
+            priority: Priority::Medium,
+            position: 1,
+            version: 1,
+            parent_id: None,
+            owner_id: None,
+            label_id: Some(label_1.id),
+            label_name: Some(label_1.name.clone()),
+            label_color: Some(label_1.color.clone()),
+            label_description: None,
+            label_archived: None,
+        };
+        let res = fold_entities(vec![row.clone(), row.clone(), row]);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].labels, vec![label_1]);
+    }
+
+    #[test]
+    fn fold_entities_test() {
+        let label_1 = Label {
+            id: 1,
+            name: "label_1".to_string(),
+            color: "#cccccc".to_string(),
+            description: None,
+            archived: false,
+        };
+        let label_2 = Label {
+            id: 2,
+            name: "label_2".to_string(),
+            color: "#cccccc".to_string(),
+            description: None,
+            archived: false,
+        };
 
         let row = vec![
             TodoWithLabelFromRow {
                 id: 1,
                 text: "todo_1".to_string(),
                 completed: false,
+                completed_at: None,
+                due_date: None,
+                priority: Priority::Medium,
+                position: 1,
+                version: 1,
+                parent_id: None,
+                owner_id: None,
                 label_id: Some(label_1.id),
                 label_name: Some(label_1.name.clone()),
+                label_color: Some(label_1.color.clone()),
+                label_description: None,
+                label_archived: None,
             },
             TodoWithLabelFromRow {
                 id: 1,
                 text: "todo_1".to_string(),
                 completed: false,
+                completed_at: None,
+                due_date: None,
+                priority: Priority::Medium,
+                position: 1,
+                version: 1,
+                parent_id: None,
+                owner_id: None,
                 label_id: Some(label_2.id),
                 label_name: Some(label_2.name.clone()),
+                label_color: Some(label_2.color.clone()),
+                label_description: None,
+                label_archived: None,
             },
             TodoWithLabelFromRow {
                 id: 2,
                 text: "todo_2".to_string(),
                 completed: false,
+                completed_at: None,
+                due_date: None,
+                priority: Priority::Medium,
+                position: 2,
+                version: 1,
+                parent_id: None,
+                owner_id: None,
                 label_id: Some(label_1.id),
                 label_name: Some(label_1.name.clone()),
+                label_color: Some(label_1.color.clone()),
+                label_description: None,
+                label_archived: None,
             },
         ];
         let res = fold_entities(row);
@@ -300,18 +1872,60 @@ mod test {
                     id: 1,
                     text: "todo_1".to_string(),
                     completed: false,
+                    completed_at: None,
+                    due_date: None,
+                    priority: Priority::Medium,
+                    position: 1,
+                    version: 1,
+                    parent_id: None,
+                    owner_id: None,
                     labels: vec![label_1.clone(), label_2.clone()],
                 },
                 TodoEntity {
                     id: 2,
                     text: "todo_2".to_string(),
                     completed: false,
+                    completed_at: None,
+                    due_date: None,
+                    priority: Priority::Medium,
+                    position: 2,
+                    version: 1,
+                    parent_id: None,
+                    owner_id: None,
                     labels: vec![label_1.clone()],
                 },
             ]
         );
     }
 
+    #[test]
+    fn create_todo_builder_validates() {
+        let payload = CreateTodo::builder()
+            .text("  buy   milk ")
+            .label_ids(vec![1, 2])
+            .priority(Priority::High)
+            .build()
+            .expect("valid payload");
+        assert_eq!(
+            payload,
+            CreateTodo {
+                priority: Priority::High,
+                ..CreateTodo::new("buy milk".to_string(), vec![1, 2])
+            }
+        );
+
+        let errors = CreateTodo::builder()
+            .text("   ")
+            .build()
+            .expect_err("empty text must be rejected");
+        assert!(errors.field_errors().contains_key("text"));
+
+        let yesterday = Utc::now() - chrono::Duration::days(1);
+        let past_due = CreateTodo::builder().text("late").due_date(Some(yesterday));
+        assert!(past_due.clone().build().is_err());
+        assert!(past_due.allow_past_due(true).build().is_ok());
+    }
+
     #[cfg(feature = "database-test")]
     #[tokio::test]
     async fn crud_scenario() {
@@ -322,7 +1936,7 @@ mod test {
         let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
         let pool = PgPool::connect(&database_url)
             .await
-            .expect(&format!("failed to connect to db, url: [{}]", database_url));
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
 
         let label_name = "test label".to_string();
         let optional_label = sqlx::query_as::<_, Label>(
@@ -356,7 +1970,7 @@ mod test {
 
         // create
         let created_todo = repository
-            .create(CreateTodo::new(todo_text.clone(), vec![label_1.id]))
+            .create(None, CreateTodo::new(todo_text.clone(), vec![label_1.id]))
             .await
             .expect("[create] failed to create todo");
         assert_eq!(created_todo.text, todo_text);
@@ -365,49 +1979,245 @@ mod test {
 
         // find
         let found_todo = repository
-            .find(created_todo.id)
+            .find(None, created_todo.id)
             .await
             .expect("[find] failed to find todo");
         assert_eq!(found_todo, created_todo);
 
+        // find_many
+        let found_todos = repository
+            .find_many(None, vec![created_todo.id, -1])
+            .await
+            .expect("[find_many] failed to find todos");
+        assert_eq!(found_todos, vec![created_todo.clone()]);
+
         // all
         let todos = repository
-            .all()
+            .all(
+                None,
+                TodoFilter::default(),
+                TodoSort::default(),
+                Pagination::default(),
+            )
             .await
             .expect("[all] failed to get all todos");
         assert_eq!(*todos.first().unwrap(), created_todo.clone());
 
+        // all after a cursor
+        let todos = repository
+            .all(
+                None,
+                TodoFilter::default(),
+                TodoSort::IdDesc,
+                Pagination {
+                    limit: 1,
+                    after: Some(created_todo.id + 1),
+                    ..Pagination::default()
+                },
+            )
+            .await
+            .expect("[all] failed to get todos after cursor");
+        assert_eq!(todos, vec![created_todo.clone()]);
+
+        // by_label
+        let todos = repository
+            .by_label(None, label_1.id)
+            .await
+            .expect("[by_label] failed to get todos by label");
+        assert!(todos.contains(&created_todo));
+        assert!(todos
+            .iter()
+            .all(|todo| todo.labels.iter().any(|label| label.id == label_1.id)));
+
+        // assign_label_many
+        let other_todo = repository
+            .create(None, CreateTodo::new(todo_text.clone(), vec![]))
+            .await
+            .expect("[create] failed to create todo");
+        let assigned = repository
+            .assign_label_many(None, label_1.id, vec![created_todo.id, other_todo.id])
+            .await
+            .expect("[assign_label_many] failed to assign label");
+        assert_eq!(assigned, 1);
+        let other_todo = repository
+            .find(None, other_todo.id)
+            .await
+            .expect("[find] failed to find todo");
+        assert_eq!(other_todo.labels, vec![label_1.clone()]);
+        assert!(repository
+            .assign_label_many(None, -1, vec![created_todo.id])
+            .await
+            .is_err());
+        let deleted = repository
+            .delete_many(None, vec![other_todo.id, other_todo.id, -1])
+            .await
+            .expect("[delete_many] failed to delete todo");
+        assert_eq!(deleted, 1);
+
+        // add label twice, then remove it
+        let labeled_todo = repository
+            .add_label(None, created_todo.id, label_1.id)
+            .await
+            .expect("[add_label] failed to add label");
+        assert_eq!(labeled_todo, created_todo);
+        let unlabeled_todo = repository
+            .remove_label(None, created_todo.id, label_1.id)
+            .await
+            .expect("[remove_label] failed to remove label");
+        assert!(unlabeled_todo.labels.is_empty());
+        let labeled_todo = repository
+            .add_label(None, created_todo.id, label_1.id)
+            .await
+            .expect("[add_label] failed to add label");
+        assert_eq!(labeled_todo, created_todo);
+        assert!(repository
+            .add_label(None, created_todo.id, -1)
+            .await
+            .is_err());
+
+        // replace
+        let replaced_todo = repository
+            .replace(
+                None,
+                created_todo.id,
+                ReplaceTodo {
+                    text: "[crud_scenario] replaced todo text".to_string(),
+                    completed: false,
+                    label_ids: vec![label_1.id],
+                    due_date: None,
+                    priority: Priority::Medium,
+                },
+            )
+            .await
+            .expect("[replace] failed to replace todo");
+        assert_eq!(
+            replaced_todo,
+            TodoEntity {
+                text: "[crud_scenario] replaced todo text".to_string(),
+                version: 2,
+                ..created_todo.clone()
+            }
+        );
+
         // update
         let updated_text = "[crud_scenario] updated todo text".to_string();
         let updated_todo = repository
             .update(
+                None,
                 created_todo.id,
                 UpdateTodo {
                     text: Some(updated_text.clone()),
                     completed: Some(true),
                     label_ids: Some(vec![]),
+                    due_date: None,
+                    priority: None,
+                    version: None,
+                    parent_id: None,
+                    label_mode: LabelMode::Replace,
                 },
             )
             .await
             .expect("[update] failed to update todo");
+        assert!(updated_todo.completed_at.is_some());
         assert_eq!(
             updated_todo,
             TodoEntity {
                 id: created_todo.id,
                 text: updated_text.clone(),
                 completed: true,
+                completed_at: updated_todo.completed_at,
+                due_date: None,
+                priority: Priority::Medium,
+                position: created_todo.position,
+                version: 3,
+                parent_id: None,
+                owner_id: None,
                 labels: vec![],
             }
         );
 
+        // toggle
+        let toggled_todo = repository
+            .toggle(None, created_todo.id)
+            .await
+            .expect("[toggle] failed to toggle todo");
+        assert!(!toggled_todo.completed);
+        assert_eq!(toggled_todo.completed_at, None);
+        let toggled_todo = repository
+            .toggle(None, created_todo.id)
+            .await
+            .expect("[toggle] failed to toggle todo");
+        assert!(toggled_todo.completed_at.is_some());
+        let unchanged_todo = repository
+            .update(
+                None,
+                created_todo.id,
+                UpdateTodo::new(None, Some(true), None),
+            )
+            .await
+            .expect("[update] failed to update todo");
+        assert_eq!(unchanged_todo.completed_at, toggled_todo.completed_at);
+        assert_eq!(
+            toggled_todo,
+            TodoEntity {
+                completed_at: toggled_todo.completed_at,
+                version: 5,
+                ..updated_todo.clone()
+            }
+        );
+
+        // versioned update
+        let versioned_todo = repository
+            .update(
+                None,
+                created_todo.id,
+                UpdateTodo {
+                    version: Some(unchanged_todo.version),
+                    ..UpdateTodo::new(Some(updated_text.clone()), None, None)
+                },
+            )
+            .await
+            .expect("[update] failed to update todo with matching version");
+        assert_eq!(versioned_todo.version, unchanged_todo.version + 1);
+        let err = repository
+            .update(
+                None,
+                created_todo.id,
+                UpdateTodo {
+                    version: Some(unchanged_todo.version),
+                    ..UpdateTodo::new(Some(updated_text.clone()), None, None)
+                },
+            )
+            .await
+            .expect_err("[update] stale version must be rejected");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::VersionConflict(version)) if *version == versioned_todo.version
+        ));
+
         // delete
-        let _result = repository
-            .delete(created_todo.id)
+        repository
+            .delete(None, created_todo.id)
             .await
             .expect("failed to delete todo");
-        let res_after_delete = repository.find(created_todo.id).await;
+        let res_after_delete = repository.find(None, created_todo.id).await;
         assert!(res_after_delete.is_err());
 
+        // restore
+        let restored_todo = repository
+            .restore(None, created_todo.id)
+            .await
+            .expect("[restore] failed to restore todo");
+        assert_eq!(restored_todo, versioned_todo);
+
+        // delete completed
+        let deleted = repository
+            .delete_completed(None)
+            .await
+            .expect("[delete_completed] failed to delete completed todos");
+        assert!(deleted >= 1);
+        assert!(repository.find(None, created_todo.id).await.is_err());
+
         let rows = sqlx::query(
             r#"
             select * from todo_labels where todo_id=$1
@@ -416,8 +2226,869 @@ mod test {
         .bind(created_todo.id)
         .fetch_all(&pool)
         .await
-        .expect("failed to fetch todo_labels");
-        assert!(rows.is_empty());
+        .expect("failed to fetch todo_labels");
+        assert!(rows.is_empty());
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn create_idempotent_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+
+        let repository = TodoRepositoryForDb::new(pool);
+        let key = format!(
+            "create_idempotent_scenario-{}",
+            Utc::now().timestamp_micros()
+        );
+        let ttl = Duration::from_secs(60);
+        let payload = || CreateTodo::new("[create_idempotent_scenario] todo".to_string(), vec![]);
+
+        let first = repository
+            .create_idempotent(None, key.clone(), ttl, payload())
+            .await
+            .expect("[create_idempotent] failed to create todo");
+        let repeated = repository
+            .create_idempotent(None, key.clone(), ttl, payload())
+            .await
+            .expect("[create_idempotent] failed to repeat key");
+        assert_eq!(repeated, first);
+
+        // an expired key creates a new todo
+        let expired = repository
+            .create_idempotent(None, key, Duration::ZERO, payload())
+            .await
+            .expect("[create_idempotent] failed to create todo");
+        assert_ne!(expired.id, first.id);
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn parent_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = TodoRepositoryForDb::new(pool);
+        let create = |text: &str, parent_id: Option<i32>| CreateTodo {
+            parent_id,
+            ..CreateTodo::new(format!("[parent_scenario] {}", text), vec![])
+        };
+
+        let root = repository.create(None, create("root", None)).await.unwrap();
+        let child = repository
+            .create(None, create("child", Some(root.id)))
+            .await
+            .expect("[create] failed to create child");
+        assert_eq!(child.parent_id, Some(root.id));
+        let grandchild = repository
+            .create(None, create("grandchild", Some(child.id)))
+            .await
+            .expect("[create] failed to create grandchild");
+
+        // children
+        let children = repository
+            .children(None, root.id)
+            .await
+            .expect("[children] failed to list children");
+        assert_eq!(children, vec![child.clone()]);
+
+        // unknown parent
+        let err = repository
+            .create(None, create("orphan", Some(-1)))
+            .await
+            .expect_err("[create] unknown parent must be rejected");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::UnknownParent(-1))
+        ));
+
+        // cycle
+        let payload = UpdateTodo {
+            parent_id: Some(Some(grandchild.id)),
+            ..UpdateTodo::new(None, None, None)
+        };
+        let err = repository
+            .update(None, root.id, payload)
+            .await
+            .expect_err("[update] cycle must be rejected");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::ParentCycle(id)) if *id == grandchild.id
+        ));
+
+        // detach
+        let payload = UpdateTodo {
+            parent_id: Some(None),
+            ..UpdateTodo::new(None, None, None)
+        };
+        let detached = repository
+            .update(None, child.id, payload)
+            .await
+            .expect("[update] failed to detach child");
+        assert_eq!(detached.parent_id, None);
+        assert!(repository.children(None, root.id).await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn label_filter_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let mut label_ids = vec![];
+        for name in ["[label_filter] a", "[label_filter] b"] {
+            let id = sqlx::query_scalar::<_, i32>(
+                r#"
+                with inserted as (
+                    insert into labels (name) values ($1)
+                    on conflict do nothing
+                    returning id
+                )
+                select id from inserted
+                union all
+                select id from labels where name = $1
+                "#,
+            )
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to prepare label data.");
+            label_ids.push(id);
+        }
+        // a dedicated owner keeps todos of concurrent tests out of the results
+        let owner_id = Some("[label_filter_scenario]");
+        let repository = TodoRepositoryForDb::new(pool);
+        let mut todo_ids = vec![];
+        for (text, label_ids) in [
+            ("both", label_ids.clone()),
+            ("first", vec![label_ids[0]]),
+            ("none", vec![]),
+        ] {
+            let todo = repository
+                .create(owner_id, CreateTodo::new(text.to_string(), label_ids))
+                .await
+                .expect("[create] returned Err");
+            todo_ids.push(todo.id);
+        }
+
+        for (label_match, expected) in [
+            (LabelMatch::All, vec!["both"]),
+            (LabelMatch::Any, vec!["first", "both"]),
+        ] {
+            let filter = TodoFilter {
+                label_ids: label_ids.clone(),
+                label_match,
+                ..TodoFilter::default()
+            };
+            let todos = repository
+                .all(
+                    owner_id,
+                    filter.clone(),
+                    TodoSort::default(),
+                    Pagination::default(),
+                )
+                .await
+                .expect("[all] returned Err");
+            let texts: Vec<&str> = todos.iter().map(|todo| todo.text.as_str()).collect();
+            assert_eq!(texts, expected);
+            // labels of matching todos are folded completely
+            assert_eq!(todos.last().unwrap().labels.len(), 2);
+            let count = repository
+                .count(owner_id, filter)
+                .await
+                .expect("[count] returned Err");
+            assert_eq!(count, expected.len() as i64);
+        }
+
+        repository
+            .delete_many(owner_id, todo_ids)
+            .await
+            .expect("[delete_many] returned Err");
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn label_mode_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let mut label_ids = vec![];
+        for name in ["[label_mode] a", "[label_mode] b"] {
+            let id = sqlx::query_scalar::<_, i32>(
+                r#"
+                with inserted as (
+                    insert into labels (name) values ($1)
+                    on conflict do nothing
+                    returning id
+                )
+                select id from inserted
+                union all
+                select id from labels where name = $1
+                "#,
+            )
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to prepare label data.");
+            label_ids.push(id);
+        }
+        let owner_id = Some("[label_mode_scenario]");
+        let repository = TodoRepositoryForDb::new(pool);
+        let todo = repository
+            .create(
+                owner_id,
+                CreateTodo::new("[label_mode_scenario] todo".to_string(), vec![label_ids[0]]),
+            )
+            .await
+            .expect("[create] returned Err");
+        let update = |label_mode: LabelMode, ids: Vec<i32>| {
+            UpdateTodo::new(None, None, Some(ids)).with_label_mode(label_mode)
+        };
+
+        for (label_mode, ids, expected) in [
+            (LabelMode::Add, vec![label_ids[1]], label_ids.clone()),
+            (LabelMode::Remove, vec![label_ids[0]], vec![label_ids[1]]),
+            (LabelMode::Replace, vec![label_ids[0]], vec![label_ids[0]]),
+        ] {
+            let updated = repository
+                .update(owner_id, todo.id, update(label_mode, ids))
+                .await
+                .expect("[update] returned Err");
+            let ids: Vec<i32> = updated.labels.iter().map(|label| label.id).collect();
+            assert_eq!(ids, expected, "{:?}", label_mode);
+        }
+
+        repository
+            .delete(owner_id, todo.id)
+            .await
+            .expect("[delete] returned Err");
+    }
+
+    #[tokio::test]
+    async fn retry_methods_on_connection_errors() {
+        use sqlx::postgres::PgPoolOptions;
+        use std::time::Instant;
+
+        // nothing listens on port 1, so every attempt ends with a pool timeout
+        let pool = PgPoolOptions::new()
+            .connect_timeout(Duration::from_millis(10))
+            .connect_lazy("postgres://localhost:1/todos")
+            .expect("failed to build pool");
+        let repository = TodoRepositoryForDb::new(pool).with_retry(RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(100),
+        });
+
+        let started = Instant::now();
+        let err = repository
+            .delete(None, 1)
+            .await
+            .expect_err("[delete] must fail without a database");
+        // 100ms and 200ms of backoff between the three attempts
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Unexpected(_))
+        ));
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn purge_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let label_id = sqlx::query_scalar::<_, i32>(
+            r#"
+            with inserted as (
+                insert into labels (name) values ($1)
+                on conflict do nothing
+                returning id
+            )
+            select id from inserted
+            union all
+            select id from labels where name = $1
+            "#,
+        )
+        .bind("[purge] label")
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to prepare label data.");
+        // purging only touches the todos of its owner, so concurrent tests keep theirs
+        let owner_id = Some("[purge_scenario]");
+        let other_owner_id = Some("[purge_scenario] other");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let mut todo_ids = vec![];
+        for owner_id in [owner_id, owner_id, other_owner_id] {
+            let todo = repository
+                .create(
+                    owner_id,
+                    CreateTodo::new("[purge_scenario]".to_string(), vec![label_id]),
+                )
+                .await
+                .expect("[create] returned Err");
+            todo_ids.push(todo.id);
+        }
+        repository
+            .delete(owner_id, todo_ids[1])
+            .await
+            .expect("[delete] returned Err");
+
+        let purged = repository
+            .purge(owner_id)
+            .await
+            .expect("[purge] returned Err");
+        assert_eq!(purged, 2);
+        let rows = sqlx::query_scalar::<_, i32>(
+            r#"
+            select todo_id from todo_labels where todo_id = any($1) order by todo_id
+            "#,
+        )
+        .bind(&todo_ids)
+        .fetch_all(&pool)
+        .await
+        .expect("failed to fetch todo_labels");
+        assert_eq!(rows, vec![todo_ids[2]]);
+        let todos = sqlx::query_scalar::<_, i32>("select id from todos where id = any($1)")
+            .bind(&todo_ids)
+            .fetch_all(&pool)
+            .await
+            .expect("failed to fetch todos");
+        assert_eq!(todos, vec![todo_ids[2]]);
+
+        repository
+            .purge(other_owner_id)
+            .await
+            .expect("[purge] returned Err");
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn exists_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let owner_id = Some("[exists_scenario]");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let todo = repository
+            .create(
+                owner_id,
+                CreateTodo::new("[exists_scenario]".to_string(), vec![]),
+            )
+            .await
+            .expect("[create] returned Err");
+
+        assert!(repository
+            .exists(owner_id, todo.id)
+            .await
+            .expect("[exists] returned Err"));
+        assert!(!repository
+            .exists(Some("[exists_scenario] other"), todo.id)
+            .await
+            .expect("[exists] returned Err"));
+        assert!(!repository
+            .exists(owner_id, -1)
+            .await
+            .expect("[exists] returned Err"));
+
+        repository
+            .delete(owner_id, todo.id)
+            .await
+            .expect("[delete] returned Err");
+        assert!(!repository
+            .exists(owner_id, todo.id)
+            .await
+            .expect("[exists] returned Err"));
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn stats_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let mut label_ids = vec![];
+        for name in ["[stats] a", "[stats] b"] {
+            let id = sqlx::query_scalar::<_, i32>(
+                r#"
+                with inserted as (
+                    insert into labels (name) values ($1)
+                    on conflict do nothing
+                    returning id
+                )
+                select id from inserted
+                union all
+                select id from labels where name = $1
+                "#,
+            )
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to prepare label data.");
+            label_ids.push(id);
+        }
+        // a dedicated owner keeps todos of concurrent tests out of the results
+        let owner_id = Some("[stats_scenario]");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let past_due = Some("2000-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        let payloads = [
+            (label_ids.clone(), past_due, false),
+            (vec![label_ids[0]], past_due, true),
+            (vec![], None, false),
+            (vec![], None, true),
+        ];
+        let mut todo_ids = vec![];
+        for (label_ids, due_date, completed) in payloads {
+            let payload = CreateTodo::builder()
+                .text("[stats_scenario]")
+                .label_ids(label_ids)
+                .due_date(due_date)
+                .allow_past_due(true)
+                .build()
+                .unwrap();
+            let todo = repository
+                .create(owner_id, payload)
+                .await
+                .expect("[create] returned Err");
+            if completed {
+                repository
+                    .toggle(owner_id, todo.id)
+                    .await
+                    .expect("[toggle] returned Err");
+            }
+            todo_ids.push(todo.id);
+        }
+
+        let stats = repository
+            .stats(owner_id)
+            .await
+            .expect("[stats] returned Err");
+        assert_eq!(
+            stats,
+            TodoStats {
+                total: 4,
+                completed: 2,
+                open: 2,
+                overdue: 1,
+                labels: 2,
+            }
+        );
+
+        repository
+            .delete_many(owner_id, todo_ids)
+            .await
+            .expect("[delete_many] returned Err");
+        let stats = repository
+            .stats(owner_id)
+            .await
+            .expect("[stats] returned Err");
+        assert_eq!(stats, TodoStats::default());
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn created_range_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        // a dedicated owner keeps todos of concurrent tests out of the results
+        let owner_id = Some("[created_range_scenario]");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let mut todo_ids = vec![];
+        for day in 1..=3 {
+            let todo = repository
+                .create(
+                    owner_id,
+                    CreateTodo::new(format!("[created_range_scenario] day {}", day), vec![]),
+                )
+                .await
+                .expect("[create] returned Err");
+            sqlx::query("update todos set created_at = $1 where id = $2")
+                .bind(
+                    format!("2024-01-0{}T12:00:00Z", day)
+                        .parse::<DateTime<Utc>>()
+                        .unwrap(),
+                )
+                .bind(todo.id)
+                .execute(&pool)
+                .await
+                .expect("failed to backdate todo");
+            todo_ids.push(todo.id);
+        }
+
+        // both bounds are inclusive
+        let filter = TodoFilter {
+            created_after: Some("2024-01-01T12:00:00Z".parse().unwrap()),
+            created_before: Some("2024-01-02T12:00:00Z".parse().unwrap()),
+            ..TodoFilter::default()
+        };
+        let todos = repository
+            .all(
+                owner_id,
+                filter.clone(),
+                TodoSort::IdAsc,
+                Pagination::default(),
+            )
+            .await
+            .expect("[all] returned Err");
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, todo_ids[..2]);
+        let count = repository
+            .count(owner_id, filter)
+            .await
+            .expect("[count] returned Err");
+        assert_eq!(count, 2);
+
+        repository
+            .delete_many(owner_id, todo_ids)
+            .await
+            .expect("[delete_many] returned Err");
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn owner_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = TodoRepositoryForDb::new(pool);
+        let alice = Some("[owner_scenario] alice");
+        let bob = Some("[owner_scenario] bob");
+
+        let todo = repository
+            .create(
+                alice,
+                CreateTodo::new("[owner_scenario] todo".to_string(), vec![]),
+            )
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(todo.owner_id.as_deref(), alice);
+        assert_eq!(repository.find(alice, todo.id).await.unwrap(), todo);
+        assert_eq!(repository.open_count(alice).await.unwrap(), 1);
+
+        // other owners, including unauthenticated callers, cannot see the todo
+        for owner_id in [bob, None] {
+            let err = repository
+                .find(owner_id, todo.id)
+                .await
+                .expect_err("[find] other owner must not see the todo");
+            assert!(matches!(
+                err.downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+        }
+        let count = repository
+            .count(bob, TodoFilter::default())
+            .await
+            .expect("[count] returned Err");
+        assert_eq!(count, 0);
+        assert_eq!(repository.open_count(bob).await.unwrap(), 0);
+        assert!(repository.toggle(bob, todo.id).await.is_err());
+        assert!(repository.delete(bob, todo.id).await.is_err());
+
+        repository
+            .delete(alice, todo.id)
+            .await
+            .expect("[delete] returned Err");
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn update_while_deleted_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let owner = Some("[update_while_deleted_scenario]");
+        let todo = repository
+            .create(
+                owner,
+                CreateTodo::new("[update_while_deleted_scenario] todo".to_string(), vec![]),
+            )
+            .await
+            .expect("[create] returned Err");
+
+        // the delete holds the row until it commits, the update has to wait for it
+        let mut tx = pool.begin().await.expect("failed to begin");
+        sqlx::query("update todos set deleted_at = now() where id = $1")
+            .bind(todo.id)
+            .execute(&mut tx)
+            .await
+            .expect("failed to delete todo");
+        let update = tokio::spawn({
+            let repository = repository.clone();
+            async move {
+                repository
+                    .update(
+                        owner,
+                        todo.id,
+                        UpdateTodo {
+                            version: Some(todo.version),
+                            ..UpdateTodo::new(Some("updated".to_string()), None, None)
+                        },
+                    )
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tx.commit().await.expect("failed to commit");
+
+        let err = update
+            .await
+            .unwrap()
+            .expect_err("[update] deleted todo must not be updated");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(id)) if *id == todo.id
+        ));
+        let text = sqlx::query_scalar::<_, String>("select text from todos where id = $1")
+            .bind(todo.id)
+            .fetch_one(&pool)
+            .await
+            .expect("failed to fetch todo");
+        assert_eq!(text, todo.text);
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn import_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let label_name = format!("import-{}", Utc::now().timestamp_micros() % 1_000_000_000);
+        let item = |text: &str, completed: bool, name: &str| ImportTodo {
+            text: text.to_string(),
+            completed,
+            label_names: vec![name.to_string()],
+        };
+
+        let summary = repository
+            .import(
+                None,
+                vec![
+                    item("[import_scenario] first", false, &label_name),
+                    item("[import_scenario] second", true, &label_name.to_uppercase()),
+                ],
+            )
+            .await
+            .expect("[import] failed to import todos");
+        assert_eq!(
+            summary,
+            ImportSummary {
+                created: 2,
+                labels_created: 1
+            }
+        );
+
+        let label_id = sqlx::query_scalar::<_, i32>("select id from labels where name = $1")
+            .bind(&label_name)
+            .fetch_one(&pool)
+            .await
+            .expect("[import] label was not created");
+        let todos = repository
+            .by_label(None, label_id)
+            .await
+            .expect("[by_label] failed to get todos");
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos.iter().filter(|todo| todo.completed).count(), 1);
+    }
+
+    /// Every todo row of `owner_id` joined with its labels, compared around a failing call.
+    #[cfg(feature = "database-test")]
+    async fn snapshot(pool: &PgPool, owner_id: &str) -> Vec<(i32, String, bool, i32, Option<i32>)> {
+        sqlx::query_as(
+            r#"
+            select todos.id, todos.text, todos.completed, todos.version, tl.label_id
+            from todos
+            left outer join todo_labels tl on todos.id = tl.todo_id
+            where todos.owner_id = $1
+            order by todos.id, tl.label_id
+            "#,
+        )
+        .bind(owner_id)
+        .fetch_all(pool)
+        .await
+        .expect("failed to snapshot todos")
+    }
+
+    /// Awaits a repository call that must abort its transaction and asserts it left
+    /// the rows of `owner_id` untouched, returning the error for further checks.
+    #[cfg(feature = "database-test")]
+    async fn assert_rolled_back<T: std::fmt::Debug>(
+        pool: &PgPool,
+        owner_id: &str,
+        call: impl std::future::Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Error {
+        let before = snapshot(pool, owner_id).await;
+        let err = call.await.expect_err("call must fail");
+        assert_eq!(
+            snapshot(pool, owner_id).await,
+            before,
+            "rows changed by: {}",
+            err
+        );
+        err
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn create_rollback_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let owner = "[create_rollback_scenario]";
+        let todo_text = "[create_rollback_scenario] todo text".to_string();
+
+        // label id which does not exist aborts the transaction after the todo is inserted
+        let err = assert_rolled_back(
+            &pool,
+            owner,
+            repository.create(
+                Some(owner),
+                CreateTodo::new(todo_text.clone(), vec![i32::MAX]),
+            ),
+        )
+        .await;
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::UnknownLabels(ids)) if *ids == vec![i32::MAX]
+        ));
+
+        // a failing payload drops the todos inserted before it
+        assert_rolled_back(
+            &pool,
+            owner,
+            repository.create_many(
+                Some(owner),
+                vec![
+                    CreateTodo::new(todo_text.clone(), vec![]),
+                    CreateTodo::new(todo_text, vec![i32::MAX]),
+                ],
+            ),
+        )
+        .await;
+        assert!(snapshot(&pool, owner).await.is_empty());
+    }
+
+    #[cfg(feature = "database-test")]
+    #[tokio::test]
+    async fn update_rollback_scenario() {
+        use dotenv::dotenv;
+
+        dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(&database_url)
+            .await
+            .unwrap_or_else(|_| panic!("failed to connect to db, url: [{}]", database_url));
+
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let owner = "[update_rollback_scenario]";
+        let label_id = sqlx::query_scalar::<_, i32>(
+            r#"
+            insert into labels (name) values ($1) returning id
+            "#,
+        )
+        .bind(format!(
+            "rollback-{}",
+            Utc::now().timestamp_micros() % 1_000_000_000
+        ))
+        .fetch_one(&pool)
+        .await
+        .expect("failed to insert label");
+        let todo = repository
+            .create(
+                Some(owner),
+                CreateTodo::new(
+                    "[update_rollback_scenario] todo".to_string(),
+                    vec![label_id],
+                ),
+            )
+            .await
+            .expect("[create] returned Err");
+
+        // the text is written and the old labels are detached before the unknown label fails
+        let err = assert_rolled_back(
+            &pool,
+            owner,
+            repository.update(
+                Some(owner),
+                todo.id,
+                UpdateTodo {
+                    text: Some("[update_rollback_scenario] updated".to_string()),
+                    completed: Some(true),
+                    label_ids: Some(vec![i32::MAX]),
+                    due_date: None,
+                    priority: Some(Priority::High),
+                    parent_id: None,
+                    version: None,
+                    label_mode: LabelMode::Replace,
+                },
+            ),
+        )
+        .await;
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::UnknownLabels(ids)) if *ids == vec![i32::MAX]
+        ));
+        assert_eq!(repository.find(Some(owner), todo.id).await.unwrap(), todo);
+
+        repository
+            .delete(Some(owner), todo.id)
+            .await
+            .expect("[delete] returned Err");
     }
 }
 
@@ -425,8 +3096,13 @@ mod test {
 pub mod test_utils {
     use super::*;
     use anyhow::Context;
-    use std::collections::HashMap;
-    use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+    use std::cmp::Ordering;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{
+        atomic::{self, AtomicI32},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    };
+    use std::time::Instant;
 
     impl TodoEntity {
         pub fn new(id: i32, text: String, labels: Vec<Label>) -> Self {
@@ -434,14 +3110,87 @@ pub mod test_utils {
                 id,
                 text,
                 completed: false,
+                completed_at: None,
+                due_date: None,
+                priority: Priority::default(),
+                position: id,
+                version: 1,
+                parent_id: None,
+                owner_id: None,
                 labels,
             }
         }
+
+        fn is_owned_by(&self, owner_id: Option<&str>) -> bool {
+            self.owner_id.as_deref() == owner_id
+        }
+
+        /// Mirrors `COMPLETED_AT_TRANSITION` of the DB implementation.
+        fn completed_at_after(&self, completed: bool) -> Option<DateTime<Utc>> {
+            match (self.completed, completed) {
+                (false, true) => Some(Utc::now()),
+                (true, false) => None,
+                _ => self.completed_at,
+            }
+        }
+    }
+
+    impl UpdateTodo {
+        pub fn new(
+            text: Option<String>,
+            completed: Option<bool>,
+            label_ids: Option<Vec<i32>>,
+        ) -> Self {
+            Self {
+                text,
+                completed,
+                label_ids,
+                due_date: None,
+                priority: None,
+                parent_id: None,
+                version: None,
+                label_mode: LabelMode::Replace,
+            }
+        }
     }
 
-    impl CreateTodo {
-        pub fn new(text: String, label_ids: Vec<i32>) -> Self {
-            Self { text, label_ids }
+    impl TodoSort {
+        fn compare(&self, a: &TodoEntity, b: &TodoEntity) -> Ordering {
+            match self {
+                // ids are handed out in creation order
+                TodoSort::IdAsc | TodoSort::CreatedAtAsc => a.id.cmp(&b.id),
+                TodoSort::IdDesc | TodoSort::CreatedAtDesc => b.id.cmp(&a.id),
+                TodoSort::TextAsc => a.text.cmp(&b.text).then(a.id.cmp(&b.id)),
+                TodoSort::TextDesc => b.text.cmp(&a.text).then(b.id.cmp(&a.id)),
+                TodoSort::Priority => b.priority.cmp(&a.priority).then(b.id.cmp(&a.id)),
+                TodoSort::Position => a.position.cmp(&b.position).then(a.id.cmp(&b.id)),
+            }
+        }
+    }
+
+    impl TodoFilter {
+        fn matches(&self, todo: &TodoEntity, created_at: Option<DateTime<Utc>>) -> bool {
+            let completed = self
+                .completed
+                .is_none_or(|completed| todo.completed == completed);
+            let text = self
+                .query()
+                .is_none_or(|q| todo.text.to_lowercase().contains(&q.to_lowercase()));
+            let overdue = !self.overdue
+                || (!todo.completed && todo.due_date.is_some_and(|due| due < Utc::now()));
+            let has_label = |id: &i32| todo.labels.iter().any(|label| label.id == *id);
+            let labels = self.label_ids.is_empty()
+                || match self.label_match {
+                    LabelMatch::All => self.label_ids.iter().all(has_label),
+                    LabelMatch::Any => self.label_ids.iter().any(has_label),
+                };
+            let created = created_at.is_some_and(|created_at| {
+                self.created_after.is_none_or(|after| created_at >= after)
+                    && self
+                        .created_before
+                        .is_none_or(|before| created_at <= before)
+            });
+            completed && text && overdue && labels && created
         }
     }
 
@@ -450,88 +3199,694 @@ pub mod test_utils {
     #[derive(Debug, Clone)]
     pub struct TodoRepositoryForMemory {
         store: Arc<RwLock<TodoDatas>>,
-        labels: Vec<Label>,
+        tombstones: Arc<RwLock<TodoDatas>>,
+        idempotency_keys: Arc<RwLock<HashMap<String, (i32, Instant)>>>,
+        /// `TodoEntity` does not expose it, so it is kept by id.
+        created_at: Arc<RwLock<HashMap<i32, DateTime<Utc>>>>,
+        last_id: Arc<AtomicI32>,
+        labels: Arc<RwLock<Vec<Label>>>,
     }
 
     impl TodoRepositoryForMemory {
         pub fn new(labels: Vec<Label>) -> Self {
             Self {
                 store: Arc::default(),
-                labels,
+                tombstones: Arc::default(),
+                idempotency_keys: Arc::default(),
+                created_at: Arc::default(),
+                last_id: Arc::default(),
+                labels: Arc::new(RwLock::new(labels)),
             }
         }
 
-        fn write_score_ref(&self) -> RwLockWriteGuard<TodoDatas> {
+        fn labels(&self) -> Vec<Label> {
+            self.labels.read().unwrap().clone()
+        }
+
+        fn write_score_ref(&self) -> RwLockWriteGuard<'_, TodoDatas> {
             self.store.write().unwrap()
         }
 
-        fn read_score_ref(&self) -> RwLockReadGuard<TodoDatas> {
+        fn read_score_ref(&self) -> RwLockReadGuard<'_, TodoDatas> {
             self.store.read().unwrap()
         }
 
+        fn next_id(&self) -> i32 {
+            self.last_id.fetch_add(1, atomic::Ordering::SeqCst) + 1
+        }
+
+        pub fn todos(&self) -> Vec<TodoEntity> {
+            self.read_score_ref().values().cloned().collect()
+        }
+
+        pub fn set_created_at(&self, id: i32, created_at: DateTime<Utc>) {
+            self.created_at.write().unwrap().insert(id, created_at);
+        }
+
+        fn created_at(&self, id: i32) -> Option<DateTime<Utc>> {
+            self.created_at.read().unwrap().get(&id).copied()
+        }
+
+        pub fn detach_label(&self, label_id: i32) {
+            let mut store = self.write_score_ref();
+            for todo in store.values_mut() {
+                todo.labels.retain(|label| label.id != label_id);
+            }
+        }
+
+        /// Updates the copies of `label` held by todos, which the DB gets for free by joining.
+        pub fn refresh_label(&self, label: &Label) {
+            let mut labels = self.labels.write().unwrap();
+            let mut store = self.write_score_ref();
+            let mut tombstones = self.tombstones.write().unwrap();
+            for copy in labels
+                .iter_mut()
+                .chain(store.values_mut().flat_map(|todo| todo.labels.iter_mut()))
+                .chain(
+                    tombstones
+                        .values_mut()
+                        .flat_map(|todo| todo.labels.iter_mut()),
+                )
+                .filter(|copy| copy.id == label.id)
+            {
+                *copy = label.clone();
+            }
+        }
+
+        pub fn replace_label(&self, label_id: i32, new_label: &Label) {
+            let mut store = self.write_score_ref();
+            for todo in store.values_mut() {
+                if !todo.labels.iter().any(|label| label.id == label_id) {
+                    continue;
+                }
+                todo.labels.retain(|label| label.id != label_id);
+                if !todo.labels.contains(new_label) {
+                    todo.labels.push(new_label.clone());
+                }
+            }
+        }
+
+        fn ensure_labels_exist(&self, label_ids: &[i32]) -> anyhow::Result<()> {
+            let known_ids: Vec<i32> = self.labels().iter().map(|label| label.id).collect();
+            let unknown_ids = unknown_label_ids(label_ids, &known_ids);
+            if !unknown_ids.is_empty() {
+                return Err(RepositoryError::UnknownLabels(unknown_ids).into());
+            }
+            Ok(())
+        }
+
         fn resolve_labels(&self, label_ids: Vec<i32>) -> Vec<Label> {
+            let labels = self.labels();
             label_ids
                 .iter()
-                .filter_map(|id| {
-                    self.labels
-                        .iter()
-                        .find(|label| label.id == *id)
-                        .map(|label| label.clone())
-                })
+                .filter_map(|id| labels.iter().find(|label| label.id == *id).cloned())
                 .collect()
         }
-    }
 
-    #[async_trait]
-    impl TodoRepository for TodoRepositoryForMemory {
-        async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+        /// Mirrors `TodoRepositoryForDb::check_parent`.
+        fn check_parent(
+            store: &TodoDatas,
+            owner_id: Option<&str>,
+            parent_id: i32,
+            id: Option<i32>,
+        ) -> anyhow::Result<()> {
+            if !store
+                .get(&parent_id)
+                .is_some_and(|todo| todo.is_owned_by(owner_id))
+            {
+                return Err(RepositoryError::UnknownParent(parent_id).into());
+            }
+            let mut ancestor = Some(parent_id);
+            // bounded in case the chain is already broken
+            for _ in 0..=store.len() {
+                let Some(ancestor_id) = ancestor else {
+                    break;
+                };
+                if Some(ancestor_id) == id {
+                    return Err(RepositoryError::ParentCycle(parent_id).into());
+                }
+                ancestor = store.get(&ancestor_id).and_then(|todo| todo.parent_id);
+            }
+            Ok(())
+        }
+
+        fn insert(
+            &self,
+            owner_id: Option<&str>,
+            payload: CreateTodo,
+        ) -> anyhow::Result<TodoEntity> {
+            payload.validate()?;
+            self.ensure_labels_exist(&payload.label_ids)?;
             let mut store = self.write_score_ref();
-            let id = store.len() as i32 + 1;
+            if let Some(parent_id) = payload.parent_id {
+                Self::check_parent(&store, owner_id, parent_id, None)?;
+            }
+            let id = self.next_id();
             let labels = self.resolve_labels(payload.label_ids);
-            let todo = TodoEntity::new(id, payload.text.clone(), labels);
+            let todo = TodoEntity {
+                due_date: payload.due_date,
+                priority: payload.priority,
+                parent_id: payload.parent_id,
+                owner_id: owner_id.map(str::to_string),
+                ..TodoEntity::new(id, payload.text.clone(), labels)
+            };
+            self.set_created_at(id, Utc::now());
             store.insert(id, todo.clone());
             Ok(todo)
         }
+    }
+
+    #[async_trait]
+    impl TodoRepository for TodoRepositoryForMemory {
+        async fn create(
+            &self,
+            owner_id: Option<&str>,
+            payload: CreateTodo,
+        ) -> anyhow::Result<TodoEntity> {
+            self.insert(owner_id, payload)
+        }
+
+        async fn create_many(
+            &self,
+            owner_id: Option<&str>,
+            payloads: Vec<CreateTodo>,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            let mut todos = vec![];
+            for payload in payloads {
+                todos.push(self.create(owner_id, payload).await?);
+            }
+            Ok(todos)
+        }
+
+        async fn create_idempotent(
+            &self,
+            owner_id: Option<&str>,
+            key: String,
+            ttl: Duration,
+            payload: CreateTodo,
+        ) -> anyhow::Result<TodoEntity> {
+            let key = owner_scoped_key(owner_id, key);
+            let mut keys = self.idempotency_keys.write().unwrap();
+            keys.retain(|_, (_, created_at)| created_at.elapsed() < ttl);
+            if let Some((id, _)) = keys.get(&key) {
+                let todo = self
+                    .read_score_ref()
+                    .get(id)
+                    .cloned()
+                    .ok_or(RepositoryError::NotFound(*id))?;
+                return Ok(todo);
+            }
+            let todo = self.insert(owner_id, payload)?;
+            keys.insert(key, (todo.id, Instant::now()));
+            Ok(todo)
+        }
 
-        async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
+        async fn find(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
             let store = self.read_score_ref();
             // TODO: Use Box::new
             let todo = store
                 .get(&id)
-                .map(|todo| todo.clone())
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .cloned()
                 .ok_or(RepositoryError::NotFound(id))?;
             Ok(todo)
         }
 
-        async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
+        async fn exists(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<bool> {
+            let store = self.read_score_ref();
+            Ok(store
+                .get(&id)
+                .is_some_and(|todo| todo.is_owned_by(owner_id)))
+        }
+
+        async fn find_many(
+            &self,
+            owner_id: Option<&str>,
+            ids: Vec<i32>,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            let store = self.read_score_ref();
+            let mut todos: Vec<TodoEntity> = store
+                .values()
+                .filter(|todo| todo.is_owned_by(owner_id) && ids.contains(&todo.id))
+                .cloned()
+                .collect();
+            let sort = TodoSort::default();
+            todos.sort_by(|a, b| sort.compare(a, b));
+            Ok(todos)
+        }
+
+        async fn all(
+            &self,
+            owner_id: Option<&str>,
+            filter: TodoFilter,
+            sort: TodoSort,
+            pagination: Pagination,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            let store = self.read_score_ref();
+            let mut todos: Vec<TodoEntity> = store
+                .values()
+                .filter(|todo| {
+                    todo.is_owned_by(owner_id) && filter.matches(todo, self.created_at(todo.id))
+                })
+                .filter(|todo| pagination.after.is_none_or(|after| todo.id < after))
+                .cloned()
+                .collect();
+            todos.sort_by(|a, b| sort.compare(a, b));
+            let todos = todos
+                .into_iter()
+                .skip(pagination.offset as usize)
+                .take(pagination.limit as usize)
+                .collect();
+            Ok(todos)
+        }
+
+        async fn count(&self, owner_id: Option<&str>, filter: TodoFilter) -> anyhow::Result<i64> {
+            let store = self.read_score_ref();
+            let count = store
+                .values()
+                .filter(|todo| {
+                    todo.is_owned_by(owner_id) && filter.matches(todo, self.created_at(todo.id))
+                })
+                .count();
+            Ok(count as i64)
+        }
+
+        async fn open_count(&self, owner_id: Option<&str>) -> anyhow::Result<i64> {
+            let store = self.read_score_ref();
+            let count = store
+                .values()
+                .filter(|todo| todo.is_owned_by(owner_id) && !todo.completed)
+                .count();
+            Ok(count as i64)
+        }
+
+        async fn stats(&self, owner_id: Option<&str>) -> anyhow::Result<TodoStats> {
+            let store = self.read_score_ref();
+            let now = Utc::now();
+            let mut stats = TodoStats::default();
+            let mut label_ids = HashSet::new();
+            for todo in store.values().filter(|todo| todo.is_owned_by(owner_id)) {
+                stats.total += 1;
+                if todo.completed {
+                    stats.completed += 1;
+                } else {
+                    stats.open += 1;
+                    if todo.due_date.is_some_and(|due| due < now) {
+                        stats.overdue += 1;
+                    }
+                }
+                label_ids.extend(todo.labels.iter().map(|label| label.id));
+            }
+            stats.labels = label_ids.len() as i64;
+            Ok(stats)
+        }
+
+        async fn by_label(
+            &self,
+            owner_id: Option<&str>,
+            label_id: i32,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            let store = self.read_score_ref();
+            let mut todos: Vec<TodoEntity> = store
+                .values()
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .filter(|todo| todo.labels.iter().any(|label| label.id == label_id))
+                .cloned()
+                .collect();
+            let sort = TodoSort::default();
+            todos.sort_by(|a, b| sort.compare(a, b));
+            Ok(todos)
+        }
+
+        async fn children(
+            &self,
+            owner_id: Option<&str>,
+            id: i32,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
             let store = self.read_score_ref();
-            Ok(store.values().cloned().collect())
+            if !store
+                .get(&id)
+                .is_some_and(|todo| todo.is_owned_by(owner_id))
+            {
+                return Err(RepositoryError::NotFound(id).into());
+            }
+            let mut todos: Vec<TodoEntity> = store
+                .values()
+                .filter(|todo| todo.parent_id == Some(id))
+                .cloned()
+                .collect();
+            let sort = TodoSort::Position;
+            todos.sort_by(|a, b| sort.compare(a, b));
+            Ok(todos)
         }
 
-        async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+        async fn update(
+            &self,
+            owner_id: Option<&str>,
+            id: i32,
+            payload: UpdateTodo,
+        ) -> anyhow::Result<TodoEntity> {
+            payload.validate()?;
             let mut store = self.write_score_ref();
-            let todo = store.get(&id).context(RepositoryError::NotFound(id))?;
+            let todo = store
+                .get(&id)
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .context(RepositoryError::NotFound(id))?;
+            if let Some(Some(parent_id)) = payload.parent_id {
+                Self::check_parent(&store, owner_id, parent_id, Some(id))?;
+            }
+            if payload
+                .version
+                .is_some_and(|version| version != todo.version)
+            {
+                return Err(RepositoryError::VersionConflict(todo.version).into());
+            }
             let text = payload.text.unwrap_or(todo.text.clone());
             let completed = payload.completed.unwrap_or(todo.completed);
-            let labels = match payload.label_ids {
-                Some(v) => self.resolve_labels(v),
-                None => todo.labels.clone(),
+            let due_date = payload.due_date.unwrap_or(todo.due_date);
+            let priority = payload.priority.unwrap_or(todo.priority);
+            let labels = match (payload.label_ids, payload.label_mode) {
+                (Some(label_ids), LabelMode::Replace) => {
+                    self.ensure_labels_exist(&label_ids)?;
+                    self.resolve_labels(label_ids)
+                }
+                (Some(label_ids), LabelMode::Add) => {
+                    self.ensure_labels_exist(&label_ids)?;
+                    let mut labels = todo.labels.clone();
+                    for label in self.resolve_labels(label_ids) {
+                        if !labels.contains(&label) {
+                            labels.push(label);
+                        }
+                    }
+                    labels
+                }
+                (Some(label_ids), LabelMode::Remove) => todo
+                    .labels
+                    .iter()
+                    .filter(|label| !label_ids.contains(&label.id))
+                    .cloned()
+                    .collect(),
+                (None, _) => todo.labels.clone(),
             };
             let todo = TodoEntity {
                 id,
                 text,
                 completed,
+                completed_at: todo.completed_at_after(completed),
+                due_date,
+                priority,
+                position: todo.position,
+                version: todo.version + 1,
+                parent_id: payload.parent_id.unwrap_or(todo.parent_id),
+                owner_id: todo.owner_id.clone(),
                 labels,
             };
             store.insert(id, todo.clone());
             Ok(todo)
         }
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        async fn replace(
+            &self,
+            owner_id: Option<&str>,
+            id: i32,
+            payload: ReplaceTodo,
+        ) -> anyhow::Result<TodoEntity> {
+            payload.validate()?;
+            self.ensure_labels_exist(&payload.label_ids)?;
+            let mut store = self.write_score_ref();
+            let todo = store
+                .get(&id)
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .ok_or(RepositoryError::NotFound(id))?;
+            let todo = TodoEntity {
+                id,
+                text: payload.text,
+                completed: payload.completed,
+                completed_at: todo.completed_at_after(payload.completed),
+                due_date: payload.due_date,
+                priority: payload.priority,
+                position: todo.position,
+                version: todo.version + 1,
+                parent_id: todo.parent_id,
+                owner_id: todo.owner_id.clone(),
+                labels: self.resolve_labels(payload.label_ids),
+            };
+            store.insert(id, todo.clone());
+            Ok(todo)
+        }
+
+        async fn toggle(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+            let mut store = self.write_score_ref();
+            let todo = store
+                .get_mut(&id)
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .ok_or(RepositoryError::NotFound(id))?;
+            todo.completed_at = todo.completed_at_after(!todo.completed);
+            todo.completed = !todo.completed;
+            todo.version += 1;
+            Ok(todo.clone())
+        }
+
+        async fn duplicate(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+            let source = self.find(owner_id, id).await?;
+            self.insert(owner_id, CreateTodo::copy_of(&source)?)
+        }
+
+        async fn add_label(
+            &self,
+            owner_id: Option<&str>,
+            id: i32,
+            label_id: i32,
+        ) -> anyhow::Result<TodoEntity> {
+            let mut store = self.write_score_ref();
+            let todo = store
+                .get_mut(&id)
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .ok_or(RepositoryError::NotFound(id))?;
+            let label = self
+                .labels()
+                .into_iter()
+                .find(|label| label.id == label_id)
+                .ok_or(RepositoryError::NotFound(label_id))?;
+            if !todo.labels.contains(&label) {
+                todo.labels.push(label);
+            }
+            Ok(todo.clone())
+        }
+
+        async fn remove_label(
+            &self,
+            owner_id: Option<&str>,
+            id: i32,
+            label_id: i32,
+        ) -> anyhow::Result<TodoEntity> {
+            let mut store = self.write_score_ref();
+            let todo = store
+                .get_mut(&id)
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .ok_or(RepositoryError::NotFound(id))?;
+            if !self.labels().iter().any(|label| label.id == label_id) {
+                return Err(RepositoryError::NotFound(label_id).into());
+            }
+            todo.labels.retain(|label| label.id != label_id);
+            Ok(todo.clone())
+        }
+
+        async fn reorder(&self, owner_id: Option<&str>, ids: Vec<i32>) -> anyhow::Result<()> {
+            let mut store = self.write_score_ref();
+            let mut known_ids: Vec<i32> = store
+                .values()
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .map(|todo| todo.id)
+                .collect();
+            if !same_ids(&ids, &mut known_ids) {
+                return Err(RepositoryError::OrderMismatch.into());
+            }
+            for (position, id) in ids.iter().enumerate() {
+                store.get_mut(id).unwrap().position = position as i32 + 1;
+            }
+            Ok(())
+        }
+
+        async fn assign_label_many(
+            &self,
+            owner_id: Option<&str>,
+            label_id: i32,
+            todo_ids: Vec<i32>,
+        ) -> anyhow::Result<u64> {
+            let mut store = self.write_score_ref();
+            let label = self
+                .labels()
+                .into_iter()
+                .find(|label| label.id == label_id)
+                .ok_or(RepositoryError::NotFound(label_id))?;
+            if let Some(id) = todo_ids
+                .iter()
+                .find(|id| !store.get(id).is_some_and(|todo| todo.is_owned_by(owner_id)))
+            {
+                return Err(RepositoryError::NotFound(*id).into());
+            }
+            let mut assigned = 0;
+            for id in todo_ids {
+                let todo = store.get_mut(&id).unwrap();
+                if !todo.labels.contains(&label) {
+                    todo.labels.push(label.clone());
+                    assigned += 1;
+                }
+            }
+            Ok(assigned)
+        }
+
+        async fn import(
+            &self,
+            owner_id: Option<&str>,
+            items: Vec<ImportTodo>,
+        ) -> anyhow::Result<ImportSummary> {
+            for item in &items {
+                item.validate()?;
+            }
+            let mut labels_created = 0;
+            {
+                let mut labels = self.labels.write().unwrap();
+                for name in items.iter().flat_map(|item| &item.label_names) {
+                    if labels
+                        .iter()
+                        .any(|label| label.name.to_lowercase() == name.to_lowercase())
+                    {
+                        continue;
+                    }
+                    let id = labels.iter().map(|label| label.id).max().unwrap_or(0) + 1;
+                    labels.push(Label::new(id, name.clone()));
+                    labels_created += 1;
+                }
+            }
+
+            let labels = self.labels();
+            let created = items.len() as u64;
+            for item in items {
+                let ids = item
+                    .label_names
+                    .iter()
+                    .filter_map(|name| {
+                        labels
+                            .iter()
+                            .find(|label| label.name.to_lowercase() == name.to_lowercase())
+                    })
+                    .map(|label| label.id)
+                    .collect();
+                let todo = self.insert(owner_id, CreateTodo::new(item.text, ids))?;
+                if item.completed {
+                    let mut store = self.write_score_ref();
+                    let todo = store.get_mut(&todo.id).unwrap();
+                    todo.completed_at = todo.completed_at_after(true);
+                    todo.completed = true;
+                }
+            }
+            Ok(ImportSummary {
+                created,
+                labels_created,
+            })
+        }
+
+        async fn delete(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<()> {
             let mut store = self.write_score_ref();
-            store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
+            if !store
+                .get(&id)
+                .is_some_and(|todo| todo.is_owned_by(owner_id))
+            {
+                return Err(RepositoryError::NotFound(id).into());
+            }
+            let todo = store.remove(&id).unwrap();
+            self.tombstones.write().unwrap().insert(id, todo);
             Ok(())
         }
+
+        async fn delete_many(&self, owner_id: Option<&str>, ids: Vec<i32>) -> anyhow::Result<u64> {
+            let mut store = self.write_score_ref();
+            let mut tombstones = self.tombstones.write().unwrap();
+            let mut deleted = 0;
+            for id in ids {
+                if store
+                    .get(&id)
+                    .is_some_and(|todo| todo.is_owned_by(owner_id))
+                {
+                    let todo = store.remove(&id).unwrap();
+                    tombstones.insert(id, todo);
+                    deleted += 1;
+                }
+            }
+            Ok(deleted)
+        }
+
+        async fn set_all_completed(
+            &self,
+            owner_id: Option<&str>,
+            completed: bool,
+        ) -> anyhow::Result<u64> {
+            let mut store = self.write_score_ref();
+            let mut updated = 0;
+            for todo in store
+                .values_mut()
+                .filter(|todo| todo.is_owned_by(owner_id) && todo.completed != completed)
+            {
+                todo.completed_at = todo.completed_at_after(completed);
+                todo.completed = completed;
+                todo.version += 1;
+                updated += 1;
+            }
+            Ok(updated)
+        }
+
+        async fn delete_completed(&self, owner_id: Option<&str>) -> anyhow::Result<u64> {
+            let mut store = self.write_score_ref();
+            let mut tombstones = self.tombstones.write().unwrap();
+            let ids: Vec<i32> = store
+                .values()
+                .filter(|todo| todo.is_owned_by(owner_id) && todo.completed)
+                .map(|todo| todo.id)
+                .collect();
+            for id in &ids {
+                let todo = store.remove(id).unwrap();
+                tombstones.insert(*id, todo);
+            }
+            Ok(ids.len() as u64)
+        }
+
+        async fn purge(&self, owner_id: Option<&str>) -> anyhow::Result<u64> {
+            let mut store = self.write_score_ref();
+            let mut tombstones = self.tombstones.write().unwrap();
+            let ids: HashSet<i32> = store
+                .values()
+                .chain(tombstones.values())
+                .filter(|todo| todo.is_owned_by(owner_id))
+                .map(|todo| todo.id)
+                .collect();
+            store.retain(|id, _| !ids.contains(id));
+            tombstones.retain(|id, _| !ids.contains(id));
+            self.idempotency_keys
+                .write()
+                .unwrap()
+                .retain(|_, (id, _)| !ids.contains(id));
+            self.created_at
+                .write()
+                .unwrap()
+                .retain(|id, _| !ids.contains(id));
+            Ok(ids.len() as u64)
+        }
+
+        async fn restore(&self, owner_id: Option<&str>, id: i32) -> anyhow::Result<TodoEntity> {
+            let mut store = self.write_score_ref();
+            let mut tombstones = self.tombstones.write().unwrap();
+            if !tombstones
+                .get(&id)
+                .is_some_and(|todo| todo.is_owned_by(owner_id))
+            {
+                return Err(RepositoryError::NotFound(id).into());
+            }
+            let todo = tombstones.remove(&id).unwrap();
+            store.insert(id, todo.clone());
+            Ok(todo)
+        }
     }
 
     mod test {
@@ -548,45 +3903,243 @@ pub mod test_utils {
             let labels = vec![];
             let repository = TodoRepositoryForMemory::new(labels);
             let todo = repository
-                .create(CreateTodo::new(text, label_ids))
+                .create(None, CreateTodo::new(text, label_ids))
                 .await
                 .expect("failed to create todo");
             assert_eq!(todo, expected);
 
             // find
-            let todo = repository.find(todo.id).await.unwrap();
+            let todo = repository.find(None, todo.id).await.unwrap();
             assert_eq!(todo, expected);
 
             // all
-            let todos = repository.all().await.expect("failed to get all todos");
+            let todos = repository
+                .all(
+                    None,
+                    TodoFilter::default(),
+                    TodoSort::default(),
+                    Pagination::default(),
+                )
+                .await
+                .expect("failed to get all todos");
+            assert_eq!(todos, vec![expected.clone()]);
+
+            // all filtered by completed
+            let filter = TodoFilter {
+                completed: Some(true),
+                ..Default::default()
+            };
+            let todos = repository
+                .all(None, filter, TodoSort::default(), Pagination::default())
+                .await
+                .expect("failed to get completed todos");
+            assert!(todos.is_empty());
+            let filter = TodoFilter {
+                completed: Some(false),
+                ..Default::default()
+            };
+            let todos = repository
+                .all(None, filter, TodoSort::default(), Pagination::default())
+                .await
+                .expect("failed to get incomplete todos");
+            assert_eq!(todos, vec![expected.clone()]);
+
+            // all filtered by text
+            let filter = TodoFilter {
+                q: Some("TODO TE".to_string()),
+                ..Default::default()
+            };
+            let todos = repository
+                .all(None, filter, TodoSort::default(), Pagination::default())
+                .await
+                .expect("failed to search todos");
             assert_eq!(todos, vec![expected.clone()]);
+            let filter = TodoFilter {
+                q: Some("missing".to_string()),
+                ..Default::default()
+            };
+            let todos = repository
+                .all(None, filter, TodoSort::default(), Pagination::default())
+                .await
+                .expect("failed to search todos");
+            assert!(todos.is_empty());
+
+            // count
+            let count = repository
+                .count(None, TodoFilter::default())
+                .await
+                .expect("failed to count todos");
+            assert_eq!(count, 1);
 
             // update
             let updated_text = "updated todo text".to_string();
             let todo = repository
                 .update(
+                    None,
                     id,
                     UpdateTodo {
                         text: Some(updated_text.clone()),
                         completed: Some(true),
                         label_ids: Some(vec![]),
+                        due_date: None,
+                        priority: None,
+                        version: None,
+                        parent_id: None,
+                        label_mode: LabelMode::Replace,
                     },
                 )
                 .await
                 .expect("failed to update");
+            assert!(todo.completed_at.is_some());
             assert_eq!(
                 todo,
                 TodoEntity {
                     id,
                     text: updated_text.clone(),
                     completed: true,
+                    completed_at: todo.completed_at,
+                    due_date: None,
+                    priority: Priority::Medium,
+                    position: id,
+                    version: 2,
+                    parent_id: None,
+                    owner_id: None,
                     labels: vec![],
                 }
             );
 
             // delete
-            let result = repository.delete(id).await;
+            let result = repository.delete(None, id).await;
             assert!(result.is_ok());
         }
+
+        #[tokio::test]
+        async fn completed_at_follows_completed_flag() {
+            let repository = TodoRepositoryForMemory::new(vec![]);
+            let todo = repository
+                .create(None, CreateTodo::new("todo".to_string(), vec![]))
+                .await
+                .unwrap();
+            assert_eq!(todo.completed_at, None);
+
+            // set
+            let completed = repository
+                .update(None, todo.id, UpdateTodo::new(None, Some(true), None))
+                .await
+                .unwrap();
+            assert!(completed.completed_at.is_some());
+
+            // no change
+            let unchanged = repository
+                .update(
+                    None,
+                    todo.id,
+                    UpdateTodo::new(Some("renamed".to_string()), Some(true), None),
+                )
+                .await
+                .unwrap();
+            assert_eq!(unchanged.completed_at, completed.completed_at);
+            let unchanged = repository
+                .update(None, todo.id, UpdateTodo::new(None, None, None))
+                .await
+                .unwrap();
+            assert_eq!(unchanged.completed_at, completed.completed_at);
+
+            // clear
+            let reopened = repository.toggle(None, todo.id).await.unwrap();
+            assert_eq!(reopened.completed_at, None);
+            let reopened = repository
+                .update(None, todo.id, UpdateTodo::new(None, Some(false), None))
+                .await
+                .unwrap();
+            assert_eq!(reopened.completed_at, None);
+        }
+
+        #[tokio::test]
+        async fn reject_too_many_labels() {
+            let repository = TodoRepositoryForMemory::new(vec![]);
+            let label_ids = (1..=MAX_LABELS_PER_TODO as i32 + 1).collect();
+            let result = repository
+                .create(None, CreateTodo::new("todo text".to_string(), label_ids))
+                .await;
+            assert!(result.is_err());
+            assert!(repository.todos().is_empty());
+        }
+
+        #[tokio::test]
+        async fn todos_are_partitioned_by_owner() {
+            let repository = TodoRepositoryForMemory::new(vec![]);
+            let todo = repository
+                .create(Some("alice"), CreateTodo::new("todo".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            assert_eq!(todo.owner_id.as_deref(), Some("alice"));
+
+            for owner_id in [Some("bob"), None] {
+                assert!(repository.find(owner_id, todo.id).await.is_err());
+                assert!(repository
+                    .update(owner_id, todo.id, UpdateTodo::new(None, Some(true), None))
+                    .await
+                    .is_err());
+                assert!(repository.delete(owner_id, todo.id).await.is_err());
+                let todos = repository
+                    .all(
+                        owner_id,
+                        TodoFilter::default(),
+                        TodoSort::default(),
+                        Pagination::default(),
+                    )
+                    .await
+                    .unwrap();
+                assert!(todos.is_empty());
+            }
+            assert_eq!(repository.find(Some("alice"), todo.id).await.unwrap(), todo);
+        }
+
+        #[tokio::test]
+        async fn exists_without_loading_the_todo() {
+            let repository = TodoRepositoryForMemory::new(vec![]);
+            let todo = repository
+                .create(Some("alice"), CreateTodo::new("todo".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            assert!(repository.exists(Some("alice"), todo.id).await.unwrap());
+            assert!(!repository.exists(Some("bob"), todo.id).await.unwrap());
+            assert!(!repository.exists(Some("alice"), todo.id + 1).await.unwrap());
+
+            repository.delete(Some("alice"), todo.id).await.unwrap();
+            assert!(!repository.exists(Some("alice"), todo.id).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn ids_are_not_reused_after_delete() {
+            let repository = TodoRepositoryForMemory::new(vec![]);
+            for text in ["first", "second", "third"] {
+                repository
+                    .create(None, CreateTodo::new(text.to_string(), vec![]))
+                    .await
+                    .expect("failed to create todo");
+            }
+            repository
+                .delete(None, 2)
+                .await
+                .expect("failed to delete todo");
+            let fourth = repository
+                .create(None, CreateTodo::new("fourth".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            assert_eq!(fourth.id, 4);
+
+            let mut todos = repository.todos();
+            todos.sort_by_key(|todo| todo.id);
+            assert_eq!(
+                todos,
+                vec![
+                    TodoEntity::new(1, "first".to_string(), vec![]),
+                    TodoEntity::new(3, "third".to_string(), vec![]),
+                    TodoEntity::new(4, "fourth".to_string(), vec![]),
+                ]
+            );
+        }
     }
 }
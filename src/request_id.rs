@@ -0,0 +1,47 @@
+use axum::{
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+use uuid::Uuid;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Id of the current request, taken from `X-Request-Id` or generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub HeaderValue);
+
+/// Accepts the caller's `X-Request-Id` when it is short visible ASCII, otherwise generates a UUID.
+/// The id is stored as `RequestId` and echoed back in the response.
+pub async fn propagate_request_id<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let request_id = req
+        .headers()
+        .get(X_REQUEST_ID)
+        .filter(|value| !value.is_empty() && value.len() <= 200 && value.to_str().is_ok())
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&Uuid::new_v4().to_string()).expect("uuid is a valid header")
+        });
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let mut res = next.run(req).await;
+    res.headers_mut().insert(X_REQUEST_ID, request_id);
+    res
+}
+
+/// Span of the `TraceLayer`, carrying the `RequestId` so every log line of the request has it.
+pub fn make_span<B>(req: &Request<B>) -> Span {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.0.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        request_id = %request_id,
+    )
+}
@@ -0,0 +1,49 @@
+use axum::{
+    body::Body,
+    handler::Handler,
+    http::Method,
+    routing::{on, MethodFilter},
+    Router,
+};
+
+/// `Router` keeping the method and path of every route added, so they can be listed at startup.
+pub struct RouteTable<B = Body> {
+    router: Router<B>,
+    routes: Vec<(Method, &'static str)>,
+}
+
+impl<B: http_body::Body + Send + 'static> RouteTable<B> {
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            routes: vec![],
+        }
+    }
+
+    /// Adds `handler` for `method` on `path`, methods added for the same path are merged.
+    pub fn route<H, T>(mut self, method: Method, path: &'static str, handler: H) -> Self
+    where
+        H: Handler<T, B>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(method.clone())
+            .unwrap_or_else(|_| panic!("no method filter for [{}]", method));
+        self.router = self.router.route(path, on(filter, handler));
+        self.routes.push((method, path));
+        self
+    }
+
+    pub fn merge(mut self, other: RouteTable<B>) -> Self {
+        self.router = self.router.merge(other.router);
+        self.routes.extend(other.routes);
+        self
+    }
+
+    pub fn routes(&self) -> &[(Method, &'static str)] {
+        &self.routes
+    }
+
+    pub fn into_router(self) -> Router<B> {
+        self.router
+    }
+}
@@ -0,0 +1,104 @@
+use crate::repositories::{
+    label::{CreateLabel, LabelQuery, LabelRepository},
+    todo::{CreateTodo, TodoFilter, TodoRepository},
+};
+
+/// Example labels, created in this order.
+const LABELS: [&str; 3] = ["work", "home", "errand"];
+
+/// Example todos with the names of their labels.
+const TODOS: [(&str, &[&str]); 4] = [
+    ("Write the weekly report", &["work"]),
+    ("Clean the kitchen", &["home"]),
+    ("Buy milk on the way home", &["home", "errand"]),
+    ("Call the bank", &[]),
+];
+
+/// Inserts the example labels and todos into empty repositories.
+/// Each table is only seeded when it has no rows, so running it again is a no-op.
+/// The todos have no owner, so they are only visible while authentication is disabled.
+pub async fn seed<Todo: TodoRepository, Label: LabelRepository>(
+    todo_repository: &Todo,
+    label_repository: &Label,
+) -> anyhow::Result<()> {
+    if label_repository
+        .all(LabelQuery::default())
+        .await?
+        .is_empty()
+    {
+        for name in LABELS {
+            label_repository
+                .create(CreateLabel::new(name.to_string()))
+                .await?;
+        }
+        tracing::info!("seeded {} labels", LABELS.len());
+    }
+
+    if todo_repository.count(None, TodoFilter::default()).await? == 0 {
+        let labels = label_repository.all(LabelQuery::default()).await?;
+        for (text, label_names) in TODOS {
+            let label_ids = label_names
+                .iter()
+                .filter_map(|name| labels.iter().find(|label| label.name == *name))
+                .map(|label| label.id)
+                .collect();
+            let payload = CreateTodo::builder()
+                .text(text)
+                .label_ids(label_ids)
+                .build()?;
+            todo_repository.create(None, payload).await?;
+        }
+        tracing::info!("seeded {} todos", TODOS.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::{
+        label::{test_utils::LabelRepositoryForMemory, Label},
+        todo::test_utils::TodoRepositoryForMemory,
+    };
+
+    #[tokio::test]
+    async fn seed_is_idempotent() {
+        // the in-memory todo repository only accepts labels it is built with
+        let labels = LABELS
+            .iter()
+            .enumerate()
+            .map(|(index, name)| Label::new(index as i32 + 1, name.to_string()))
+            .collect();
+        let todo_repository = TodoRepositoryForMemory::new(labels);
+        let label_repository = LabelRepositoryForMemory::new();
+
+        for _ in 0..2 {
+            seed(&todo_repository, &label_repository)
+                .await
+                .expect("failed to seed");
+            assert_eq!(
+                label_repository
+                    .all(LabelQuery::default())
+                    .await
+                    .unwrap()
+                    .len(),
+                LABELS.len()
+            );
+            assert_eq!(
+                todo_repository
+                    .count(None, TodoFilter::default())
+                    .await
+                    .unwrap(),
+                TODOS.len() as i64
+            );
+        }
+        let todo = todo_repository.find(None, 3).await.unwrap();
+        let names: Vec<&str> = todo
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["home", "errand"]);
+    }
+}
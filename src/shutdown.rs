@@ -0,0 +1,122 @@
+use axum::{http::Request, middleware::Next, response::Response};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Number of requests currently being handled, see `InFlight::track`.
+#[derive(Debug, Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Middleware counting the request until its response is ready, or it is dropped.
+    pub async fn track<B>(self, req: Request<B>, next: Next<B>) -> Response {
+        let _guard = InFlightGuard::new(self.0);
+        next.run(req).await
+    }
+}
+
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Drain {
+    /// Every connection was closed in time.
+    Completed,
+    /// Requests still running when the timeout hit are dropped with the process.
+    TimedOut { pending: usize },
+}
+
+/// Awaits a server running `with_graceful_shutdown`, which stops accepting connections and
+/// finishes the requests in flight once `draining` resolved. Gives up `timeout` after that.
+pub async fn drain<S, E>(
+    server: S,
+    draining: impl Future<Output = ()>,
+    timeout: Duration,
+    in_flight: &InFlight,
+) -> Result<Drain, E>
+where
+    S: Future<Output = Result<(), E>>,
+{
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => return result.map(|()| Drain::Completed),
+        _ = draining => {}
+    }
+    match tokio::time::timeout(timeout, server).await {
+        Ok(result) => result.map(|()| Drain::Completed),
+        Err(_) => {
+            let pending = in_flight.count();
+            tracing::warn!(
+                "shutdown timed out after {:?}, dropping {} requests in flight",
+                timeout,
+                pending
+            );
+            Ok(Drain::TimedOut { pending })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn drain_gives_up_after_timeout() {
+        let in_flight = InFlight::default();
+        let _first = InFlightGuard::new(in_flight.0.clone());
+        let _second = InFlightGuard::new(in_flight.0.clone());
+
+        // a connection that never finishes keeps the server running
+        let server = std::future::pending::<Result<(), Infallible>>();
+        let drained = drain(
+            server,
+            std::future::ready(()),
+            Duration::from_millis(10),
+            &in_flight,
+        )
+        .await;
+        assert_eq!(drained, Ok(Drain::TimedOut { pending: 2 }));
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_requests_in_flight() {
+        let in_flight = InFlight::default();
+        let guard = InFlightGuard::new(in_flight.0.clone());
+        let server = async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(guard);
+            Ok::<_, Infallible>(())
+        };
+        let drained = drain(
+            server,
+            std::future::ready(()),
+            Duration::from_secs(60),
+            &in_flight,
+        )
+        .await;
+        assert_eq!(drained, Ok(Drain::Completed));
+        assert_eq!(in_flight.count(), 0);
+    }
+}